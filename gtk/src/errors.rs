@@ -1,4 +1,8 @@
-use pop_upgrade::client::Error as ClientError;
+use crate::fl;
+use pop_upgrade::{
+    client::{Error as ClientError, Status},
+    error_code::{ErrorCategory, ErrorCode, ErrorReport},
+};
 use std::error::Error as ErrorTrait;
 
 #[derive(Debug, Error)]
@@ -21,11 +25,31 @@ pub enum UiError {
 
 impl UiError {
     pub fn iter_sources(&self) -> ErrorIter<'_> { ErrorIter { current: self.source() } }
+
+    /// The error report of the daemon, if the error originated from a failed status.
+    pub fn report(&self) -> Option<&ErrorReport> {
+        let underlying = match self {
+            UiError::Dismiss(_, why)
+            | UiError::Recovery(why)
+            | UiError::Refresh(why)
+            | UiError::Updates(why)
+            | UiError::Upgrade(why) => why,
+            UiError::Cancel(_) | UiError::Finalize(_) => return None,
+        };
+
+        match underlying {
+            UnderlyingError::Status(status) => status.report.as_ref(),
+            UnderlyingError::Client(_) => None,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
-#[error("{}", _0)]
-pub struct StatusError(Box<str>);
+#[error("{}", why)]
+pub struct StatusError {
+    why:    Box<str>,
+    report: Option<ErrorReport>,
+}
 
 #[derive(Debug, Error)]
 pub enum UnderlyingError {
@@ -35,8 +59,49 @@ pub enum UnderlyingError {
     Status(#[from] StatusError),
 }
 
-impl From<Box<str>> for UnderlyingError {
-    fn from(why: Box<str>) -> Self { UnderlyingError::Status(StatusError(why)) }
+impl From<Status> for UnderlyingError {
+    fn from(status: Status) -> Self {
+        UnderlyingError::Status(StatusError { why: status.why, report: status.error })
+    }
+}
+
+/// A localized description of an error reported by the daemon, and how to resolve it.
+pub fn localize_report(report: &ErrorReport) -> Option<String> {
+    let (message, hint) = match report.code() {
+        Some(ErrorCode::NoConnection) => {
+            (fl!("error-code-no-connection"), fl!("error-hint-no-connection"))
+        }
+        Some(ErrorCode::NoSpace) => (fl!("error-code-no-space"), fl!("error-hint-no-space")),
+        Some(ErrorCode::ReadOnly) => (fl!("error-code-read-only"), fl!("error-hint-read-only")),
+        Some(ErrorCode::AptLocked) => (fl!("error-code-apt-locked"), fl!("error-hint-apt-locked")),
+        Some(ErrorCode::DpkgBroken) => {
+            (fl!("error-code-dpkg-broken"), fl!("error-hint-package-manager"))
+        }
+        Some(ErrorCode::Repositories) => {
+            (fl!("error-code-repositories"), fl!("error-hint-repositories"))
+        }
+        Some(ErrorCode::ChecksumMismatch) => {
+            (fl!("error-code-checksum"), fl!("error-hint-try-again"))
+        }
+        _ => match report.category()? {
+            ErrorCategory::Network => (fl!("error-code-download"), fl!("error-hint-no-connection")),
+            ErrorCategory::Storage => (fl!("error-code-storage"), fl!("error-hint-storage")),
+            ErrorCategory::PackageManager => {
+                (fl!("error-code-package-manager"), fl!("error-hint-package-manager"))
+            }
+            ErrorCategory::Bootloader => {
+                (fl!("error-code-bootloader"), fl!("error-hint-collect-logs"))
+            }
+            ErrorCategory::Recovery => (fl!("error-code-recovery"), fl!("error-hint-collect-logs")),
+            ErrorCategory::Release => (fl!("error-code-release"), fl!("error-hint-try-again")),
+            ErrorCategory::None
+            | ErrorCategory::System
+            | ErrorCategory::Permission
+            | ErrorCategory::Cancelled => return None,
+        },
+    };
+
+    Some(fomat!((message) "\n\n" (hint)))
 }
 
 pub struct ErrorIter<'a> {
@@ -8,7 +8,7 @@ use self::scan::scan;
 
 use num_traits::cast::FromPrimitive;
 use pop_upgrade::{
-    client::{Client, ReleaseInfo, Status},
+    client::{self, Client, ReleaseInfo, Signal, Status},
     daemon::{DaemonStatus, DismissEvent},
     release::RefreshOp,
 };
@@ -23,8 +23,11 @@ pub enum BackgroundEvent {
     GetStatus(DaemonStatus),
     IsActive(SyncSender<bool>),
     DismissNotification(bool),
+    DismissUpgradeReport,
+    RecoveryModeUnset,
     RefreshOS,
     Reset,
+    RestoreApplications,
     Scan,
     Shutdown,
     UpdateRecovery(Box<str>),
@@ -68,6 +71,12 @@ pub fn run(
                     send(event)
                 }
 
+                BackgroundEvent::DismissUpgradeReport => {
+                    if let Err(why) = client.upgrade_report_dismiss() {
+                        error!("failed to dismiss the upgrade report: {}", why);
+                    }
+                }
+
                 BackgroundEvent::DownloadUpgrade(info) => {
                     self::release::download(client, send, info);
                 }
@@ -85,6 +94,14 @@ pub fn run(
                     let _ = tx.send(client.status().is_ok());
                 }
 
+                BackgroundEvent::RecoveryModeUnset => {
+                    if let Err(why) = client.recovery_mode_unset() {
+                        error!("failed to cancel the pending recovery mode: {}", why);
+                    }
+
+                    scan(client, send);
+                }
+
                 BackgroundEvent::RefreshOS => {
                     refresh_os(client, send);
                 }
@@ -96,6 +113,8 @@ pub fn run(
                     });
                 }
 
+                BackgroundEvent::RestoreApplications => restore_applications(client),
+
                 BackgroundEvent::Scan => scan(client, send),
 
                 BackgroundEvent::Shutdown => {
@@ -117,7 +136,7 @@ fn status_recovery_upgrade(client: &Client) -> UiEvent {
             if status.status == 0 {
                 UiEvent::Completed(CompletedEvent::Recovery)
             } else {
-                UiEvent::Error(UiError::Recovery(status.why.into()))
+                UiEvent::Error(UiError::Recovery(status.into()))
             }
         }
         Err(why) => UiEvent::Error(UiError::Recovery(why.into())),
@@ -130,7 +149,7 @@ fn status_release_upgrade(client: &Client) -> UiEvent {
             if status.status == 0 {
                 UiEvent::Completed(CompletedEvent::Download)
             } else {
-                UiEvent::Error(UiError::Upgrade(status.why.into()))
+                UiEvent::Error(UiError::Upgrade(status.into()))
             }
         }
         Err(why) => UiEvent::Error(UiError::Upgrade(why.into())),
@@ -156,6 +175,42 @@ fn refresh_os(client: &Client, send: &dyn Fn(UiEvent)) {
     send(UiEvent::Completed(CompletedEvent::Refresh));
 }
 
+fn restore_applications(client: &Client) {
+    if let Err(why) = client.refresh_restore() {
+        error!("failed to reinstall the preserved applications: {}", why);
+        return;
+    }
+
+    let result = client.event_listen(
+        DaemonStatus::RestoringApplications,
+        Client::refresh_restore_status,
+        |status| {
+            if status.status != 0 {
+                error!("failed to reinstall the preserved applications: {}", status.why);
+            }
+        },
+        |_client, signal| {
+            if let Signal::RefreshRestoreResult(status, unavailable) = signal {
+                if status.status != 0 {
+                    error!("failed to reinstall the preserved applications: {}", status.why);
+                } else if unavailable.is_empty() {
+                    info!("reinstalled the applications preserved for the refresh");
+                } else {
+                    warn!("applications were not available to reinstall: {:?}", unavailable);
+                }
+
+                return Ok(client::Continue(false));
+            }
+
+            Ok(client::Continue(true))
+        },
+    );
+
+    if let Err(why) = result {
+        error!("failed to listen for the reinstall of the preserved applications: {}", why);
+    }
+}
+
 fn status_changed(send: &dyn Fn(UiEvent), new_status: Status, expected: DaemonStatus) {
     let status = DaemonStatus::from_u8(new_status.status).expect("unknown daemon status value");
     send(UiEvent::StatusChanged(expected, status, new_status.why));
@@ -39,12 +39,18 @@ pub fn upgrade_listen(client: &Client, send: &dyn Fn(UiEvent)) -> bool {
                     println!("Progress {}/{}", progress, total);
                     send(UiEvent::Progress(ProgressEvent::Recovery(progress, total)));
                 }
+                Signal::RecoverySyncProgress(progress) => {
+                    send(UiEvent::Progress(ProgressEvent::RecoverySync(
+                        progress.current,
+                        progress.total,
+                    )));
+                }
                 Signal::RecoveryEvent(event) => {
                     send(UiEvent::Recovery(OsRecoveryEvent::Event(event)));
                 }
                 Signal::RecoveryResult(status) => {
                     if status.status != 0 {
-                        *error = Some(status.why);
+                        *error = Some(status);
                     }
 
                     return Ok(client::Continue(false));
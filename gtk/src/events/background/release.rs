@@ -15,19 +15,14 @@ pub fn download(client: &Client, send: &dyn Fn(UiEvent), info: ReleaseInfo) {
     }
 
     let &ReleaseInfo { ref current, ref next, .. } = &info;
-    // TODO: Re-enable this when QA is ready for testing this behavior.
-    //    let how = if client.recovery_exists() {
-    //        // Upgrade the recovery partition in addition to the OS.
-    //        if !upgrade_recovery(client, send, next) {
-    //            return;
-    //        }
-    //
-    //        UpgradeMethod::Recovery
-    //    } else {
-    //        UpgradeMethod::Offline
-    //    };
-
-    let how = UpgradeMethod::Offline;
+    // The daemon upgrades the recovery partition to the new release as part of this method.
+    let how = if client.recovery_exists()
+        && client.daemon.supports_upgrade_method(UpgradeMethod::Recovery)
+    {
+        UpgradeMethod::Recovery
+    } else {
+        UpgradeMethod::Offline
+    };
 
     send(UiEvent::Initiated(InitiatedEvent::Download(next.clone())));
 
@@ -51,7 +46,7 @@ pub fn download(client: &Client, send: &dyn Fn(UiEvent), info: ReleaseInfo) {
             match signal {
                 Signal::PackageFetchResult(status) | Signal::RecoveryResult(status) => {
                     if status.status != 0 {
-                        *error = Some(status.why);
+                        *error = Some(status);
                         return Ok(client::Continue(false));
                     }
                 }
@@ -77,7 +72,7 @@ pub fn download(client: &Client, send: &dyn Fn(UiEvent), info: ReleaseInfo) {
                 }
                 Signal::ReleaseResult(status) => {
                     if status.status != 0 {
-                        *error = Some(status.why);
+                        *error = Some(status);
                     }
 
                     return Ok(client::Continue(false));
@@ -88,6 +83,12 @@ pub fn download(client: &Client, send: &dyn Fn(UiEvent), info: ReleaseInfo) {
                         progress.total,
                     )));
                 }
+                Signal::RecoverySyncProgress(progress) => {
+                    send(UiEvent::Progress(ProgressEvent::RecoverySync(
+                        progress.current,
+                        progress.total,
+                    )));
+                }
                 _ => (),
             }
 
@@ -135,7 +136,7 @@ pub fn update(client: &Client, send: &dyn Fn(UiEvent)) -> bool {
                 match signal {
                     Signal::PackageFetchResult(status) => {
                         if status.status != 0 {
-                            *error = Some(status.why);
+                            *error = Some(status);
                         }
 
                         return Ok(client::Continue(false));
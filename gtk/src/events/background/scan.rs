@@ -4,9 +4,10 @@ use crate::{
 };
 
 use pop_upgrade::{
-    client::{Client, Error as ClientError, ReleaseInfo},
+    client::{Client, Error as ClientError, PreservedApplications, ReleaseInfo},
     daemon::DaemonStatus,
-    release::{self, STARTUP_UPGRADE_FILE},
+    recovery::RecoveryMode,
+    release::{self, verify::UpgradeReport, STARTUP_UPGRADE_FILE},
 };
 
 use std::path::Path;
@@ -25,7 +26,10 @@ pub enum ScanEvent {
         current:      Option<Box<str>>,
         upgrade_text: Box<str>,
 
-        upgrade: Option<ReleaseInfo>,
+        upgrade:       Option<ReleaseInfo>,
+        report:        Option<UpgradeReport>,
+        preserved:     Option<PreservedApplications>,
+        recovery_mode: Option<RecoveryMode>,
     },
     PermissionDenied,
 }
@@ -57,8 +61,7 @@ pub fn scan(client: &Client, send: &dyn Fn(UiEvent)) {
     let upgrade_text: String = if !reboot_ready && release::upgrade_in_progress() {
         fl!("upgrade-downloading")
     } else {
-        let devel = pop_upgrade::development_releases_enabled();
-        let result = client.release_check(devel);
+        let result = client.release_check(false);
         match result {
             Ok(info) => {
                 current = dbg!(Some(info.current.clone()));
@@ -113,12 +116,30 @@ pub fn scan(client: &Client, send: &dyn Fn(UiEvent)) {
         }
     };
 
+    let report = client.upgrade_report().unwrap_or_else(|why| {
+        error!("failed to get the upgrade report: {}", why);
+        None
+    });
+
+    let preserved = client.refresh_preserved().unwrap_or_else(|why| {
+        error!("failed to get the applications preserved for the refresh: {}", why);
+        None
+    });
+
+    let recovery_mode = client.recovery_mode().unwrap_or_else(|why| {
+        error!("failed to get the pending recovery mode: {}", why);
+        None
+    });
+
     send(UiEvent::Completed(CompletedEvent::Scan(ScanEvent::Found {
         current,
         is_current,
         is_lts,
+        preserved,
         reboot_ready,
+        recovery_mode,
         refresh: client.recovery_exists(),
+        report,
         status_failed,
         upgrade_text: Box::from(upgrade_text),
         upgrade,
@@ -3,11 +3,11 @@ pub mod background;
 pub use self::background::{scan::ScanEvent, BackgroundEvent};
 
 use crate::{
-    errors::UiError,
+    errors::{localize_report, UiError},
     fl, get_dismiss_row, get_upgrade_row, notify, reboot,
     state::State,
     widgets::{
-        dialogs::{RefreshDialog, UpgradeDialog},
+        dialogs::{RefreshDialog, ReportDialog, RestoreDialog, UpgradeDialog},
         permissions::PermissionDenied,
         Dismisser, Section,
     },
@@ -19,7 +19,7 @@ use gtk::prelude::*;
 
 use pop_upgrade::{
     daemon::{DaemonStatus, DISMISSED},
-    recovery::RecoveryEvent,
+    recovery::{RecoveryEvent, RecoveryMode},
     release::{
         eol::{EolDate, EolStatus},
         UpgradeEvent,
@@ -89,6 +89,7 @@ pub enum CompletedEvent {
 pub enum ProgressEvent {
     Fetching(u64, u64),
     Recovery(u64, u64),
+    RecoverySync(u64, u64),
     Updates(u8),
 }
 
@@ -145,6 +146,16 @@ pub fn attach(gui_receiver: flume::Receiver<UiEvent>, widgets: EventWidgets, mut
                             .show_progress();
                     }
 
+                    ProgressEvent::RecoverySync(progress, total) => {
+                        widgets.recovery.options[RECOVERY_PARTITION]
+                            .label(&fl!(
+                                "recovery-sync-progress",
+                                current = (progress / 1024),
+                                total = (total / 1024)
+                            ))
+                            .sublabel(None);
+                    }
+
                     ProgressEvent::Updates(percent) => {
                         widgets.upgrade.options[0].progress_exact(percent / 4 + 25).show_progress();
                     }
@@ -298,14 +309,31 @@ fn cancelled_upgrade(state: &mut State, widgets: &EventWidgets) {
 }
 
 /// Programs the refresh button
-fn connect_refresh(state: &State, widgets: &EventWidgets) {
+fn connect_refresh(state: &State, widgets: &EventWidgets, mode: Option<RecoveryMode>) {
+    let option = &widgets.recovery.options[REFRESH_OS];
+
+    // A pending recovery mode may be cancelled before the system is rebooted.
+    if let Some(mode) = mode {
+        let action = enclose!((state.sender => sender) move || {
+            let _ = sender.send(BackgroundEvent::RecoveryModeUnset);
+        });
+
+        option
+            .sublabel(Some(&fl!("recovery-mode-pending", mode = (mode.as_str()))))
+            .button_signal(Some((fl!("button-cancel"), action)))
+            .show();
+
+        return;
+    }
+
     let action = enclose!((state.gui_sender => sender) move || {
         if let Some(sender) = sender.upgrade() {
             let _ = sender.send(UiEvent::Recovery(OsRecoveryEvent::Refresh));
         }
     });
 
-    widgets.recovery.options[REFRESH_OS]
+    option
+        .sublabel(Some(&fl!("refresh-description")))
         .button_signal(Some((fl!("button-refresh"), action)))
         .show();
 }
@@ -438,9 +466,12 @@ fn error(state: &mut State, widgets: &EventWidgets, why: UiError) {
             format!("{}:\n\n{:#?}", fl!("error-recovery-update"), why).as_str(),
         );
     } else {
-        (state.callback_error.borrow())(
-            &fomat!((&*GENERIC) "\n\n" (fl!("error-originating-cause")) "\n\n" (error_message)),
-        );
+        let hint = why.report().and_then(localize_report);
+        (state.callback_error.borrow())(&fomat!(
+            (&*GENERIC)
+            if let Some(hint) = hint { "\n\n" (hint) }
+            "\n\n" (fl!("error-originating-cause")) "\n\n" (error_message)
+        ));
     }
 
     error!("{}", error_message);
@@ -538,8 +569,11 @@ fn scan_event(state: &mut State, widgets: &EventWidgets, event: ScanEvent) {
             mut current,
             is_current,
             is_lts,
+            preserved,
             reboot_ready,
+            recovery_mode,
             refresh,
+            report,
             status_failed,
             upgrade_text,
             upgrade,
@@ -565,13 +599,27 @@ fn scan_event(state: &mut State, widgets: &EventWidgets, event: ScanEvent) {
 
             if refresh {
                 widgets.recovery.show();
-                connect_refresh(&state, widgets);
+                connect_refresh(&state, widgets, recovery_mode);
                 recovery::update_status(&state, widgets, status_failed, upgrading_recovery);
             } else {
                 widgets.recovery.hide();
             }
 
             widgets.container.show();
+
+            // Offer to reinstall the applications which were removed by a refresh of the OS.
+            if let Some(preserved) = preserved {
+                if gtk::ResponseType::Accept == RestoreDialog::new(&preserved).run() {
+                    let _ = state.sender.send(BackgroundEvent::RestoreApplications);
+                }
+            }
+
+            // Report the outcome of the last release upgrade, until it is dismissed.
+            if let Some(report) = report {
+                if gtk::ResponseType::Accept == ReportDialog::new(&report).run() {
+                    let _ = state.sender.send(BackgroundEvent::DismissUpgradeReport);
+                }
+            }
         }
     }
 }
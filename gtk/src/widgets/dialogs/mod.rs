@@ -1,7 +1,11 @@
 mod refresh;
+mod report;
+mod restore;
 mod upgrade;
 
-pub use self::{refresh::RefreshDialog, upgrade::UpgradeDialog};
+pub use self::{
+    refresh::RefreshDialog, report::ReportDialog, restore::RestoreDialog, upgrade::UpgradeDialog,
+};
 
 use crate::fl;
 use gtk::prelude::*;
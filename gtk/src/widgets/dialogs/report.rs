@@ -0,0 +1,46 @@
+use gtk::prelude::*;
+
+use super::DialogTemplate;
+use crate::fl;
+use pop_upgrade::release::verify::UpgradeReport;
+
+#[derive(AsRef, Deref)]
+#[as_ref]
+#[deref]
+pub struct ReportDialog(DialogTemplate);
+
+impl ReportDialog {
+    pub fn new(report: &UpgradeReport) -> Self {
+        let (icon, title) = if report.is_success() {
+            ("emblem-ok-symbolic", fl!("dialog-report-success", version = (&*report.to)))
+        } else {
+            ("dialog-warning", fl!("dialog-report-failure", version = (&*report.to)))
+        };
+
+        Self(cascade! {
+            DialogTemplate::new(
+                icon,
+                &title,
+                &fl!("button-dismiss"),
+                &gtk::STYLE_CLASS_SUGGESTED_ACTION,
+                |content| {
+                    content.add(&label(&fl!(
+                        "dialog-report-description",
+                        from = (&*report.from),
+                        to = (&*report.to)
+                    )));
+
+                    for issue in report.issues() {
+                        content.add(&label(&["• ", &issue].concat()));
+                    }
+                },
+            );
+            ..set_size_request(480, 200);
+            ..set_valign(gtk::Align::Start);
+        })
+    }
+}
+
+fn label(text: &str) -> gtk::Label {
+    gtk::LabelBuilder::new().label(text).wrap(true).xalign(0.0).build()
+}
@@ -0,0 +1,36 @@
+use gtk::prelude::*;
+
+use super::DialogTemplate;
+use crate::fl;
+use pop_upgrade::client::PreservedApplications;
+
+#[derive(AsRef, Deref)]
+#[as_ref]
+#[deref]
+pub struct RestoreDialog(DialogTemplate);
+
+impl RestoreDialog {
+    pub fn new(preserved: &PreservedApplications) -> Self {
+        let applications = preserved.packages.len() + preserved.flatpaks.len();
+
+        Self(cascade! {
+            DialogTemplate::new(
+                "system-software-install",
+                &fl!("dialog-restore-title"),
+                &fl!("button-reinstall"),
+                &gtk::STYLE_CLASS_SUGGESTED_ACTION,
+                |content| {
+                    content.add(
+                        &gtk::LabelBuilder::new()
+                            .label(&fl!("dialog-restore-description", applications = applications))
+                            .wrap(true)
+                            .xalign(0.0)
+                            .build(),
+                    );
+                },
+            );
+            ..set_size_request(480, 200);
+            ..set_valign(gtk::Align::Start);
+        })
+    }
+}
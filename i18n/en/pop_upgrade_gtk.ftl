@@ -8,6 +8,7 @@ button-download = Download
 button-perform-refresh = Reboot & Refresh
 button-perform-upgrade = Reboot & Upgrade
 button-refresh = Refresh
+button-reinstall = Reinstall
 button-update = Update
 button-upgrade = Upgrade
 
@@ -19,7 +20,7 @@ dialog-refresh-description =
  
  {"*"} All user accounts and files in the /home directory will be kept
  {"*"} Users and user groups will be retained
- {"*"} All system applications installed by the user will be removed
+ {"*"} All system applications installed by the user will be removed, and offered to be reinstalled after the refresh
  {"*"} All files in the OS partition outside of the /home directory will be lost
  {"*"} All system-wide configuration changes will be lost, with the exception of:
      - The system timezone
@@ -29,6 +30,13 @@ dialog-refresh-description =
  
  Please be sure to save all of your work before clicking to reboot.
 
+dialog-report-description = The upgrade from {-os} {$from} to {-os} {$to} has been applied.
+dialog-report-failure = Issues were found after upgrading to {-os} {$version}
+dialog-report-success = {-os} {$version} was installed successfully
+
+dialog-restore-title = Reinstall Applications
+dialog-restore-description = {$applications} applications installed before refreshing {-os} were removed by the refresh. Reinstall them now?
+
 daemon-checking = Checking for updates to daemon
 daemon-updating = Updating the upgrade daemon
 
@@ -40,9 +48,31 @@ eol-imminent = Support for {-os} {$current} ends {$date}. Upgrade for security a
 eol-error = failed to fetch EOL date
 
 error-build-status = Failed to retrieve build status due to an internal error
+error-code-apt-locked = Another application is using the package manager
+error-code-bootloader = The boot loader could not be configured
+error-code-checksum = The downloaded files were corrupted
+error-code-download = Files required for the upgrade could not be downloaded
+error-code-dpkg-broken = The package manager is in a broken state
+error-code-no-connection = Unable to connect to the internet
+error-code-no-space = There is not enough free disk space
+error-code-package-manager = The package manager encountered an error
+error-code-read-only = A file system is mounted read-only
+error-code-recovery = The recovery partition could not be updated
+error-code-release = Unable to retrieve information about the next release
+error-code-repositories = The software repositories could not be updated
+error-code-storage = A file could not be read or written
 error-collect-logs = If you are a System76 customer, please run the System76 Driver tool to collect logs and contact support with the logs.
 error-connection = Connection failed. You may be offline
 error-header = Looks like we've encountered an issue! No worries, these are a list of files which may have been changed:
+error-hint-apt-locked = Wait for other software updates to finish, then try again.
+error-hint-collect-logs = {error-collect-logs}
+error-hint-no-connection = Check your network connection, then try again.
+error-hint-no-space = Free up disk space by removing unused files or applications, then try again.
+error-hint-package-manager = Run `sudo apt install -f` and `sudo dpkg --configure -a` in a terminal, then try again.
+error-hint-read-only = Restart the computer to remount the file system, then try again.
+error-hint-repositories = Disable third-party software repositories in the Software Sources settings, then try again.
+error-hint-storage = Check that the disk is healthy and has free space, then try again.
+error-hint-try-again = {error-try-again}
 error-no-changelog-found = No changelog found
 error-originating-cause = Originating error cause
 error-package-manager = If you are seeing package manager issues, please run the following commands and send them to support in your support ticket:
@@ -73,8 +103,15 @@ permission-denied = Permission Required
 
 recovery-downloading = Downloading the recovery partition update
 recovery-header = Recovery Partition
+recovery-mode-pending = { $mode ->
+    [refresh] The OS will be refreshed on the next boot
+    [upgrade] The new release will be installed on the next boot
+    [repair] The recovery partition will boot into a repair shell on the next boot
+   *[install] The recovery partition will boot into the installer on the next boot
+}
 recovery-progress = {recovery-downloading}: ({$current} of {$total} MiB)
 recovery-sync = Syncing recovery image to disk
+recovery-sync-progress = {recovery-sync}: ({$current} of {$total} MiB)
 recovery-update-found = Recovery partition update is available
 recovery-verify = Verifying the fetched recovery image
 
@@ -1,8 +1,12 @@
 use async_fs::File;
 use futures::prelude::*;
-use hex::FromHex;
-use sha2::{digest::generic_array::GenericArray, Digest, Sha256};
-use std::io;
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -17,29 +21,217 @@ pub enum ValidateError {
     Io(#[from] io::Error),
 }
 
-pub async fn validate_checksum(file: &mut File, checksum: &str) -> Result<(), ValidateError> {
-    info!("validating checksum of downloaded ISO");
-    let expected = <[u8; 32]>::from_hex(checksum)
-        .map(GenericArray::from)
-        .map_err(|_| ValidateError::InvalidInput)?;
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    Md5,
+    Sha256,
+    Sha512,
+}
 
-    let mut hasher = Sha256::new();
-    let mut buffer = vec![0u8; 8 * 1024];
+impl Algorithm {
+    /// The algorithm which produces digests of this many bytes.
+    fn from_len(len: usize) -> Option<Self> {
+        match len {
+            16 => Some(Algorithm::Md5),
+            32 => Some(Algorithm::Sha256),
+            64 => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// The algorithm named by apt's `Hash:` fields and `--print-uris` output.
+    fn from_apt_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "MD5" | "MD5SUM" => Some(Algorithm::Md5),
+            "SHA256" => Some(Algorithm::Sha256),
+            "SHA512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+/// An expected digest, and the algorithm that it was computed with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    pub digest:    Vec<u8>,
+}
+
+impl Checksum {
+    /// Parses a hex digest, whose algorithm is inferred from its length, or apt's
+    /// `<algorithm>:<hex digest>` form.
+    pub fn parse(input: &str) -> Result<Self, ValidateError> {
+        let input = input.trim();
+
+        let (algorithm, hex) = match input.find(':') {
+            Some(pos) => {
+                let algorithm =
+                    Algorithm::from_apt_name(&input[..pos]).ok_or(ValidateError::InvalidInput)?;
+                (Some(algorithm), &input[pos + 1..])
+            }
+            None => (None, input),
+        };
+
+        let digest = hex::decode(hex).map_err(|_| ValidateError::InvalidInput)?;
+
+        match Algorithm::from_len(digest.len()) {
+            Some(found) if algorithm.map_or(true, |algorithm| algorithm == found) => {
+                Ok(Checksum { algorithm: found, digest })
+            }
+            _ => Err(ValidateError::InvalidInput),
+        }
+    }
+
+    /// Compares a digest computed with this checksum's algorithm.
+    pub fn check(&self, found: &[u8]) -> Result<(), ValidateError> {
+        if found != self.digest.as_slice() {
+            return Err(ValidateError::Checksum {
+                expected: hex::encode(&self.digest),
+                found:    hex::encode(found),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+pub enum Hasher {
+    Md5(Md5),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    pub fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Md5 => Hasher::Md5(Md5::new()),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Md5(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+/// Hashes everything which is written through it, so that a download may be validated without
+/// reading it back from the disk.
+pub struct HashingWriter<W> {
+    inner:  W,
+    hasher: Hasher,
+}
+
+impl<W> HashingWriter<W> {
+    pub fn new(inner: W, algorithm: Algorithm) -> Self {
+        HashingWriter { inner, hasher: Hasher::new(algorithm) }
+    }
+
+    /// The digest of everything that was written.
+    pub fn finalize(self) -> Vec<u8> { self.hasher.finalize() }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for HashingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(written)) = result {
+            this.hasher.update(&buf[..written]);
+        }
+
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Computes the digest of everything that is read from the reader.
+pub async fn digest<R: AsyncRead + Unpin>(
+    mut reader: R,
+    algorithm: Algorithm,
+) -> io::Result<Vec<u8>> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0u8; 64 * 1024];
 
     loop {
-        match file.read(&mut buffer).await? {
+        match reader.read(&mut buffer).await? {
             0 => break,
             read => hasher.update(&buffer[..read]),
         }
     }
 
-    let found = hasher.finalize();
-    if &*found != &*expected {
-        return Err(ValidateError::Checksum {
-            expected: checksum.into(),
-            found:    format!("{:x}", found),
-        });
+    Ok(hasher.finalize())
+}
+
+pub async fn validate_checksum(file: &mut File, checksum: &str) -> Result<(), ValidateError> {
+    info!("validating checksum of downloaded ISO");
+    let checksum = Checksum::parse(checksum)?;
+    checksum.check(&digest(file, checksum.algorithm).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MD5: &str = "0cc175b9c0f1b6a831c399e269772661";
+    const SHA256: &str = "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb";
+    const SHA512: &str = "1f40fc92da241694750979ee6cf582f2d5d7d28e18335de05abc54d0560e0f53\
+                          02860c652bf08d560252aa5e74210546f369fbbbce8c12cfc7957b2652fe9a75";
+
+    #[test]
+    fn parse() {
+        assert_eq!(Checksum::parse(MD5).unwrap().algorithm, Algorithm::Md5);
+        assert_eq!(Checksum::parse(SHA256).unwrap().algorithm, Algorithm::Sha256);
+        assert_eq!(Checksum::parse(SHA512).unwrap().algorithm, Algorithm::Sha512);
+
+        let apt = Checksum::parse(&["SHA256:", SHA256].concat()).unwrap();
+        assert_eq!(apt.algorithm, Algorithm::Sha256);
+        assert_eq!(Checksum::parse(&["MD5Sum:", MD5].concat()).unwrap().algorithm, Algorithm::Md5);
+
+        assert!(Checksum::parse(&["SHA512:", SHA256].concat()).is_err());
+        assert!(Checksum::parse("0a1b").is_err());
+        assert!(Checksum::parse("not hex").is_err());
     }
 
-    Ok(())
+    #[test]
+    fn hashing_writer() {
+        for (algorithm, expected) in
+            &[(Algorithm::Md5, MD5), (Algorithm::Sha256, SHA256), (Algorithm::Sha512, SHA512)]
+        {
+            let mut written = Vec::new();
+            let mut writer = HashingWriter::new(&mut written, *algorithm);
+            async_io::block_on(writer.write_all(b"a")).unwrap();
+
+            let checksum = Checksum::parse(expected).unwrap();
+            assert!(checksum.check(&writer.finalize()).is_ok());
+            assert_eq!(written, b"a");
+
+            let found = async_io::block_on(digest(&b"a"[..], *algorithm)).unwrap();
+            assert!(checksum.check(&found).is_ok());
+            assert!(checksum.check(&[0; 16]).is_err());
+        }
+    }
 }
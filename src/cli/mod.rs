@@ -11,13 +11,19 @@ use num_traits::FromPrimitive;
 use pop_upgrade::{
     client,
     daemon::*,
+    error_code::{ErrorCategory, ErrorCode, ErrorReport},
     misc,
-    recovery::{RecoveryEvent, ReleaseFlags as RecoveryReleaseFlags},
+    recovery::{
+        RecoveryEvent, RecoveryIntegrity, RecoveryMode, ReleaseFlags as RecoveryReleaseFlags,
+    },
     release::{
+        bootloader,
         eol::{EolDate, EolStatus},
-        systemd::{self, LoaderEntry},
+        manifest::PackageChange,
+        systemd::LoaderEntry,
         RefreshOp, UpgradeEvent, UpgradeMethod,
     },
+    release_api::Channel,
 };
 use std::{
     convert::TryFrom,
@@ -32,12 +38,25 @@ const FETCH_RESULT_STR: &str = "Package fetch status";
 const FETCH_RESULT_SUCCESS: &str = "cargo has been loaded successfully";
 const FETCH_RESULT_ERROR: &str = "package-fetching aborted";
 
+const REINSTALL_RESULT_STR: &str = "Package reinstall status";
+const REINSTALL_RESULT_SUCCESS: &str = "removed packages reinstalled";
+const REINSTALL_RESULT_ERROR: &str = "package reinstall aborted";
+
 const RECOVERY_RESULT_STR: &str = "Recovery upgrade status";
 const RECOVERY_RESULT_SUCCESS: &str = "recovery partition refueled and ready to go";
 const RECOVERY_RESULT_ERROR: &str = "recovery upgrade aborted";
 
+const RESTORE_RESULT_STR: &str = "Application restore status";
+const RESTORE_RESULT_SUCCESS: &str = "preserved applications reinstalled";
+const RESTORE_RESULT_ERROR: &str = "application restore aborted";
+
+const VERIFY_RESULT_STR: &str = "Recovery verify status";
+const VERIFY_RESULT_SUCCESS: &str = "recovery partition verified";
+const VERIFY_RESULT_ERROR: &str = "recovery verify aborted";
+
 const UPGRADE_RESULT_STR: &str = "Release upgrade status";
 const UPGRADE_RESULT_SUCCESS: &str = "systems are go for launch: reboot now";
+const UPGRADE_LIVE_RESULT_SUCCESS: &str = "new release installed: reboot to load the new kernel";
 const UPGRADE_RESULT_ERROR: &str = "release upgrade aborted";
 
 #[derive(Shrinkwrap)]
@@ -51,7 +70,7 @@ impl Client {
         match matches.subcommand() {
             ("default-boot", _) => {
                 root_required()?;
-                systemd::BootConf::load()?.set_default_boot_variant(LoaderEntry::Recovery)?;
+                bootloader::load()?.boot_once(LoaderEntry::Recovery)?;
             }
             ("upgrade", Some(matches)) => {
                 match matches.subcommand() {
@@ -83,6 +102,57 @@ impl Client {
                     "build: " (version.build)
                 );
             }
+            ("verify", _) => {
+                self.recovery_verify()?;
+
+                let integrity = match self.event_listen_recovery_verify()? {
+                    Some(integrity) => integrity,
+                    None => return Ok(()),
+                };
+
+                for path in &integrity.missing {
+                    pintln!((color_primary("Missing")) ": " (color_secondary(path)));
+                }
+
+                for path in &integrity.corrupt {
+                    pintln!((color_primary("Corrupt")) ": " (color_secondary(path)));
+                }
+
+                if integrity.unverifiable {
+                    return Err(anyhow!(
+                        "the recovery partition is unverifiable, because it has no checksums: \
+                         upgrade it to verify it"
+                    ));
+                }
+
+                if !integrity.is_intact() {
+                    return Err(anyhow!(
+                        "{} of {} files on the recovery partition are missing or corrupt",
+                        integrity.missing.len() + integrity.corrupt.len(),
+                        integrity.checked
+                    ));
+                }
+
+                println!("all {} files on the recovery partition are intact", integrity.checked);
+            }
+            ("mode", Some(matches)) => match matches.subcommand() {
+                ("set", Some(matches)) => {
+                    let mode = matches
+                        .value_of("MODE")
+                        .expect("missing required MODE argument")
+                        .parse::<RecoveryMode>()?;
+
+                    self.recovery_mode_set(mode)?;
+                    pintln!(
+                        "reboot to boot into the recovery partition in " (mode.as_str()) " mode"
+                    );
+                }
+                ("unset", _) => self.recovery_mode_unset()?,
+                _ => match self.recovery_mode()? {
+                    Some(mode) => pintln!("mode: " (mode.as_str())),
+                    None => println!("mode: none"),
+                },
+            },
             _ => unreachable!(),
         }
 
@@ -92,41 +162,57 @@ impl Client {
     pub fn release(&self, matches: &ArgMatches) -> anyhow::Result<()> {
         match matches.subcommand() {
             ("dismiss", _) => {
-                let devel = pop_upgrade::development_releases_enabled();
-                let (_, _, _, is_lts) = self.release_check(devel)?;
+                let (_, _, _, is_lts) = self.release_check(false)?;
                 if is_lts {
                     self.dismiss_notification(DismissEvent::ByUser)?;
                 } else {
                     println!("Only LTS releases may dismiss notifications");
                 }
             }
-            ("check", _) => {
-                let mut buffer = String::new();
-                let (current, next, available, is_lts) = self.release_check(false)?;
-
-                if atty::is(atty::Stream::Stdout) {
-                    println!(
-                        "      Current Release: {}\n         Next Release: {}\nNew Release \
-                         Available: {}",
-                        current,
-                        next,
-                        misc::format_build_number(available, &mut buffer)
-                    );
-                } else if available >= 0 {
-                    if is_lts && (self.dismissed(&next) || self.dismiss_by_timestamp(&next)?) {
-                        return Ok(());
-                    }
+            ("channel", Some(matches)) => match matches.subcommand() {
+                ("set", Some(matches)) => {
+                    let channel = matches
+                        .value_of("CHANNEL")
+                        .expect("missing required CHANNEL argument")
+                        .parse::<Channel>()?;
 
-                    let (summary, body) = notification_message(&current, &next);
+                    self.set_channel(channel)?;
+                    pintln!("release upgrades will be taken from the " (channel) " channel");
+                }
+                _ => match self.channel()? {
+                    Some(channel) => pintln!("channel: " (channel)),
+                    None => println!("channel: unknown"),
+                },
+            },
+            ("check", _) => {
+                // Offer to reinstall the applications preserved before a refresh of the OS, while
+                // the release is checked.
+                let reinstall = if !atty::is(atty::Stream::Stdout)
+                    && matches!(self.refresh_preserved(), Ok(Some(_)))
+                {
+                    Some(std::thread::spawn(|| {
+                        notify(
+                            "Reinstall your applications",
+                            "Applications installed before the refresh of Pop!_OS can be \
+                             reinstalled.",
+                            || {
+                                let _ = exec::Command::new("gnome-control-center")
+                                    .arg("upgrade")
+                                    .exec();
+                            },
+                        );
+                    }))
+                } else {
+                    None
+                };
 
-                    let upgrade_panel =
-                        if &*current == "18.04" { "info-overview" } else { "upgrade" };
+                let result = self.release_check_notify();
 
-                    notify(&summary, &body, || {
-                        let _ =
-                            exec::Command::new("gnome-control-center").arg(upgrade_panel).exec();
-                    });
+                if let Some(reinstall) = reinstall {
+                    let _ = reinstall.join();
                 }
+
+                result?;
             }
             // Update the current system, without performing a release upgrade
             ("update", Some(matches)) => {
@@ -142,11 +228,18 @@ impl Client {
                     self.event_listen_fetch_updates()?;
                 }
             }
-            // Perform an upgrade to the next release. Supports either systemd or recovery upgrades.
+            // Perform an upgrade to the next release. Supports offline, live, or recovery upgrades.
             ("upgrade", Some(matches)) => {
-                let (method, matches) = (UpgradeMethod::Offline, matches);
-                let forcing =
-                    matches.is_present("force-next") || pop_upgrade::development_releases_enabled();
+                let (method, success) = if matches.is_present("live") {
+                    (UpgradeMethod::Live, UPGRADE_LIVE_RESULT_SUCCESS)
+                } else if matches.is_present("recovery") {
+                    (UpgradeMethod::Recovery, UPGRADE_RESULT_SUCCESS)
+                } else {
+                    (UpgradeMethod::Offline, UPGRADE_RESULT_SUCCESS)
+                };
+
+                let forcing = matches.is_present("force-next")
+                    || self.channel().ok().flatten().map_or(false, Channel::allows_development);
                 let (current, next, available, _is_lts) = self.release_check(forcing)?;
 
                 if atty::is(atty::Stream::Stdout) {
@@ -162,7 +255,7 @@ impl Client {
                 if forcing || available >= 0 {
                     // Ask to perform the release upgrade, and then listen for its signals.
                     self.release_upgrade(method, current.as_ref(), next.as_ref())?;
-                    let mut recall = self.event_listen_release_upgrade()?;
+                    let mut recall = self.event_listen_release_upgrade(success)?;
 
                     // Repeat as necessary.
                     while recall {
@@ -172,7 +265,7 @@ impl Client {
                             color_secondary("attempting to perform upgrade again")
                         );
                         self.release_upgrade(method, current.as_ref(), next.as_ref())?;
-                        recall = self.event_listen_release_upgrade()?;
+                        recall = self.event_listen_release_upgrade(success)?;
                     }
 
                     // Finalize the release upgrade.
@@ -183,18 +276,81 @@ impl Client {
             }
             // Set the recovery partition as the next boot target, and configure it to
             // automatically switch to the refresh view.
-            ("refresh", Some(matches)) => {
-                let action = match matches.subcommand() {
-                    ("disable", _) => RefreshOp::Disable,
-                    _ => RefreshOp::Enable,
-                };
+            ("refresh", Some(matches)) => match matches.subcommand() {
+                // Reinstall the applications which were preserved before the refresh.
+                ("restore", _) => match self.refresh_preserved()? {
+                    Some(preserved) => {
+                        println!(
+                            "reinstalling {} packages and {} flatpaks from {} sources",
+                            preserved.packages.len(),
+                            preserved.flatpaks.len(),
+                            preserved.sources.len()
+                        );
 
-                self.refresh_os(action)?;
-                println!("reboot to boot into the recovery partition to begin the refresh install");
-            }
+                        self.refresh_restore()?;
+                        self.event_listen_refresh_restore()?;
+                    }
+                    None => println!("no applications were preserved for a refresh"),
+                },
+                ("discard", _) => self.refresh_discard()?,
+                ("disable", _) => {
+                    self.refresh_os(RefreshOp::Disable)?;
+                }
+                _ => {
+                    self.refresh_os(RefreshOp::Enable)?;
+                    println!(
+                        "reboot to boot into the recovery partition to begin the refresh install"
+                    );
+                }
+            },
             ("repair", Some(_)) => {
                 self.release_repair()?;
             }
+            // Show how the last upgrade changed the installed packages.
+            ("changes", Some(matches)) => match self.package_diff()? {
+                Some(diff) => {
+                    pintln!((color_primary("Operation")) ": " (color_secondary(&diff.operation)));
+
+                    write_package_changes("Added", &diff.added);
+                    write_package_changes("Removed", &diff.removed);
+                    write_package_changes("Upgraded", &diff.upgraded);
+                    write_package_changes("Downgraded", &diff.downgraded);
+
+                    if matches.is_present("reinstall") {
+                        self.package_reinstall_removed()?;
+                        self.event_listen_package_reinstall()?;
+                    } else if !diff.manual_removed.is_empty() {
+                        pintln!(
+                            (color_primary("Removed packages installed by the user")) ": "
+                            (color_secondary(diff.manual_removed.join(", "))) "\n"
+                            "Run `pop-upgrade release changes --reinstall` to reinstall them"
+                        );
+                    }
+                }
+                None => println!("no package changes have been recorded"),
+            },
+            // Show the outcome of the last release upgrade, and optionally dismiss it.
+            ("report", Some(matches)) => match self.upgrade_report()? {
+                Some(report) => {
+                    let outcome = if report.is_success() { "success" } else { "failure" };
+
+                    pintln!(
+                        (color_primary("Upgraded from")) ": " (color_secondary(&report.from)) "\n"
+                        (color_primary("Upgraded to")) ": " (color_secondary(&report.to)) "\n"
+                        (color_primary("Detected release")) ": " (color_secondary(&report.detected)) "\n"
+                        (color_primary("Outcome")) ": " (color_secondary(outcome))
+                    );
+
+                    for issue in report.issues() {
+                        pintln!((color_primary("Issue")) ": " (color_error(&issue)));
+                    }
+
+                    if matches.is_present("dismiss") {
+                        self.upgrade_report_dismiss()?;
+                    }
+                }
+                None => println!("no release upgrade to report"),
+            },
             _ => unreachable!(),
         }
 
@@ -255,6 +411,42 @@ impl Client {
         }
     }
 
+    /// Shows the current and next release, or when not run in a terminal, notifies the user of a
+    /// new release which has not been dismissed.
+    fn release_check_notify(&self) -> anyhow::Result<()> {
+        let client::ReleaseInfo { current, next, build: available, is_lts, stale, .. } =
+            self.0.release_check(false)?;
+
+        if atty::is(atty::Stream::Stdout) {
+            let mut buffer = String::new();
+            println!(
+                "      Current Release: {}\n         Next Release: {}\nNew Release \
+                 Available: {}",
+                current,
+                next,
+                misc::format_build_number(available, &mut buffer)
+            );
+
+            if stale {
+                println!("the release server is unreachable: showing cached release data");
+            }
+        } else if available >= 0 {
+            if is_lts && (self.dismissed(&next) || self.dismiss_by_timestamp(&next)?) {
+                return Ok(());
+            }
+
+            let (summary, body) = notification_message(&current, &next);
+
+            let upgrade_panel = if &*current == "18.04" { "info-overview" } else { "upgrade" };
+
+            notify(&summary, &body, || {
+                let _ = exec::Command::new("gnome-control-center").arg(upgrade_panel).exec();
+            });
+        }
+
+        Ok(())
+    }
+
     fn release_check(
         &self,
         force_next: bool,
@@ -270,22 +462,20 @@ impl Client {
             client::Client::fetch_updates_status,
             |new_status| {
                 log_result(
-                    new_status.status,
+                    &new_status,
                     FETCH_RESULT_STR,
                     FETCH_RESULT_SUCCESS,
                     FETCH_RESULT_ERROR,
-                    &new_status.why,
                 )
             },
             |_client, signal| {
                 match signal {
                     client::Signal::PackageFetchResult(status) => {
                         log_result(
-                            status.status,
+                            &status,
                             "Package fetch status",
                             "cargo has been loaded successfully",
                             "package-fetching aborted",
-                            &status.why,
                         );
 
                         return Ok(client::Continue(false));
@@ -317,6 +507,39 @@ impl Client {
         )
     }
 
+    fn event_listen_package_reinstall(&self) -> Result<(), client::Error> {
+        self.event_listen(
+            DaemonStatus::PackageReinstall,
+            client::Client::package_reinstall_removed_status,
+            |new_status| {
+                log_result(
+                    &new_status,
+                    REINSTALL_RESULT_STR,
+                    REINSTALL_RESULT_SUCCESS,
+                    REINSTALL_RESULT_ERROR,
+                )
+            },
+            |_client, signal| {
+                if let client::Signal::PackageReinstallResult(status, reinstalled) = signal {
+                    for package in reinstalled {
+                        pintln!((color_primary("Reinstalled")) ": " (color_secondary(package)));
+                    }
+
+                    log_result(
+                        &status,
+                        REINSTALL_RESULT_STR,
+                        REINSTALL_RESULT_SUCCESS,
+                        REINSTALL_RESULT_ERROR,
+                    );
+
+                    return Ok(client::Continue(false));
+                }
+
+                Ok(client::Continue(true))
+            },
+        )
+    }
+
     fn event_listen_recovery_upgrade(&self) -> Result<(), client::Error> {
         let mut reset = false;
 
@@ -325,11 +548,10 @@ impl Client {
             client::Client::recovery_upgrade_release_status,
             |new_status| {
                 log_result(
-                    new_status.status,
+                    &new_status,
                     RECOVERY_RESULT_STR,
                     RECOVERY_RESULT_SUCCESS,
                     RECOVERY_RESULT_ERROR,
-                    &new_status.why,
                 )
             },
             move |_client, signal| {
@@ -347,6 +569,22 @@ impl Client {
 
                         reset = true;
                     }
+                    client::Signal::RecoverySyncProgress(progress) => {
+                        print!(
+                            "\r{} {}/{} {} ({}/{} {})",
+                            color_primary("Synced"),
+                            color_info(progress.current / 1024),
+                            color_info(progress.total / 1024),
+                            color_primary("MiB"),
+                            color_info(progress.files),
+                            color_info(progress.files_total),
+                            color_primary("files")
+                        );
+
+                        let _ = io::stdout().flush();
+
+                        reset = true;
+                    }
                     client::Signal::RecoveryEvent(event) => {
                         if reset {
                             reset = false;
@@ -366,11 +604,10 @@ impl Client {
                         }
 
                         log_result(
-                            status.status,
+                            &status,
                             RECOVERY_RESULT_STR,
                             RECOVERY_RESULT_SUCCESS,
                             RECOVERY_RESULT_ERROR,
-                            &status.why,
                         );
 
                         return Ok(client::Continue(false));
@@ -383,30 +620,122 @@ impl Client {
         )
     }
 
-    fn event_listen_release_upgrade(&self) -> Result<bool, client::Error> {
+    /// Returns the result of the verification, if it succeeded.
+    fn event_listen_recovery_verify(&self) -> Result<Option<RecoveryIntegrity>, client::Error> {
+        let mut result = None;
+        let mut reset = false;
+
+        self.event_listen(
+            DaemonStatus::RecoveryVerify,
+            client::Client::recovery_verify_status,
+            |new_status| {
+                log_result(
+                    &new_status,
+                    VERIFY_RESULT_STR,
+                    VERIFY_RESULT_SUCCESS,
+                    VERIFY_RESULT_ERROR,
+                )
+            },
+            |_client, signal| {
+                match signal {
+                    client::Signal::RecoveryVerifyProgress(progress) => {
+                        print!(
+                            "\r{} {}/{} {}",
+                            color_primary("Verified"),
+                            color_info(progress.progress),
+                            color_info(progress.total),
+                            color_primary("files")
+                        );
+
+                        let _ = io::stdout().flush();
+
+                        reset = true;
+                    }
+                    client::Signal::RecoveryVerifyResult(status, integrity) => {
+                        if reset {
+                            reset = false;
+                            println!();
+                        }
+
+                        if status.status == 0 {
+                            result = Some(integrity);
+                        } else {
+                            log_result(
+                                &status,
+                                VERIFY_RESULT_STR,
+                                VERIFY_RESULT_SUCCESS,
+                                VERIFY_RESULT_ERROR,
+                            );
+                        }
+
+                        return Ok(client::Continue(false));
+                    }
+                    _ => (),
+                }
+
+                Ok(client::Continue(true))
+            },
+        )?;
+
+        Ok(result)
+    }
+
+    fn event_listen_refresh_restore(&self) -> Result<(), client::Error> {
+        self.event_listen(
+            DaemonStatus::RestoringApplications,
+            client::Client::refresh_restore_status,
+            |new_status| {
+                log_result(
+                    &new_status,
+                    RESTORE_RESULT_STR,
+                    RESTORE_RESULT_SUCCESS,
+                    RESTORE_RESULT_ERROR,
+                )
+            },
+            |_client, signal| {
+                if let client::Signal::RefreshRestoreResult(status, unavailable) = signal {
+                    for application in unavailable {
+                        pintln!((color_primary("Unavailable")) ": " (color_secondary(application)));
+                    }
+
+                    log_result(
+                        &status,
+                        RESTORE_RESULT_STR,
+                        RESTORE_RESULT_SUCCESS,
+                        RESTORE_RESULT_ERROR,
+                    );
+
+                    return Ok(client::Continue(false));
+                }
+
+                Ok(client::Continue(true))
+            },
+        )
+    }
+
+    fn event_listen_release_upgrade(&self, success: &'static str) -> Result<bool, client::Error> {
         let recall = &mut false;
+        let mut reset = false;
 
         let result = self.event_listen(
             DaemonStatus::ReleaseUpgrade,
             client::Client::release_upgrade_status,
             |new_status| {
                 log_result(
-                    new_status.status,
+                    &new_status,
                     UPGRADE_RESULT_STR,
-                    UPGRADE_RESULT_SUCCESS,
+                    success,
                     UPGRADE_RESULT_ERROR,
-                    &new_status.why,
                 )
             },
             |_client, signal| {
                 match signal {
                     client::Signal::PackageFetchResult(status) => {
                         log_result(
-                            status.status,
+                            &status,
                             FETCH_RESULT_STR,
                             FETCH_RESULT_SUCCESS,
                             FETCH_RESULT_ERROR,
-                            &status.why,
                         );
                     }
                     client::Signal::PackageFetched(package) => {
@@ -430,24 +759,69 @@ impl Client {
                     client::Signal::ReleaseResult(status) => {
                         if !*recall {
                             log_result(
-                                status.status,
+                                &status,
                                 UPGRADE_RESULT_STR,
-                                UPGRADE_RESULT_SUCCESS,
+                                success,
                                 UPGRADE_RESULT_ERROR,
-                                &status.why,
                             );
                         }
 
                         return Ok(client::Continue(false));
                     }
                     client::Signal::ReleaseEvent(event) => {
+                        if reset {
+                            reset = false;
+                            println!();
+                        }
+
                         println!(
                             "{}: {}",
                             color_primary("Event"),
                             color_secondary(<&'static str>::from(event))
                         );
                     }
-                    client::Signal::NoConnection => {
+                    client::Signal::RecoveryDownloadProgress(progress) => {
+                        print!(
+                            "\r{} {}/{} {}",
+                            color_primary("Fetched"),
+                            color_info(progress.progress / 1024),
+                            color_info(progress.total / 1024),
+                            color_primary("MiB")
+                        );
+
+                        let _ = io::stdout().flush();
+
+                        reset = true;
+                    }
+                    client::Signal::RecoverySyncProgress(progress) => {
+                        print!(
+                            "\r{} {}/{} {} ({}/{} {})",
+                            color_primary("Synced"),
+                            color_info(progress.current / 1024),
+                            color_info(progress.total / 1024),
+                            color_primary("MiB"),
+                            color_info(progress.files),
+                            color_info(progress.files_total),
+                            color_primary("files")
+                        );
+
+                        let _ = io::stdout().flush();
+
+                        reset = true;
+                    }
+                    client::Signal::RecoveryEvent(event) => {
+                        if reset {
+                            reset = false;
+                            println!();
+                        }
+
+                        println!(
+                            "{}: {}",
+                            color_primary("Recovery event"),
+                            <&'static str>::from(event)
+                        );
+                    }
+                    client::Signal::NoConnection(hosts) => {
                         println!(
                             "{}",
                             color_error(
@@ -456,6 +830,10 @@ impl Client {
                             )
                         );
 
+                        for host in hosts.iter() {
+                            println!("    {}: {}", color_info("Unreachable"), color_secondary(host));
+                        }
+
                         let prompt = format!("    {} y/N", color_primary("Try again?"));
 
                         if prompt::get_bool(&prompt, false) {
@@ -543,6 +921,24 @@ fn notification_message(current: &str, next: &str) -> (String, String) {
     ("Upgrade Available".into(), fomat!("Pop!_OS " (next) " is available to download"))
 }
 
+fn write_package_changes(kind: &str, changes: &[PackageChange]) {
+    if changes.is_empty() {
+        return;
+    }
+
+    pintln!((color_primary(kind)) ":");
+
+    for change in changes {
+        let versions = match (change.from.is_empty(), change.to.is_empty()) {
+            (true, _) => change.to.clone(),
+            (_, true) => change.from.clone(),
+            _ => [&*change.from, " -> ", &*change.to].concat(),
+        };
+
+        pintln!("    " (color_secondary(&change.name)) " " (versions));
+    }
+}
+
 fn write_apt_event(event: AptUpgradeEvent) {
     match event {
         AptUpgradeEvent::Processing { package } => {
@@ -574,25 +970,58 @@ fn write_apt_event(event: AptUpgradeEvent) {
 }
 
 fn log_result(
-    status: u8,
+    status: &client::Status,
     event: &'static str,
     success: &'static str,
     error: &'static str,
-    why: &str,
 ) {
     let inner: String;
 
     println!(
         "{}: {}",
         color_info(event),
-        if status == 0 {
+        if status.status == 0 {
             color_primary(success)
         } else {
-            inner = format!("{}: {}", color_error(error), color_error_desc(why));
+            inner = format!("{}: {}", color_error(error), color_error_desc(&status.why));
 
             Paint::wrapping(inner.as_str())
         }
     );
+
+    if let Some(hint) = status.error.as_ref().and_then(error_hint) {
+        println!("{}: {}", color_info("Hint"), color_secondary(hint));
+    }
+}
+
+/// Suggests how the user may resolve an error reported by the daemon.
+fn error_hint(report: &ErrorReport) -> Option<&'static str> {
+    let hint = match report.code() {
+        Some(ErrorCode::NoConnection) => "check your network connection, then try again",
+        Some(ErrorCode::NoSpace) => "free up disk space, then try again",
+        Some(ErrorCode::ReadOnly) => "a file system is mounted read-only: reboot, then try again",
+        Some(ErrorCode::AptLocked) => "wait for other package managers to exit, then try again",
+        Some(ErrorCode::DpkgBroken) => {
+            "run `sudo apt install -f` and `sudo dpkg --configure -a`, then try again"
+        }
+        Some(ErrorCode::NotRoot) => "rerun the command with `sudo`",
+        Some(ErrorCode::Repositories) => {
+            "disable third party repositories with `pop-upgrade release repair`, then try again"
+        }
+        Some(ErrorCode::ChecksumMismatch) => "the download was corrupted: try again",
+        Some(ErrorCode::SignatureInvalid) => {
+            "the release could not be verified as authentic: do not install it"
+        }
+        _ => match report.category()? {
+            ErrorCategory::Network => "check your network connection, then try again",
+            ErrorCategory::PackageManager => {
+                "run `sudo apt install -f` and `sudo dpkg --configure -a`, then try again"
+            }
+            _ => return None,
+        },
+    };
+
+    Some(hint)
 }
 
 pub fn root_required() -> anyhow::Result<()> {
@@ -1,8 +1,17 @@
 use crate::{
     daemon::{DaemonStatus as PrimaryStatus, *},
-    recovery::{RecoveryEvent, ReleaseFlags as RecoveryReleaseFlags},
-    release::{RefreshOp, UpgradeEvent, UpgradeMethod},
-    sighandler, DBUS_IFACE, DBUS_NAME, DBUS_PATH,
+    error_code::ErrorReport,
+    recovery::{
+        RecoveryEvent, RecoveryIntegrity, RecoveryMode, ReleaseFlags as RecoveryReleaseFlags,
+        SyncProgress,
+    },
+    release::{
+        manifest::{PackageChange, PackageDiff},
+        verify::UpgradeReport,
+        RefreshOp, UpgradeEvent, UpgradeMethod,
+    },
+    release_api::Channel,
+    sighandler, DBUS_IFACE, DBUS_INTERFACE_MIN, DBUS_INTERFACE_VERSION, DBUS_NAME, DBUS_PATH,
 };
 
 use dbus::{
@@ -17,6 +26,41 @@ use thiserror::Error;
 
 const TIMEOUT: i32 = 0x7fff_ffff;
 
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+
+/// Information about the daemon, negotiated when the client connects.
+#[derive(Clone, Debug)]
+pub struct DaemonInfo {
+    pub version:         Box<str>,
+    pub interface:       u32,
+    pub interface_min:   u32,
+    pub upgrade_methods: Vec<UpgradeMethod>,
+    pub features:        Vec<Box<str>>,
+}
+
+impl DaemonInfo {
+    /// Daemons which predate the `Version` method only support offline upgrades.
+    fn legacy() -> Self {
+        Self {
+            version:         Box::from("unknown"),
+            interface:       0,
+            interface_min:   0,
+            upgrade_methods: vec![UpgradeMethod::Offline],
+            features:        Vec::new(),
+        }
+    }
+
+    /// Whether the daemon advertised support for the given feature.
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|supported| supported.as_ref() == feature)
+    }
+
+    /// Whether the daemon is able to perform a release upgrade with this method.
+    pub fn supports_upgrade_method(&self, method: UpgradeMethod) -> bool {
+        self.upgrade_methods.contains(&method)
+    }
+}
+
 // Information about the current fetch progress.
 #[derive(Clone, Debug)]
 pub struct FetchStatus {
@@ -41,14 +85,25 @@ pub struct RepoCompatError {
 
 /// A signal received by the daemon.
 pub enum Signal {
-    NoConnection,
+    /// Hosts which could not be reached.
+    NoConnection(Vec<Box<str>>),
     PackageFetchResult(Status),
     PackageFetched(FetchStatus),
     PackageFetching(Box<str>),
+    /// The result of reinstalling the removed packages, and those which were reinstalled.
+    PackageReinstallResult(Status, Vec<String>),
     PackageUpgrade(HashMap<Box<str>, Box<str>>),
     RecoveryDownloadProgress(Progress),
     RecoveryEvent(RecoveryEvent),
     RecoveryResult(Status),
+    /// Progress of syncing the ISO to the recovery partition.
+    RecoverySyncProgress(SyncProgress),
+    /// The number of files of the recovery partition which have been verified.
+    RecoveryVerifyProgress(Progress),
+    /// The result of verifying the recovery partition, and the files which failed verification.
+    RecoveryVerifyResult(Status, RecoveryIntegrity),
+    /// The result of reinstalling the preserved applications, and those which were unavailable.
+    RefreshRestoreResult(Status, Vec<String>),
     ReleaseResult(Status),
     ReleaseEvent(UpgradeEvent),
 }
@@ -72,6 +127,14 @@ pub struct Fetched {
     pub total:             u32,
 }
 
+/// Applications preserved before a refresh of the OS, which may be reinstalled.
+#[derive(Clone, Debug)]
+pub struct PreservedApplications {
+    pub packages: Vec<String>,
+    pub sources:  Vec<String>,
+    pub flatpaks: Vec<String>,
+}
+
 /// The version of the recovery partition's image.
 #[derive(Clone, Debug)]
 pub struct RecoveryVersion {
@@ -90,13 +153,45 @@ pub struct ReleaseInfo {
     pub build:   i16,
     pub urgent:  Option<u16>,
     pub is_lts:  bool,
+    /// The release API could not be reached, so the information may be out of date.
+    pub stale:   bool,
 }
 
 /// The status of an action, and a description of why.
+///
+/// Daemons which implement version 2 or later of the interface also describe failures with an
+/// error report.
 #[derive(Clone, Debug)]
 pub struct Status {
     pub status: u8,
     pub why:    Box<str>,
+    pub error:  Option<ErrorReport>,
+}
+
+impl Status {
+    fn read(message: &Message) -> Result<Self, dbus::arg::TypeMismatchError> {
+        let mut iter = message.iter_init();
+        let status = iter.read::<u8>()?;
+        let why = iter.read::<&str>()?;
+
+        let error = if status == 0 {
+            None
+        } else {
+            match (
+                iter.read::<u8>(),
+                iter.read::<u16>(),
+                iter.read::<HashMap<String, String>>(),
+                iter.read::<Vec<String>>(),
+            ) {
+                (Ok(category), Ok(code), Ok(context), Ok(causes)) => {
+                    Some(ErrorReport { category, code, context, causes })
+                }
+                _ => None,
+            }
+        };
+
+        Ok(Status { status, why: why.into(), error })
+    }
 }
 
 #[derive(Debug, Error)]
@@ -110,18 +205,38 @@ pub enum Error {
     #[error("calling {} method failed", _0)]
     Call(&'static str, #[source] dbus::Error),
 
+    #[error(
+        "client implements version {} of the interface, but the daemon requires at least version \
+         {}: restart the application to use the updated client",
+        client,
+        required
+    )]
+    ClientTooOld { client: u32, required: u32 },
+
     #[error("unable to establish dbus connection")]
     Connection(#[source] dbus::Error),
 
     #[error("daemon status integer was outside the acceptable range of values")]
     DaemonStatusOutOfRange,
 
+    #[error(
+        "daemon implements version {} of the interface, but this client requires at least version \
+         {}: update the pop-upgrade package",
+        daemon,
+        required
+    )]
+    DaemonTooOld { daemon: u32, required: u32 },
+
     #[error("failed to create {} method call", _0)]
     NewMethodCall(&'static str, String),
+
+    #[error("the daemon does not support the {}", _0)]
+    Unsupported(&'static str),
 }
 
 pub struct Client {
-    pub bus: Connection,
+    pub bus:    Connection,
+    pub daemon: DaemonInfo,
 }
 
 impl Client {
@@ -141,16 +256,66 @@ impl Client {
                 add_match(bus, signals::PACKAGE_FETCH_RESULT)?;
                 add_match(bus, signals::PACKAGE_FETCHED)?;
                 add_match(bus, signals::PACKAGE_FETCHING)?;
+                add_match(bus, signals::PACKAGE_REINSTALL_RESULT)?;
                 add_match(bus, signals::PACKAGE_UPGRADE)?;
                 add_match(bus, signals::RECOVERY_DOWNLOAD_PROGRESS)?;
                 add_match(bus, signals::RECOVERY_RESULT)?;
                 add_match(bus, signals::RECOVERY_EVENT)?;
+                add_match(bus, signals::RECOVERY_SYNC_PROGRESS)?;
+                add_match(bus, signals::RECOVERY_VERIFY_PROGRESS)?;
+                add_match(bus, signals::RECOVERY_VERIFY_RESULT)?;
+                add_match(bus, signals::REFRESH_RESTORE_RESULT)?;
                 add_match(bus, signals::RELEASE_RESULT)?;
                 add_match(bus, signals::RELEASE_EVENT)?;
                 add_match(bus, signals::REPO_COMPAT_ERROR)?;
             }
 
-            Ok(Client { bus })
+            let mut client = Client { bus, daemon: DaemonInfo::legacy() };
+            client.daemon = client.negotiate()?;
+            Ok(client)
+        })
+    }
+
+    /// Queries the version and capabilities of the daemon, and checks that it is compatible.
+    fn negotiate(&self) -> Result<DaemonInfo, Error> {
+        let message = match self.call_method(methods::VERSION, |m| m) {
+            Ok(message) => message,
+            Err(Error::Call(_, ref why)) if why.name() == Some(UNKNOWN_METHOD) => {
+                info!("daemon does not implement the {} method: assuming legacy", methods::VERSION);
+                return Ok(DaemonInfo::legacy());
+            }
+            Err(why) => return Err(why),
+        };
+
+        let (version, interface, interface_min) = message
+            .read3::<&str, u32, u32>()
+            .map_err(|why| Error::ArgumentMismatch(methods::VERSION, why))?;
+
+        if interface < DBUS_INTERFACE_MIN {
+            return Err(Error::DaemonTooOld { daemon: interface, required: DBUS_INTERFACE_MIN });
+        }
+
+        if DBUS_INTERFACE_VERSION < interface_min {
+            return Err(Error::ClientTooOld {
+                client:   DBUS_INTERFACE_VERSION,
+                required: interface_min,
+            });
+        }
+
+        let (upgrade_methods, features) = self
+            .call_method(methods::CAPABILITIES, |m| m)?
+            .read2::<Vec<u8>, Vec<String>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::CAPABILITIES, why))?;
+
+        Ok(DaemonInfo {
+            version: version.into(),
+            interface,
+            interface_min,
+            upgrade_methods: upgrade_methods
+                .into_iter()
+                .filter_map(UpgradeMethod::from_u8)
+                .collect(),
+            features: features.into_iter().map(Box::from).collect(),
         })
     }
 
@@ -160,6 +325,18 @@ impl Client {
         Ok(())
     }
 
+    /// The channel that release upgrades are taken from, if it is known to this client.
+    pub fn channel(&self) -> Result<Option<Channel>, Error> {
+        if !self.daemon.supports(features::CHANNELS) {
+            return Err(Error::Unsupported(features::CHANNELS));
+        }
+
+        self.call_method(methods::GET_CHANNEL, |m| m)?
+            .read1::<u8>()
+            .map_err(|why| Error::ArgumentMismatch(methods::GET_CHANNEL, why))
+            .map(Channel::from_u8)
+    }
+
     /// Dismiss future desktop notifications for the currently-available upgrade.
     pub fn dismiss_notification(&self, event: DismissEvent) -> Result<bool, Error> {
         self.call_method(methods::DISMISS_NOTIFICATION, |m| m.append1(event as u8))?
@@ -198,10 +375,57 @@ impl Client {
 
     /// Retrieves the last known status of a system update.
     pub fn fetch_updates_status(&self) -> Result<Status, Error> {
-        self.call_method(methods::FETCH_UPDATES_STATUS, |m| m)?
-            .read2::<u8, &str>()
+        Status::read(&self.call_method(methods::FETCH_UPDATES_STATUS, |m| m)?)
             .map_err(|why| Error::ArgumentMismatch(methods::FETCH_UPDATES_STATUS, why))
-            .map(|(status, why)| Status { status, why: why.into() })
+    }
+
+    /// Fetches the changes made to the installed packages by the last upgrade.
+    pub fn package_diff(&self) -> Result<Option<PackageDiff>, Error> {
+        if !self.daemon.supports(features::PACKAGE_DIFF) {
+            return Err(Error::Unsupported(features::PACKAGE_DIFF));
+        }
+
+        let message = self.call_method(methods::PACKAGE_DIFF, |m| m)?;
+
+        let read = || -> Result<_, dbus::arg::TypeMismatchError> {
+            let mut iter = message.iter_init();
+
+            let available = iter.read::<bool>()?;
+
+            let diff = PackageDiff {
+                operation:      iter.read::<String>()?,
+                added:          read_changes(iter.read::<PackageChanges>()?),
+                removed:        read_changes(iter.read::<PackageChanges>()?),
+                upgraded:       read_changes(iter.read::<PackageChanges>()?),
+                downgraded:     read_changes(iter.read::<PackageChanges>()?),
+                manual_removed: iter.read::<Vec<String>>()?,
+            };
+
+            Ok(Some(diff).filter(|_| available))
+        };
+
+        read().map_err(|why| Error::ArgumentMismatch(methods::PACKAGE_DIFF, why))
+    }
+
+    /// Initiates reinstalling packages installed by the user which the last upgrade removed.
+    ///
+    /// The packages which were available to reinstall are reported by the
+    /// `PackageReinstallResult` signal.
+    pub fn package_reinstall_removed(&self) -> Result<(), Error> {
+        if !self.daemon.supports(features::PACKAGE_DIFF) {
+            return Err(Error::Unsupported(features::PACKAGE_DIFF));
+        }
+
+        self.call_method(methods::PACKAGE_REINSTALL_REMOVED, |m| m)?;
+        Ok(())
+    }
+
+    /// Retrieves the last known status of reinstalling the removed packages.
+    pub fn package_reinstall_removed_status(&self) -> Result<Status, Error> {
+        let message = self.call_method(methods::PACKAGE_REINSTALL_REMOVED_STATUS, |m| m)?;
+
+        Status::read(&message)
+            .map_err(|why| Error::ArgumentMismatch(methods::PACKAGE_REINSTALL_REMOVED_STATUS, why))
     }
 
     /// Initiates upgrading the system packages.
@@ -210,6 +434,40 @@ impl Client {
         Ok(())
     }
 
+    /// The mode that the recovery partition will boot into, if one is pending.
+    ///
+    /// Daemons which predate recovery modes are unable to report the pending mode.
+    pub fn recovery_mode(&self) -> Result<Option<RecoveryMode>, Error> {
+        if !self.daemon.supports(features::RECOVERY_MODES) {
+            return Ok(None);
+        }
+
+        self.call_method(methods::RECOVERY_MODE, |m| m)?
+            .read1::<u8>()
+            .map_err(|why| Error::ArgumentMismatch(methods::RECOVERY_MODE, why))
+            .map(RecoveryMode::from_u8)
+    }
+
+    /// Boots into the recovery partition in the given mode on the next boot.
+    pub fn recovery_mode_set(&self, mode: RecoveryMode) -> Result<(), Error> {
+        if !self.daemon.supports(features::RECOVERY_MODES) {
+            return Err(Error::Unsupported(features::RECOVERY_MODES));
+        }
+
+        self.call_method(methods::RECOVERY_MODE_SET, |m| m.append1(mode as u8))?;
+        Ok(())
+    }
+
+    /// Cancels the mode that the recovery partition was going to boot into.
+    pub fn recovery_mode_unset(&self) -> Result<(), Error> {
+        if !self.daemon.supports(features::RECOVERY_MODES) {
+            return Err(Error::Unsupported(features::RECOVERY_MODES));
+        }
+
+        self.call_method(methods::RECOVERY_MODE_UNSET, |m| m)?;
+        Ok(())
+    }
+
     /// Initiates upgrading the recovery partition via a recovery image file.
     pub fn recovery_upgrade_file<P: AsRef<str>>(&self, path: P) -> Result<u8, Error> {
         self.call_method(methods::RECOVERY_UPGRADE_FILE, move |m| m.append1(path.as_ref()))?
@@ -232,10 +490,26 @@ impl Client {
 
     /// Retrieves the last known status of a recovery upgrade.
     pub fn recovery_upgrade_release_status(&self) -> Result<Status, Error> {
-        self.call_method(methods::RECOVERY_UPGRADE_RELEASE_STATUS, |m| m)?
-            .read2::<u8, &str>()
+        Status::read(&self.call_method(methods::RECOVERY_UPGRADE_RELEASE_STATUS, |m| m)?)
             .map_err(|why| Error::ArgumentMismatch(methods::RECOVERY_UPGRADE_RELEASE_STATUS, why))
-            .map(|(status, why)| Status { status, why: why.into() })
+    }
+
+    /// Initiates checking the files of the recovery partition against the checksums of its ISO.
+    ///
+    /// The files which failed verification are reported by the `RecoveryVerifyResult` signal.
+    pub fn recovery_verify(&self) -> Result<(), Error> {
+        if !self.daemon.supports(features::RECOVERY_VERIFY) {
+            return Err(Error::Unsupported(features::RECOVERY_VERIFY));
+        }
+
+        self.call_method(methods::RECOVERY_VERIFY, |m| m)?;
+        Ok(())
+    }
+
+    /// Retrieves the last known status of verifying the recovery partition.
+    pub fn recovery_verify_status(&self) -> Result<Status, Error> {
+        Status::read(&self.call_method(methods::RECOVERY_VERIFY_STATUS, |m| m)?)
+            .map_err(|why| Error::ArgumentMismatch(methods::RECOVERY_VERIFY_STATUS, why))
     }
 
     /// Fetches the version of the recovery partition currently-installed.
@@ -253,24 +527,81 @@ impl Client {
             .map_err(|why| Error::ArgumentMismatch(methods::REFRESH_OS, why))
     }
 
+    /// Removes the applications which were preserved for a refresh of the OS.
+    pub fn refresh_discard(&self) -> Result<(), Error> {
+        if !self.daemon.supports(features::REFRESH_RESTORE) {
+            return Err(Error::Unsupported(features::REFRESH_RESTORE));
+        }
+
+        self.call_method(methods::REFRESH_DISCARD, |m| m)?;
+        Ok(())
+    }
+
+    /// Fetches the applications preserved before a refresh, which have yet to be reinstalled.
+    ///
+    /// Daemons which do not preserve applications have nothing to reinstall.
+    pub fn refresh_preserved(&self) -> Result<Option<PreservedApplications>, Error> {
+        if !self.daemon.supports(features::REFRESH_RESTORE) {
+            return Ok(None);
+        }
+
+        self.call_method(methods::REFRESH_PRESERVED, |m| m)?
+            .read4::<bool, Vec<String>, Vec<String>, Vec<String>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::REFRESH_PRESERVED, why))
+            .map(|(available, packages, sources, flatpaks)| {
+                Some(PreservedApplications { packages, sources, flatpaks }).filter(|_| available)
+            })
+    }
+
+    /// Initiates reinstalling the applications preserved before a refresh of the OS.
+    ///
+    /// The applications which could not be reinstalled are reported by the
+    /// `RefreshRestoreResult` signal.
+    pub fn refresh_restore(&self) -> Result<(), Error> {
+        if !self.daemon.supports(features::REFRESH_RESTORE) {
+            return Err(Error::Unsupported(features::REFRESH_RESTORE));
+        }
+
+        self.call_method(methods::REFRESH_RESTORE, |m| m)?;
+        Ok(())
+    }
+
+    /// Retrieves the last known status of reinstalling the preserved applications.
+    pub fn refresh_restore_status(&self) -> Result<Status, Error> {
+        Status::read(&self.call_method(methods::REFRESH_RESTORE_STATUS, |m| m)?)
+            .map_err(|why| Error::ArgumentMismatch(methods::REFRESH_RESTORE_STATUS, why))
+    }
+
     /// Check the current release information
     ///
     /// Used to determine if a release upgrade is available.
     pub fn release_check(&self, development: bool) -> Result<ReleaseInfo, Error> {
-        self.call_method(methods::RELEASE_CHECK, |m| m.append1(development))?
+        let message = self.call_method(methods::RELEASE_CHECK, |m| m.append1(development))?;
+
+        let (current, next, build, urgent, is_lts) = message
             .read5::<&str, &str, i16, i16, bool>()
-            .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_CHECK, why))
-            .map(|(current, next, build, urgent, is_lts)| ReleaseInfo {
-                current: current.into(),
-                next: next.into(),
-                build,
-                urgent: if urgent > -1 { Some(urgent as u16) } else { None },
-                is_lts,
-            })
+            .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_CHECK, why))?;
+
+        // Daemons prior to version 10 of the interface do not report stale release data.
+        let mut iter = message.iter_init();
+        let stale = (0..5).all(|_| iter.next()) && iter.get::<bool>().unwrap_or(false);
+
+        Ok(ReleaseInfo {
+            current: current.into(),
+            next: next.into(),
+            build,
+            urgent: if urgent > -1 { Some(urgent as u16) } else { None },
+            is_lts,
+            stale,
+        })
     }
 
     /// Initiates a release upgrade using the given method.
     pub fn release_upgrade(&self, how: UpgradeMethod, from: &str, to: &str) -> Result<(), Error> {
+        if !self.daemon.supports_upgrade_method(how) {
+            return Err(Error::Unsupported(how.into()));
+        }
+
         self.call_method(methods::RELEASE_UPGRADE, move |m| m.append3(how as u8, from, to))?;
 
         Ok(())
@@ -283,10 +614,8 @@ impl Client {
 
     /// Retrieves the last known status of a release upgrade.
     pub fn release_upgrade_status(&self) -> Result<Status, Error> {
-        self.call_method(methods::RELEASE_UPGRADE_STATUS, |m| m)?
-            .read2::<u8, &str>()
+        Status::read(&self.call_method(methods::RELEASE_UPGRADE_STATUS, |m| m)?)
             .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_UPGRADE_STATUS, why))
-            .map(|(status, why)| Status { status, why: why.into() })
     }
 
     /// Attempts to repair any system issues detected.
@@ -295,12 +624,64 @@ impl Client {
         Ok(())
     }
 
+    /// Fetches the report of the last release upgrade, if it has not been dismissed.
+    ///
+    /// Daemons which do not generate upgrade reports have no report to give.
+    pub fn upgrade_report(&self) -> Result<Option<UpgradeReport>, Error> {
+        if !self.daemon.supports(features::UPGRADE_REPORT) {
+            return Ok(None);
+        }
+
+        let message = self.call_method(methods::UPGRADE_REPORT, |m| m)?;
+
+        let read = || -> Result<_, dbus::arg::TypeMismatchError> {
+            let mut iter = message.iter_init();
+
+            let available = iter.read::<bool>()?;
+
+            let report = UpgradeReport {
+                from:         iter.read::<String>()?,
+                to:           iter.read::<String>()?,
+                detected:     iter.read::<String>()?,
+                error:        Some(iter.read::<String>()?).filter(|why| !why.is_empty()),
+                missing:      iter.read::<Vec<String>>()?,
+                unconfigured: iter.read::<Vec<String>>()?,
+                staged:       iter.read::<bool>()?,
+                dismissed:    false,
+            };
+
+            Ok(Some(report).filter(|_| available))
+        };
+
+        read().map_err(|why| Error::ArgumentMismatch(methods::UPGRADE_REPORT, why))
+    }
+
+    /// Marks the report of the last release upgrade as seen.
+    pub fn upgrade_report_dismiss(&self) -> Result<(), Error> {
+        if !self.daemon.supports(features::UPGRADE_REPORT) {
+            return Err(Error::Unsupported(features::UPGRADE_REPORT));
+        }
+
+        self.call_method(methods::UPGRADE_REPORT_DISMISS, |m| m)?;
+        Ok(())
+    }
+
     /// Reset the daemon to its initial state, and clean up any changes.
     pub fn reset(&self) -> Result<(), Error> {
         self.call_method(methods::RESET, |m| m)?;
         Ok(())
     }
 
+    /// Takes release upgrades from the given channel.
+    pub fn set_channel(&self, channel: Channel) -> Result<(), Error> {
+        if !self.daemon.supports(features::CHANNELS) {
+            return Err(Error::Unsupported(features::CHANNELS));
+        }
+
+        self.call_method(methods::SET_CHANNEL, |m| m.append1(channel as u8))?;
+        Ok(())
+    }
+
     /// Retrieves the status of the daemon.
     pub fn status(&self) -> Result<DaemonStatus, Error> {
         self.call_method(methods::STATUS, |m| m)?
@@ -345,10 +726,15 @@ impl Client {
                 }
             } else if let Some(signal) = filter_signal(item) {
                 let signal = match &*signal.member().unwrap() {
-                    signals::NO_CONNECTION => Signal::NoConnection,
-                    signals::PACKAGE_FETCH_RESULT => signal
-                        .read2::<u8, String>()
-                        .map(|(status, why)| Status { status, why: why.into() })
+                    signals::NO_CONNECTION => Signal::NoConnection(
+                        signal
+                            .read1::<Vec<String>>()
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(Box::from)
+                            .collect(),
+                    ),
+                    signals::PACKAGE_FETCH_RESULT => Status::read(&signal)
                         .map(Signal::PackageFetchResult)
                         .map_err(|why| {
                             Error::ArgumentMismatch(signals::PACKAGE_FETCH_RESULT, why)
@@ -366,6 +752,13 @@ impl Client {
                         .read1::<String>()
                         .map(|package| Signal::PackageFetching(Box::from(package)))
                         .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_FETCHING, why))?,
+                    signals::PACKAGE_REINSTALL_RESULT => {
+                        let status = Status::read(&signal).map_err(|why| {
+                            Error::ArgumentMismatch(signals::PACKAGE_REINSTALL_RESULT, why)
+                        })?;
+
+                        Signal::PackageReinstallResult(status, read_list(&signal))
+                    }
                     signals::PACKAGE_UPGRADE => signal
                         .read1::<HashMap<String, String>>()
                         .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_UPGRADE, why))
@@ -390,11 +783,66 @@ impl Client {
                             RecoveryEvent::from_u8(event).expect("unexpected recovery event value")
                         })
                         .map(Signal::RecoveryEvent)?,
-                    signals::RECOVERY_RESULT => signal
-                        .read2::<u8, String>()
+                    signals::RECOVERY_RESULT => Status::read(&signal)
                         .map_err(|why| Error::ArgumentMismatch(signals::RECOVERY_RESULT, why))
-                        .map(|(status, why)| Status { status, why: why.into() })
                         .map(Signal::RecoveryResult)?,
+                    signals::RECOVERY_SYNC_PROGRESS => signal
+                        .read5::<String, u32, u32, u64, u64>()
+                        .map_err(|why| {
+                            Error::ArgumentMismatch(signals::RECOVERY_SYNC_PROGRESS, why)
+                        })
+                        .map(|(file, files, files_total, current, total)| SyncProgress {
+                            file,
+                            files,
+                            files_total,
+                            current,
+                            total,
+                        })
+                        .map(Signal::RecoverySyncProgress)?,
+                    signals::RECOVERY_VERIFY_PROGRESS => signal
+                        .read2::<u32, u32>()
+                        .map_err(|why| {
+                            Error::ArgumentMismatch(signals::RECOVERY_VERIFY_PROGRESS, why)
+                        })
+                        .map(|(checked, total)| Progress {
+                            progress: u64::from(checked),
+                            total:    u64::from(total),
+                        })
+                        .map(Signal::RecoveryVerifyProgress)?,
+                    signals::RECOVERY_VERIFY_RESULT => {
+                        let status = Status::read(&signal).map_err(|why| {
+                            Error::ArgumentMismatch(signals::RECOVERY_VERIFY_RESULT, why)
+                        })?;
+
+                        // The files which failed verification follow the result.
+                        let read = || -> Result<_, dbus::arg::TypeMismatchError> {
+                            let mut iter = signal.iter_init();
+
+                            for _ in 0..6 {
+                                iter.next();
+                            }
+
+                            Ok(RecoveryIntegrity {
+                                unverifiable: iter.read::<bool>()?,
+                                checked:      iter.read::<u32>()?,
+                                missing:      iter.read::<Vec<String>>()?,
+                                corrupt:      iter.read::<Vec<String>>()?,
+                            })
+                        };
+
+                        let integrity = read().map_err(|why| {
+                            Error::ArgumentMismatch(signals::RECOVERY_VERIFY_RESULT, why)
+                        })?;
+
+                        Signal::RecoveryVerifyResult(status, integrity)
+                    }
+                    signals::REFRESH_RESTORE_RESULT => {
+                        let status = Status::read(&signal).map_err(|why| {
+                            Error::ArgumentMismatch(signals::REFRESH_RESTORE_RESULT, why)
+                        })?;
+
+                        Signal::RefreshRestoreResult(status, read_list(&signal))
+                    }
                     signals::RELEASE_EVENT => signal
                         .read1::<u8>()
                         .map_err(|why| Error::ArgumentMismatch(signals::RELEASE_EVENT, why))
@@ -402,10 +850,8 @@ impl Client {
                             UpgradeEvent::from_u8(event).expect("unexpected upgrade event value")
                         })
                         .map(Signal::ReleaseEvent)?,
-                    signals::RELEASE_RESULT => signal
-                        .read2::<u8, String>()
+                    signals::RELEASE_RESULT => Status::read(&signal)
                         .map_err(|why| Error::ArgumentMismatch(signals::RELEASE_RESULT, why))
-                        .map(|(status, why)| Status { status, why: why.into() })
                         .map(Signal::ReleaseResult)?,
                     _ => continue,
                 };
@@ -439,6 +885,21 @@ impl Client {
     }
 }
 
+fn read_changes(changes: PackageChanges) -> Vec<PackageChange> {
+    changes.into_iter().map(|(name, from, to)| PackageChange { name, from, to }).collect()
+}
+
+/// Reads the list of packages or applications which follows the result of an operation.
+fn read_list(signal: &Message) -> Vec<String> {
+    let mut iter = signal.iter_init();
+
+    if (0..6).all(|_| iter.next()) {
+        iter.get::<Vec<String>>().unwrap_or_default()
+    } else {
+        Vec::new()
+    }
+}
+
 fn filter_signal(ci: ConnectionItem) -> Option<Message> {
     if let ConnectionItem::Signal(ci) = ci {
         Some(ci)
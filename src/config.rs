@@ -0,0 +1,181 @@
+use crate::{release_api::Channel, DEVELOPMENT_RELEASE_FILE};
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Options for the daemon, as `KEY value` pairs.
+pub const CONFIG: &str = "/etc/pop-upgrade/pop-upgrade.conf";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Refuse release ISOs whose checksums are not signed by the Pop!_OS archive key. Enabled
+    /// unless it is explicitly disabled.
+    pub require_signatures: bool,
+    /// The channel that release upgrades are taken from.
+    pub channel:            Channel,
+}
+
+impl Default for Config {
+    fn default() -> Self { Config { require_signatures: true, channel: Channel::Stable } }
+}
+
+impl Config {
+    /// Reads the config, falling back to the defaults for options which are missing or invalid.
+    pub fn load() -> Self {
+        let development = Path::new(DEVELOPMENT_RELEASE_FILE).exists();
+
+        match fs::read_to_string(CONFIG) {
+            Ok(config) => Config::parse(&config, development),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Config::parse("", development),
+            Err(why) => {
+                warn!("failed to read {}; using the defaults: {}", CONFIG, why);
+                Config::parse("", development)
+            }
+        }
+    }
+
+    /// Parses the config, where `development` is the presence of the legacy flag file, which
+    /// selects the development channel if no channel is set.
+    fn parse(config: &str, development: bool) -> Self {
+        let options = whitespace_conf::parse(config);
+        let mut config = Config::default();
+
+        if let Some(value) = options.get("REQUIRE_SIGNATURES") {
+            match parse_bool(value) {
+                Some(value) => config.require_signatures = value,
+                None => warn!("{}: REQUIRE_SIGNATURES is not a boolean: {}", CONFIG, value),
+            }
+        }
+
+        match options.get("CHANNEL") {
+            Some(value) => match value.parse::<Channel>() {
+                Ok(channel) => config.channel = channel,
+                Err(why) => warn!("{}: {}", CONFIG, why),
+            },
+            None if development => config.channel = Channel::Development,
+            None => (),
+        }
+
+        config
+    }
+}
+
+/// Selects the channel that release upgrades are taken from, which replaces the legacy flag file.
+pub fn set_channel(channel: Channel) -> io::Result<()> {
+    info!("setting the release channel to {}", channel);
+
+    let config = match fs::read_to_string(CONFIG) {
+        Ok(config) => config,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(why) => return Err(why),
+    };
+
+    if let Some(parent) = Path::new(CONFIG).parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    replace(Path::new(CONFIG), &set_option(&config, "CHANNEL", channel.as_str()))?;
+
+    match fs::remove_file(DEVELOPMENT_RELEASE_FILE) {
+        Err(why) if why.kind() != io::ErrorKind::NotFound => Err(why),
+        _ => Ok(()),
+    }
+}
+
+/// Replaces the config through a temporary file, so that an interrupted write cannot leave it
+/// empty or truncated.
+fn replace(path: &Path, contents: &str) -> io::Result<()> {
+    let temporary = path.with_extension("conf.tmp");
+
+    let write = || -> io::Result<()> {
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, path)
+    };
+
+    write().map_err(|why| {
+        let _ = fs::remove_file(&temporary);
+        why
+    })
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Replaces the value of an option, or appends it, while preserving the rest of the config.
+fn set_option(config: &str, key: &str, value: &str) -> String {
+    let mut output = String::with_capacity(config.len() + key.len() + value.len() + 2);
+    let mut found = false;
+
+    for line in config.lines() {
+        if line.split_whitespace().next() == Some(key) {
+            if !found {
+                output.push_str(&fomat!((key) " " (value) "\n"));
+                found = true;
+            }
+
+            continue;
+        }
+
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    if !found {
+        output.push_str(&fomat!((key) " " (value) "\n"));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Config::parse("", false), Config::default());
+        assert_eq!(Config::parse("REQUIRE_SIGNATURES no\n", false), Config {
+            require_signatures: false,
+            channel:            Channel::Stable,
+        });
+        assert_eq!(Config::parse("REQUIRE_SIGNATURES maybe\n", false), Config::default());
+    }
+
+    #[test]
+    fn channel() {
+        assert_eq!(Config::parse("CHANNEL beta\n", false).channel, Channel::Beta);
+        assert_eq!(Config::parse("CHANNEL stable\n", true).channel, Channel::Stable);
+        assert_eq!(Config::parse("", true).channel, Channel::Development);
+        assert_eq!(Config::parse("CHANNEL nightly\n", false).channel, Channel::Stable);
+    }
+
+    #[test]
+    fn set() {
+        assert_eq!(set_option("", "CHANNEL", "beta"), "CHANNEL beta\n");
+        assert_eq!(
+            set_option("REQUIRE_SIGNATURES yes\nCHANNEL stable\n", "CHANNEL", "beta"),
+            "REQUIRE_SIGNATURES yes\nCHANNEL beta\n"
+        );
+    }
+
+    #[test]
+    fn replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pop-upgrade.conf");
+
+        fs::write(&path, "REQUIRE_SIGNATURES no\nCHANNEL stable\n").unwrap();
+        replace(&path, "REQUIRE_SIGNATURES no\nCHANNEL beta\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "REQUIRE_SIGNATURES no\nCHANNEL beta\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
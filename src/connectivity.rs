@@ -0,0 +1,286 @@
+use futures::prelude::*;
+use isahc::{config::Configurable, http::Uri, Request};
+use std::{collections::BTreeSet, error::Error as ErrorTrait, fs, io, path::Path, time::Duration};
+
+pub const RELEASE_API: &str = "https://api.pop-os.org/";
+
+const SOURCES_LIST: &str = "/etc/apt/sources.list";
+const SOURCES_PARTS: &str = "/etc/apt/sources.list.d/";
+
+const NM_NAME: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+
+/// NetworkManager's `NM_CONNECTIVITY_NONE` state: the host is not connected to any network.
+const NM_CONNECTIVITY_NONE: u32 = 1;
+
+const PROBE_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Verifies that each of the hosts serving these URLs is reachable.
+///
+/// On failure, the base URLs of the unreachable hosts are returned.
+pub async fn preflight<I, S>(urls: I) -> Result<(), Vec<String>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let hosts = urls
+        .into_iter()
+        .filter_map(|url| base_url(url.as_ref()))
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect::<Vec<String>>();
+
+    if hosts.is_empty() {
+        return Ok(());
+    }
+
+    if network_manager_offline() {
+        warn!("NetworkManager reports that the system is not connected to a network");
+        return Err(hosts);
+    }
+
+    let probes = hosts.into_iter().map(|host| async move {
+        let request = Request::head(host.as_str())
+            .connect_timeout(PROBE_CONNECT_TIMEOUT)
+            .timeout(PROBE_TIMEOUT)
+            .body(())
+            .expect("invalid connectivity probe request");
+
+        let result = match crate::http_client::for_url(&host) {
+            Ok(client) => client.send_async(request).await.map(drop).map_err(anyhow::Error::from),
+            Err(why) => Err(why.into()),
+        };
+
+        match result {
+            Ok(()) => None,
+            Err(why) => {
+                warn!("connectivity check of {} failed: {}", host, why);
+                Some(host)
+            }
+        }
+    });
+
+    let unreachable = future::join_all(probes)
+        .await
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>();
+
+    if unreachable.is_empty() {
+        Ok(())
+    } else {
+        Err(unreachable)
+    }
+}
+
+/// The repository URIs of each apt source enabled on the system.
+pub fn apt_sources() -> Vec<String> {
+    let mut uris = Vec::new();
+
+    if let Ok(content) = fs::read_to_string(SOURCES_LIST) {
+        uris.extend(list_uris(&content).into_iter().map(String::from));
+    }
+
+    let parts = match fs::read_dir(SOURCES_PARTS) {
+        Ok(parts) => parts,
+        Err(why) => {
+            warn!("failed to read {}: {}", SOURCES_PARTS, why);
+            return uris;
+        }
+    };
+
+    for entry in parts.filter_map(Result::ok) {
+        let path = entry.path();
+
+        if let Ok(content) = fs::read_to_string(&path) {
+            uris.extend(source_uris(&path, &content).into_iter().map(String::from));
+        }
+    }
+
+    uris
+}
+
+/// The URIs of the enabled repositories in a sources file, parsed by its extension.
+pub(crate) fn source_uris<'a>(path: &Path, content: &'a str) -> Vec<&'a str> {
+    match extension(path) {
+        Some("list") => list_uris(content),
+        Some("sources") => deb822_uris(content),
+        _ => Vec::new(),
+    }
+}
+
+/// Determines if an error was caused by a loss of connectivity.
+pub fn is_connection_error(why: &(dyn ErrorTrait + 'static)) -> bool {
+    let mut source = Some(why);
+
+    while let Some(why) = source {
+        if let Some(why) = why.downcast_ref::<isahc::Error>() {
+            if why.is_network() || why.kind() == &isahc::error::ErrorKind::Timeout {
+                return true;
+            }
+        }
+
+        if let Some(why) = why.downcast_ref::<io::Error>() {
+            match why.kind() {
+                io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::TimedOut => return true,
+                _ => (),
+            }
+        }
+
+        source = why.source();
+    }
+
+    false
+}
+
+/// Checks if NetworkManager knows that the system is offline.
+///
+/// If NetworkManager is not running, the system is assumed to be online.
+fn network_manager_offline() -> bool {
+    use dbus::blocking::{stdintf::org_freedesktop_dbus::Properties, Connection};
+
+    let connectivity = Connection::new_system().and_then(|bus| {
+        bus.with_proxy(NM_NAME, NM_PATH, Duration::from_secs(1))
+            .get::<u32>(NM_NAME, "Connectivity")
+    });
+
+    match connectivity {
+        Ok(state) => state == NM_CONNECTIVITY_NONE,
+        Err(why) => {
+            info!("unable to get connectivity state from NetworkManager: {}", why);
+            false
+        }
+    }
+}
+
+/// The scheme and authority of a HTTP(S) URL, with the path stripped.
+pub(crate) fn base_url(url: &str) -> Option<String> {
+    let uri = url.parse::<Uri>().ok()?;
+
+    match uri.scheme_str()? {
+        "http" | "https" => Some(fomat!((uri.scheme_str()?) "://" (uri.authority()?) "/")),
+        _ => None,
+    }
+}
+
+fn extension(path: &Path) -> Option<&str> { path.extension().and_then(|ext| ext.to_str()) }
+
+/// Repository URIs in the one-line style of sources list.
+fn list_uris(content: &str) -> Vec<&str> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+
+            match fields.next()? {
+                "deb" | "deb-src" => (),
+                _ => return None,
+            }
+
+            let mut field = fields.next()?;
+
+            // Skip over options, such as `[arch=amd64 signed-by=/path/to/key]`.
+            if field.starts_with('[') {
+                while !field.ends_with(']') {
+                    field = fields.next()?;
+                }
+
+                field = fields.next()?;
+            }
+
+            Some(field)
+        })
+        .collect()
+}
+
+/// Repository URIs in the deb822 style of sources list.
+fn deb822_uris(content: &str) -> Vec<&str> {
+    let mut uris = Vec::new();
+
+    for stanza in content.split("\n\n") {
+        let mut enabled = true;
+        let mut stanza_uris = Vec::new();
+
+        for line in stanza.lines().filter(|line| !line.starts_with('#')) {
+            let mut fields = line.splitn(2, ':');
+            let key = fields.next().unwrap_or("").trim();
+            let value = match fields.next() {
+                Some(value) => value.trim(),
+                None => continue,
+            };
+
+            if key.eq_ignore_ascii_case("Enabled") {
+                enabled = !value.eq_ignore_ascii_case("no");
+            } else if key.eq_ignore_ascii_case("URIs") {
+                stanza_uris.extend(value.split_whitespace());
+            }
+        }
+
+        if enabled {
+            uris.extend(stanza_uris);
+        }
+    }
+
+    uris
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: &str = r#"# deb http://disabled.example.com/ubuntu focal main
+deb http://us.archive.ubuntu.com/ubuntu/ focal main restricted
+deb-src [arch=amd64 signed-by=/usr/share/keyrings/key.gpg] http://apt.pop-os.org/proprietary focal main
+deb [arch=amd64] https://ppa.launchpadcontent.net/system76/pop/ubuntu focal main
+"#;
+
+    const DEB822: &str = r#"X-Repolib-Name: Pop_OS System Sources
+Enabled: yes
+Types: deb deb-src
+URIs: http://us.archive.ubuntu.com/ubuntu/ http://mirror.example.com/ubuntu/
+Suites: focal focal-security
+Components: main restricted universe multiverse
+
+X-Repolib-Name: Disabled
+Enabled: no
+Types: deb
+URIs: http://disabled.example.com/
+Suites: focal
+"#;
+
+    #[test]
+    fn list() {
+        assert_eq!(list_uris(LIST), vec![
+            "http://us.archive.ubuntu.com/ubuntu/",
+            "http://apt.pop-os.org/proprietary",
+            "https://ppa.launchpadcontent.net/system76/pop/ubuntu",
+        ]);
+    }
+
+    #[test]
+    fn deb822() {
+        assert_eq!(deb822_uris(DEB822), vec![
+            "http://us.archive.ubuntu.com/ubuntu/",
+            "http://mirror.example.com/ubuntu/"
+        ]);
+    }
+
+    #[test]
+    fn base_urls() {
+        assert_eq!(
+            base_url("http://us.archive.ubuntu.com/ubuntu/pool/main/a/apt/apt_2.0.deb"),
+            Some("http://us.archive.ubuntu.com/".into())
+        );
+        assert_eq!(
+            base_url("https://mirror.example.com:8443/ubuntu"),
+            Some("https://mirror.example.com:8443/".into())
+        );
+        assert_eq!(base_url("file:///var/local/repo"), None);
+        assert_eq!(base_url("cdrom:[Pop_OS 20.04]/"), None);
+    }
+}
@@ -10,23 +10,69 @@ pub mod methods {
     }
 
     pub const CANCEL: &str = "Cancel";
+    pub const CAPABILITIES: &str = "Capabilities";
     pub const DISMISS_NOTIFICATION: &str = "DismissNotification";
     pub const FETCH_UPDATES: &str = "FetchUpdates";
     pub const FETCH_UPDATES_STATUS: &str = "FetchUpdatesStatus";
+    pub const GET_CHANNEL: &str = "GetChannel";
+    pub const PACKAGE_DIFF: &str = "PackageDiff";
+    pub const PACKAGE_REINSTALL_REMOVED: &str = "PackageReinstallRemoved";
+    pub const PACKAGE_REINSTALL_REMOVED_STATUS: &str = "PackageReinstallRemovedStatus";
     pub const PACKAGE_UPGRADE: &str = "UpgradePackages";
+    pub const RECOVERY_MODE: &str = "RecoveryMode";
+    pub const RECOVERY_MODE_SET: &str = "RecoveryModeSet";
+    pub const RECOVERY_MODE_UNSET: &str = "RecoveryModeUnset";
     pub const RECOVERY_UPGRADE_FILE: &str = "RecoveryUpgradeFile";
     pub const RECOVERY_UPGRADE_RELEASE: &str = "RecoveryUpgradeRelease";
     pub const RECOVERY_UPGRADE_RELEASE_STATUS: &str = "RecoveryUpgradeReleaseStatus";
+    pub const RECOVERY_VERIFY: &str = "RecoveryVerify";
+    pub const RECOVERY_VERIFY_STATUS: &str = "RecoveryVerifyStatus";
     pub const RECOVERY_VERSION: &str = "RecoveryVersion";
+    pub const REFRESH_DISCARD: &str = "RefreshDiscard";
     pub const REFRESH_OS: &str = "RefreshOS";
+    pub const REFRESH_PRESERVED: &str = "RefreshPreserved";
+    pub const REFRESH_RESTORE: &str = "RefreshRestore";
+    pub const REFRESH_RESTORE_STATUS: &str = "RefreshRestoreStatus";
     pub const RELEASE_CHECK: &str = "ReleaseCheck";
     pub const RELEASE_UPGRADE: &str = "ReleaseUpgrade";
     pub const RELEASE_UPGRADE_FINALIZE: &str = "ReleaseUpgradeFinalize";
     pub const RELEASE_UPGRADE_STATUS: &str = "ReleaseUpgradeStatus";
     pub const RELEASE_REPAIR: &str = "ReleaseRepair";
     pub const RESET: &str = "Reset";
+    pub const SET_CHANNEL: &str = "SetChannel";
     pub const STATUS: &str = "Status";
     pub const UPDATE_CHECK: &str = "UpdateCheck";
+    pub const UPGRADE_REPORT: &str = "UpgradeReport";
+    pub const UPGRADE_REPORT_DISMISS: &str = "UpgradeReportDismiss";
+    pub const VERSION: &str = "Version";
+}
+
+/// Optional features which clients may query for with the `Capabilities` method.
+pub mod features {
+    pub const CHANNELS: &str = "channels";
+    pub const PACKAGE_DIFF: &str = "package-diff";
+    pub const RECOVERY_MODES: &str = "recovery-modes";
+    pub const RECOVERY_SYNC_PROGRESS: &str = "recovery-sync-progress";
+    pub const RECOVERY_UPGRADE: &str = "recovery-upgrade";
+    pub const RECOVERY_VERIFY: &str = "recovery-verify";
+    pub const REFRESH_OS: &str = "refresh-os";
+    pub const REFRESH_RESTORE: &str = "refresh-restore";
+    pub const RELEASE_REPAIR: &str = "release-repair";
+    pub const UPGRADE_REPORT: &str = "upgrade-report";
+
+    /// All features supported by this daemon.
+    pub const ALL: &[&str] = &[
+        CHANNELS,
+        PACKAGE_DIFF,
+        RECOVERY_MODES,
+        RECOVERY_SYNC_PROGRESS,
+        RECOVERY_UPGRADE,
+        RECOVERY_VERIFY,
+        REFRESH_OS,
+        REFRESH_RESTORE,
+        RELEASE_REPAIR,
+        UPGRADE_REPORT,
+    ];
 }
 
 mod error;
@@ -39,16 +85,25 @@ pub use self::{
 };
 
 use crate::{
+    config::{self, Config},
+    error_code::{ErrorCoded, ErrorReport},
     misc::{self, format_error},
     recovery::{
-        self, RecoveryError, RecoveryVersion, RecoveryVersionError,
+        self, RecoveryError, RecoveryIntegrity, RecoveryMode, RecoveryVersion,
+        RecoveryVersionError,
         ReleaseFlags as RecoveryReleaseFlags, UpgradeMethod as RecoveryUpgradeMethod,
     },
     release::{
-        self, FetchEvent, RefreshOp, ReleaseError, ReleaseStatus,
+        self,
+        manifest::{self, PackageChange, PackageDiff, Snapshot},
+        preserve::Preserved,
+        verify::UpgradeReport,
+        FetchEvent, RefreshOp, ReleaseError, ReleaseStatus, UpgradeEvent,
         UpgradeMethod as ReleaseUpgradeMethod,
     },
-    sighandler, DBUS_IFACE, DBUS_NAME, DBUS_PATH, RESTART_SCHEDULED,
+    release_api::{Channel, Release},
+    sighandler, DBUS_IFACE, DBUS_INTERFACE_MIN, DBUS_INTERFACE_VERSION, DBUS_NAME, DBUS_PATH,
+    RESTART_SCHEDULED,
 };
 
 use anyhow::Context as AnyhowContext;
@@ -66,9 +121,10 @@ use futures::prelude::*;
 use logind_dbus::LoginManager;
 use num_traits::FromPrimitive;
 use std::{
+    cell::Cell,
     collections::{HashMap, HashSet},
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -82,8 +138,11 @@ pub const INSTALL_DATE: &str = "/usr/lib/pop-upgrade/install_date";
 pub enum Event {
     Cancel,
     FetchUpdates { apt_uris: HashSet<AptRequest>, download_only: bool },
+    PackageReinstallRemoved,
     PackageUpgrade,
     RecoveryUpgrade(RecoveryUpgradeMethod),
+    RecoveryVerify,
+    RefreshRestore,
     ReleaseUpgrade { how: ReleaseUpgradeMethod, from: String, to: String },
 }
 
@@ -93,14 +152,24 @@ pub enum FgEvent {
 }
 
 pub struct LastKnown {
-    fetch:            Result<(), ReleaseError>,
-    recovery_upgrade: Result<(), RecoveryError>,
-    release_upgrade:  Result<(), ReleaseError>,
+    fetch:             Result<(), ReleaseError>,
+    package_reinstall: Result<(), ReleaseError>,
+    recovery_upgrade:  Result<(), RecoveryError>,
+    recovery_verify:   Result<(), RecoveryError>,
+    refresh_restore:   Result<(), ReleaseError>,
+    release_upgrade:   Result<(), ReleaseError>,
 }
 
 impl Default for LastKnown {
     fn default() -> Self {
-        Self { fetch: Ok(()), recovery_upgrade: Ok(()), release_upgrade: Ok(()) }
+        Self {
+            fetch:             Ok(()),
+            package_reinstall: Ok(()),
+            recovery_upgrade:  Ok(()),
+            recovery_verify:   Ok(()),
+            refresh_restore:   Ok(()),
+            release_upgrade:   Ok(()),
+        }
     }
 }
 
@@ -221,7 +290,9 @@ impl Daemon {
                                     if download_only {
                                         Ok(())
                                     } else {
-                                        (async {
+                                        Snapshot::record("package upgrade").await;
+
+                                        let result = (async {
                                             info!("performing upgrade");
                                             let (mut child, events) = AptGet::new()
                                                 .noninteractive()
@@ -240,20 +311,41 @@ impl Daemon {
                                             info!("completed apt upgrade");
 
                                             child.status().await.map_result().map_err(ReleaseError::Upgrade)
-                                        }).await
+                                        }).await;
+
+                                        let _ = PackageDiff::complete().await;
+                                        result
                                     }
                                 }
                                 Err(why) => Err(why)
                             };
 
+                            if let Err(ReleaseError::NoConnection(ref hosts)) = result {
+                                let _ = dbus_tx.send(SignalEvent::NoConnection(hosts.clone()));
+                            }
+
                             let _ = dbus_tx.send(SignalEvent::FetchResult(result));
                         }
 
+                        Event::PackageReinstallRemoved => {
+                            info!("reinstalling packages removed by the last upgrade");
+
+                            let result = manifest::reinstall_removed()
+                                .await
+                                .map_err(ReleaseError::ReinstallRemoved);
+
+                            let _ = dbus_tx.send(SignalEvent::PackageReinstallResult(result));
+                        }
+
                         Event::PackageUpgrade => {
                             info!("upgrading packages");
+                            Snapshot::record("package upgrade").await;
+
                             let _ = runtime.package_upgrade(|event| {
                                 let _ = dbus_tx.send(SignalEvent::Upgrade(event));
-                            });
+                            }).await;
+
+                            let _ = PackageDiff::complete().await;
                         }
 
                         Event::RecoveryUpgrade(action) => {
@@ -271,36 +363,143 @@ impl Daemon {
                                     let _ =
                                         dbus_tx.send(SignalEvent::RecoveryUpgradeEvent(status));
                                 }),
+                                enclose!((dbus_tx) move |progress| {
+                                    let _ =
+                                        dbus_tx.send(SignalEvent::RecoverySyncProgress(progress));
+                                }),
                             ).await;
 
                             let _ = dbus_tx.send(SignalEvent::RecoveryUpgradeResult(result));
                             processing = false;
                         }
 
+                        Event::RecoveryVerify => {
+                            info!("verifying the recovery partition");
+
+                            // Signal the progress of each percent, rather than each file.
+                            let percent = Cell::new(None);
+
+                            let progress = |checked: u32, total: u32| {
+                                prog_state.store(
+                                    (u64::from(checked), u64::from(total)),
+                                    Ordering::SeqCst,
+                                );
+
+                                let current = Some(checked * 100 / total.max(1));
+                                if percent.replace(current) != current {
+                                    let _ = dbus_tx
+                                        .send(SignalEvent::RecoveryVerifyProgress(checked, total));
+                                }
+                            };
+
+                            let result =
+                                recovery::verify_integrity(Path::new("/recovery"), progress).await;
+
+                            prog_state.store((0, 0), Ordering::SeqCst);
+
+                            let _ = dbus_tx.send(SignalEvent::RecoveryVerifyResult(result));
+                        }
+
+                        Event::RefreshRestore => {
+                            info!("restoring the applications preserved for the refresh");
+
+                            let result = release::preserve::restore()
+                                .await
+                                .map_err(ReleaseError::RefreshRestore);
+
+                            let _ = dbus_tx.send(SignalEvent::RefreshRestoreResult(result));
+                        }
+
                         Event::ReleaseUpgrade { how, from, to } => {
                             info!(
                                 "attempting release upgrade, using a {}",
                                 <&'static str>::from(how)
                             );
 
+                            // A live upgrade must not be interrupted by a shutdown.
+                            let _shutdown_lock = match how {
+                                ReleaseUpgradeMethod::Live => logind.as_mut().and_then(|logind| {
+                                    match logind.connect().inhibit(
+                                        "shutdown",
+                                        "pop-upgrade",
+                                        "installing the new release",
+                                        "block",
+                                    ) {
+                                        Ok(lock) => Some(lock),
+                                        Err(why) => {
+                                            error!("failed to inhibit shutdown: {}", why);
+                                            None
+                                        }
+                                    }
+                                }),
+                                _ => None,
+                            };
+
                             let progress = enclose!((dbus_tx, sub_status) move |event| {
                                 let _ = dbus_tx.send(SignalEvent::ReleaseUpgradeEvent(event));
                                 sub_status.store(event as u8, Ordering::SeqCst);
                             });
 
-                            let result = runtime.upgrade(
-                                how,
-                                &from,
-                                &to,
-                                &progress,
-                                fetch_closure.clone(),
-                                &|event| {
-                                    let _ = dbus_tx.send(SignalEvent::Upgrade(event));
-                                },
-                            ).await;
+                            // The recovery partition must be upgraded to the new release first.
+                            let result = match how {
+                                ReleaseUpgradeMethod::Recovery => (async {
+                                    // Fail before the ISO is fetched if the upgrade cannot proceed.
+                                    release::upgrade_preflight(how).await?;
+
+                                    progress(UpgradeEvent::AttemptingRecovery);
+
+                                    let action = RecoveryUpgradeMethod::FromRelease {
+                                        version: Some(to.clone()),
+                                        arch: None,
+                                        flags: RecoveryReleaseFlags::empty(),
+                                    };
+
+                                    recovery::recovery(
+                                        &|| (*cancel_process)(),
+                                        &action,
+                                        enclose!((dbus_tx, prog_state) move |p, t| {
+                                            prog_state.store((p, t), Ordering::SeqCst);
+                                            let _ = dbus_tx
+                                                .send(SignalEvent::RecoveryDownloadProgress(p, t));
+                                        }),
+                                        enclose!((dbus_tx) move |status| {
+                                            let _ = dbus_tx
+                                                .send(SignalEvent::RecoveryUpgradeEvent(status));
+                                        }),
+                                        enclose!((dbus_tx) move |progress| {
+                                            let _ = dbus_tx
+                                                .send(SignalEvent::RecoverySyncProgress(progress));
+                                        }),
+                                    ).await.map_err(ReleaseError::RecoveryUpgrade)
+                                }).await,
+                                _ => Ok(()),
+                            };
+
+                            let result = match result {
+                                Ok(()) => runtime.upgrade(
+                                    how,
+                                    &from,
+                                    &to,
+                                    &progress,
+                                    fetch_closure.clone(),
+                                    &|event| {
+                                        let _ = dbus_tx.send(SignalEvent::Upgrade(event));
+                                    },
+                                ).await,
+                                Err(why) => Err(why),
+                            };
 
                             let _ = AptMark::new().unhold(&["pop-upgrade"]).await;
 
+                            // Packages may have been removed before the upgrade failed.
+                            if result.is_err() {
+                                let _ = PackageDiff::complete().await;
+                            }
+
+                            if let Err(ReleaseError::NoConnection(ref hosts)) = result {
+                                let _ = dbus_tx.send(SignalEvent::NoConnection(hosts.clone()));
+                            }
+
                             let _ = fg_tx.send(FgEvent::SetUpgradeState(
                                 result,
                                 how,
@@ -317,13 +516,20 @@ impl Daemon {
             })),
         );
 
+        let mut last_known = LastKnown::default();
+
+        // Report the outcome of the last release upgrade, until it has been dismissed.
+        if let Some(report) = UpgradeReport::load().filter(|report| !report.dismissed) {
+            last_known.release_upgrade = report.into_result();
+        }
+
         Ok(Daemon {
             cancel,
             dbus_rx,
             event_tx,
             fetching_state: prog_state,
             fg_rx,
-            last_known: Default::default(),
+            last_known,
             release_upgrade: None,
             status,
             sub_status,
@@ -340,6 +546,27 @@ impl Daemon {
             warn!("failure restoring previous boot entry: {}", why);
         }
 
+        // Verify an offline upgrade which was applied on this boot.
+        if let Some(result) = release::offline::OfflineResult::take() {
+            let error = result.error.map(String::from);
+            let report =
+                async_io::block_on(UpgradeReport::generate(&result.from, &result.to, error));
+
+            info!("upgrade report: {:?}", report);
+
+            if let Err(why) = report.save() {
+                error!("failed to save the upgrade report: {}", why);
+            }
+        }
+
+        // Describe the package changes of an upgrade which completed since the last start.
+        async_io::block_on(PackageDiff::complete_pending());
+
+        // Offer to reinstall the applications preserved before a refresh of the OS.
+        if let Err(why) = async_io::block_on(release::preserve::first_boot()) {
+            error!("failed to prepare the applications preserved for the refresh: {:?}", why);
+        }
+
         let daemon = Self::new()?;
 
         let connection = Connection::new_system().map_err(DaemonError::PrivateConnection)?;
@@ -352,7 +579,7 @@ impl Daemon {
 
         let iface_token = cr.register(DBUS_IFACE, |b| {
             let _fetch_result =
-                b.signal::<(u8, String), _>(signals::PACKAGE_FETCH_RESULT, ("status", "why"));
+                b.signal::<ResultReply, _>(signals::PACKAGE_FETCH_RESULT, RESULT_REPLY);
 
             let _fetching_package =
                 b.signal::<(String,), _>(signals::PACKAGE_FETCHING, ("package",));
@@ -362,20 +589,56 @@ impl Daemon {
                 ("package", "completed", "total"),
             );
 
-            let _no_connection = b.signal::<(), _>(signals::NO_CONNECTION, ());
+            let _package_reinstall_result = b.signal::<ListResultReply, _>(
+                signals::PACKAGE_REINSTALL_RESULT,
+                ("status", "why", "category", "code", "context", "causes", "reinstalled"),
+            );
+
+            let _no_connection =
+                b.signal::<(Vec<String>,), _>(signals::NO_CONNECTION, ("hosts",));
 
             let _recovery_download_progress = b
                 .signal::<(u64, u64), _>(signals::RECOVERY_DOWNLOAD_PROGRESS, ("current", "total"));
 
             let _recovery_event = b.signal::<(u8,), _>(signals::RECOVERY_EVENT, ("event",));
 
-            let _recovery_result =
-                b.signal::<(u8, String), _>(signals::RECOVERY_RESULT, ("result", "why"));
+            let _recovery_result = b.signal::<ResultReply, _>(signals::RECOVERY_RESULT, RESULT_REPLY);
+
+            let _recovery_sync_progress = b.signal::<(String, u32, u32, u64, u64), _>(
+                signals::RECOVERY_SYNC_PROGRESS,
+                ("file", "files", "files_total", "current", "total"),
+            );
+
+            let _recovery_verify_progress = b.signal::<(u32, u32), _>(
+                signals::RECOVERY_VERIFY_PROGRESS,
+                ("checked", "total"),
+            );
+
+            let _recovery_verify_result = b.signal::<VerifyResultReply, _>(
+                signals::RECOVERY_VERIFY_RESULT,
+                (
+                    "status",
+                    "why",
+                    "category",
+                    "code",
+                    "context",
+                    "causes",
+                    "unverifiable",
+                    "checked",
+                    "missing",
+                    "corrupt",
+                ),
+            );
+
+            let _refresh_restore_result = b.signal::<ListResultReply, _>(
+                signals::REFRESH_RESTORE_RESULT,
+                ("status", "why", "category", "code", "context", "causes", "unavailable"),
+            );
 
             let _release_event = b.signal::<(u8,), _>(signals::RELEASE_EVENT, ("event",));
 
             let _release_result =
-                b.signal::<(u8, String), _>(signals::RELEASE_RESULT, ("result", "why"));
+                b.signal::<ResultReply, _>(signals::RELEASE_RESULT, RESULT_REPLY);
 
             let _repo_compat_error = b.signal::<(Vec<String>, Vec<(String, String)>), _>(
                 signals::REPO_COMPAT_ERROR,
@@ -395,6 +658,21 @@ impl Daemon {
                 },
             );
 
+            b.method(
+                methods::CAPABILITIES,
+                (),
+                ("upgrade_methods", "features"),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    let upgrade_methods =
+                        ReleaseUpgradeMethod::ALL.iter().map(|&how| how as u8).collect::<Vec<u8>>();
+
+                    let features =
+                        features::ALL.iter().map(|&feature| String::from(feature)).collect();
+
+                    Ok((upgrade_methods, features))
+                },
+            );
+
             b.method(
                 methods::DISMISS_NOTIFICATION,
                 ("dismiss",),
@@ -444,12 +722,21 @@ impl Daemon {
             b.method(
                 methods::FETCH_UPDATES_STATUS,
                 (),
-                ("status", "why"),
+                RESULT_REPLY,
                 |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
                     Ok(result_signal(daemon.last_known.fetch.as_ref()))
                 },
             );
 
+            b.method(
+                methods::GET_CHANNEL,
+                (),
+                ("channel",),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    Ok((Config::load().channel as u8,))
+                },
+            );
+
             b.method(
                 methods::PACKAGE_UPGRADE,
                 (),
@@ -468,6 +755,92 @@ impl Daemon {
                 },
             );
 
+            b.method(
+                methods::PACKAGE_DIFF,
+                (),
+                PACKAGE_DIFF_REPLY,
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    let diff = PackageDiff::load();
+                    let available = diff.is_some();
+                    let diff = diff.unwrap_or_default();
+
+                    Ok((
+                        available,
+                        diff.operation,
+                        package_changes(diff.added),
+                        package_changes(diff.removed),
+                        package_changes(diff.upgraded),
+                        package_changes(diff.downgraded),
+                        diff.manual_removed,
+                    ))
+                },
+            );
+
+            b.method(
+                methods::PACKAGE_REINSTALL_REMOVED,
+                (),
+                (),
+                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
+                    daemon.set_status(DaemonStatus::PackageReinstall, move |daemon, active| {
+                        if !active {
+                            daemon
+                                .package_reinstall_removed()
+                                .map_err(|ref why| format_error(why.as_ref()))
+                                .map_err(|why| MethodErr::failed(&why))?;
+                        }
+
+                        Ok(())
+                    })
+                },
+            );
+
+            b.method(
+                methods::PACKAGE_REINSTALL_REMOVED_STATUS,
+                (),
+                RESULT_REPLY,
+                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
+                    Ok(result_signal(daemon.last_known.package_reinstall.as_ref()))
+                },
+            );
+
+            b.method(
+                methods::RECOVERY_MODE,
+                (),
+                ("mode",),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    release::recovery_mode()
+                        .map(|mode| (mode.map_or(0, |mode| mode as u8),))
+                        .map_err(|ref why| format_error(why))
+                        .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
+            b.method(
+                methods::RECOVERY_MODE_SET,
+                ("mode",),
+                (),
+                |_ctx: &mut Context, _daemon: &mut Daemon, (mode,): (u8,)| {
+                    let mode = RecoveryMode::from_u8(mode)
+                        .ok_or("recovery mode value is out of range")
+                        .map_err(|why| MethodErr::failed(&why))?;
+
+                    release::recovery_mode_set(mode)
+                        .map_err(|ref why| format_error(why))
+                        .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
+            b.method(
+                methods::RECOVERY_MODE_UNSET,
+                (),
+                (),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    release::recovery_mode_unset()
+                        .map_err(|ref why| format_error(why))
+                        .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
             b.method(
                 methods::RECOVERY_UPGRADE_FILE,
                 ("path",),
@@ -509,12 +882,43 @@ impl Daemon {
             b.method(
                 methods::RECOVERY_UPGRADE_RELEASE_STATUS,
                 (),
-                ("status", "why"),
+                RESULT_REPLY,
                 |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
                     Ok(result_signal(daemon.last_known.recovery_upgrade.as_ref()))
                 },
             );
 
+            b.method(
+                methods::RECOVERY_VERIFY,
+                (),
+                (),
+                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
+                    if daemon.status.load(Ordering::SeqCst) == DaemonStatus::RecoveryUpgrade {
+                        return Err(MethodErr::failed(&"the recovery partition is being upgraded"));
+                    }
+
+                    daemon.set_status(DaemonStatus::RecoveryVerify, move |daemon, active| {
+                        if !active {
+                            daemon
+                                .recovery_verify()
+                                .map_err(|ref why| format_error(why.as_ref()))
+                                .map_err(|why| MethodErr::failed(&why))?;
+                        }
+
+                        Ok(())
+                    })
+                },
+            );
+
+            b.method(
+                methods::RECOVERY_VERIFY_STATUS,
+                (),
+                RESULT_REPLY,
+                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
+                    Ok(result_signal(daemon.last_known.recovery_verify.as_ref()))
+                },
+            );
+
             b.method(
                 methods::RECOVERY_VERSION,
                 (),
@@ -546,21 +950,82 @@ impl Daemon {
                 },
             );
 
+            b.method(
+                methods::REFRESH_DISCARD,
+                (),
+                (),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    info!("discarding the applications preserved for the refresh");
+                    release::preserve::discard();
+                    Ok(())
+                },
+            );
+
+            b.method(
+                methods::REFRESH_PRESERVED,
+                (),
+                ("available", "packages", "sources", "flatpaks"),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    let preserved = Preserved::pending();
+                    let available = preserved.is_some();
+                    let preserved = preserved.unwrap_or_default();
+
+                    let flatpaks = preserved
+                        .flatpaks
+                        .into_iter()
+                        .map(|flatpak| flatpak.application)
+                        .collect::<Vec<_>>();
+
+                    Ok((available, preserved.packages, preserved.sources, flatpaks))
+                },
+            );
+
+            b.method(
+                methods::REFRESH_RESTORE,
+                (),
+                (),
+                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
+                    daemon.set_status(DaemonStatus::RestoringApplications, move |daemon, active| {
+                        if !active {
+                            daemon
+                                .refresh_restore()
+                                .map_err(|ref why| format_error(why.as_ref()))
+                                .map_err(|why| MethodErr::failed(&why))?;
+                        }
+
+                        Ok(())
+                    })
+                },
+            );
+
+            b.method(
+                methods::REFRESH_RESTORE_STATUS,
+                (),
+                RESULT_REPLY,
+                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
+                    Ok(result_signal(daemon.last_known.refresh_restore.as_ref()))
+                },
+            );
+
             b.method(
                 methods::RELEASE_CHECK,
                 ("development",),
-                ("current", "next", "build", "urgent", "is_lts"),
+                ("current", "next", "build", "urgent", "is_lts", "stale"),
                 |_ctx: &mut Context, daemon: &mut Daemon, (development,): (bool,)| {
                     daemon
                         .release_check(development)
                         .map(|status| {
                             let is_lts = status.is_lts();
+                            let mut stale = status.stale;
                             let mut urgent = -1;
 
+                            let channel = Config::load().channel;
+
                             if let Ok(release) =
-                                crate::release_api::Release::get_release(status.current, "nvidia")
+                                Release::get_release(status.current, "nvidia", channel)
                             {
                                 urgent = release.build as i16;
+                                stale |= release.stale;
                             }
 
                             if status.current == "20.10" {
@@ -573,6 +1038,7 @@ impl Daemon {
                                 status.build.status_code(),
                                 urgent,
                                 is_lts,
+                                stale,
                             )
                         })
                         .map_err(|why| MethodErr::failed(&why))
@@ -609,12 +1075,43 @@ impl Daemon {
             b.method(
                 methods::RELEASE_UPGRADE_STATUS,
                 (),
-                ("status", "why"),
+                RESULT_REPLY,
                 |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
                     Ok(result_signal(daemon.last_known.release_upgrade.as_ref()))
                 },
             );
 
+            b.method(
+                methods::UPGRADE_REPORT,
+                (),
+                UPGRADE_REPORT_REPLY,
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    let report = UpgradeReport::load().filter(|report| !report.dismissed);
+                    let available = report.is_some();
+                    let report = report.unwrap_or_default();
+
+                    Ok((
+                        available,
+                        report.from,
+                        report.to,
+                        report.detected,
+                        report.error.unwrap_or_default(),
+                        report.missing,
+                        report.unconfigured,
+                        report.staged,
+                    ))
+                },
+            );
+
+            b.method(
+                methods::UPGRADE_REPORT_DISMISS,
+                (),
+                (),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    UpgradeReport::dismiss().map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
             b.method(
                 methods::RELEASE_REPAIR,
                 (),
@@ -635,6 +1132,21 @@ impl Daemon {
                 },
             );
 
+            b.method(
+                methods::SET_CHANNEL,
+                ("channel",),
+                (),
+                |_ctx: &mut Context, _daemon: &mut Daemon, (channel,): (u8,)| {
+                    let channel = Channel::from_u8(channel)
+                        .ok_or("channel value is out of range")
+                        .map_err(|why| MethodErr::failed(&why))?;
+
+                    config::set_channel(channel)
+                        .map_err(|why| fomat!("failed to set the release channel: "(why)))
+                        .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
             b.method(
                 methods::STATUS,
                 (),
@@ -655,6 +1167,16 @@ impl Daemon {
                     Ok((async_io::block_on(daemon.update_and_restart()),))
                 },
             );
+
+            b.method(
+                methods::VERSION,
+                (),
+                ("version", "interface", "interface_min"),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    let version = String::from(env!("CARGO_PKG_VERSION"));
+                    Ok((version, DBUS_INTERFACE_VERSION, DBUS_INTERFACE_MIN))
+                },
+            );
         });
 
         let (fg_receiver, receiver) = { (daemon.fg_rx.clone(), daemon.dbus_rx.clone()) };
@@ -728,6 +1250,11 @@ impl Daemon {
                                 daemon.release_upgrade = Some(state);
                             }
 
+                            Self::send_signal_message(
+                                &connection,
+                                result_message(signals::RELEASE_RESULT, result.as_ref()),
+                            );
+
                             daemon.last_known.release_upgrade = result;
                         }
                     }
@@ -738,8 +1265,12 @@ impl Daemon {
                         match &dbus_event {
                             SignalEvent::Fetched(..)
                             | SignalEvent::Fetching(_)
+                            | SignalEvent::NoConnection(_)
+                            | SignalEvent::PackageReinstallResult(_)
                             | SignalEvent::RecoveryUpgradeEvent(_)
                             | SignalEvent::RecoveryUpgradeResult(_)
+                            | SignalEvent::RecoveryVerifyResult(_)
+                            | SignalEvent::RefreshRestoreResult(_)
                             | SignalEvent::ReleaseUpgradeEvent(_)
                             | SignalEvent::Upgrade(_) => info!("{}", dbus_event),
                             _ => (),
@@ -747,9 +1278,8 @@ impl Daemon {
 
                         match dbus_event {
                             SignalEvent::FetchResult(result) => {
-                                let (status, why) = result_signal(result.as_ref());
-                                let message = Self::signal_message(signals::PACKAGE_FETCH_RESULT)
-                                    .append2(status, why);
+                                let message =
+                                    result_message(signals::PACKAGE_FETCH_RESULT, result.as_ref());
 
                                 daemon.last_known.fetch = result;
                                 message
@@ -762,8 +1292,23 @@ impl Daemon {
                                 Self::signal_message(signals::PACKAGE_FETCHING)
                                     .append1(name.as_str())
                             }
-                            SignalEvent::NoConnection => {
-                                Self::signal_message(signals::NO_CONNECTION)
+                            SignalEvent::NoConnection(hosts) => {
+                                Self::signal_message(signals::NO_CONNECTION).append1(hosts)
+                            }
+                            SignalEvent::PackageReinstallResult(result) => {
+                                let (result, reinstalled) = match result {
+                                    Ok(reinstalled) => (Ok(()), reinstalled),
+                                    Err(why) => (Err(why), Vec::new()),
+                                };
+
+                                let message = result_message(
+                                    signals::PACKAGE_REINSTALL_RESULT,
+                                    result.as_ref(),
+                                )
+                                .append1(reinstalled);
+
+                                daemon.last_known.package_reinstall = result;
+                                message
                             }
                             SignalEvent::RecoveryDownloadProgress(progress, total) => {
                                 Self::signal_message(signals::RECOVERY_DOWNLOAD_PROGRESS)
@@ -773,13 +1318,48 @@ impl Daemon {
                                 Self::signal_message(signals::RECOVERY_EVENT).append1(event as u8)
                             }
                             SignalEvent::RecoveryUpgradeResult(result) => {
-                                let (status, why) = result_signal(result.as_ref());
-                                let message = Self::signal_message(signals::RECOVERY_RESULT)
-                                    .append2(status, why);
+                                let message =
+                                    result_message(signals::RECOVERY_RESULT, result.as_ref());
 
                                 daemon.last_known.recovery_upgrade = result;
                                 message
                             }
+                            SignalEvent::RecoverySyncProgress(progress) => {
+                                Self::signal_message(signals::RECOVERY_SYNC_PROGRESS)
+                                    .append3(progress.file, progress.files, progress.files_total)
+                                    .append2(progress.current, progress.total)
+                            }
+                            SignalEvent::RecoveryVerifyProgress(checked, total) => {
+                                Self::signal_message(signals::RECOVERY_VERIFY_PROGRESS)
+                                    .append2(checked, total)
+                            }
+                            SignalEvent::RecoveryVerifyResult(result) => {
+                                let (result, integrity) = match result {
+                                    Ok(integrity) => (Ok(()), integrity),
+                                    Err(why) => (Err(why), RecoveryIntegrity::default()),
+                                };
+
+                                let message =
+                                    result_message(signals::RECOVERY_VERIFY_RESULT, result.as_ref())
+                                        .append2(integrity.unverifiable, integrity.checked)
+                                        .append2(integrity.missing, integrity.corrupt);
+
+                                daemon.last_known.recovery_verify = result;
+                                message
+                            }
+                            SignalEvent::RefreshRestoreResult(result) => {
+                                let (result, unavailable) = match result {
+                                    Ok(unavailable) => (Ok(()), unavailable),
+                                    Err(why) => (Err(why), Vec::new()),
+                                };
+
+                                let message =
+                                    result_message(signals::REFRESH_RESTORE_RESULT, result.as_ref())
+                                        .append1(unavailable);
+
+                                daemon.last_known.refresh_restore = result;
+                                message
+                            }
                             SignalEvent::ReleaseUpgradeEvent(event) => {
                                 Self::signal_message(signals::RELEASE_EVENT).append1(event as u8)
                             }
@@ -841,6 +1421,12 @@ impl Daemon {
         Ok((true, npackages))
     }
 
+    fn package_reinstall_removed(&mut self) -> anyhow::Result<()> {
+        info!("reinstalling the packages removed by the last upgrade");
+
+        self.submit_event(Event::PackageReinstallRemoved)
+    }
+
     fn package_upgrade(&mut self) -> anyhow::Result<()> {
         info!("upgrading packages for the release");
 
@@ -879,6 +1465,12 @@ impl Daemon {
         self.submit_event(event)
     }
 
+    fn recovery_verify(&mut self) -> anyhow::Result<()> {
+        info!("verifying the files of the recovery partition");
+
+        self.submit_event(Event::RecoveryVerify)
+    }
+
     fn recovery_version(&mut self) -> Result<RecoveryVersion, String> {
         info!("checking recovery version");
 
@@ -900,10 +1492,20 @@ impl Daemon {
         crate::release::refresh_os(flag).map_err(|ref why| format_error(why))
     }
 
+    fn refresh_restore(&mut self) -> anyhow::Result<()> {
+        if Preserved::pending().is_none() {
+            return Err(anyhow::anyhow!("no applications were preserved for a refresh"));
+        }
+
+        self.submit_event(Event::RefreshRestore)
+    }
+
     fn release_check(&self, development: bool) -> Result<ReleaseStatus, String> {
         info!("performing a release check");
 
-        let status = release::check::next(development).map_err(|ref why| format_error(why))?;
+        let channel = Config::load().channel;
+        let status =
+            release::check::next(channel, development).map_err(|ref why| format_error(why))?;
 
         let mut buffer = String::new();
 
@@ -1023,7 +1625,45 @@ pub async fn upgrade_required() -> anyhow::Result<bool> {
     Ok(false)
 }
 
-pub fn result_signal<E: ::std::fmt::Display>(result: Result<&(), &E>) -> (u8, String) {
+/// The status, description, error category, error code, error context, and error causes of a
+/// result.
+pub type ResultReply = (u8, String, u8, u16, HashMap<String, String>, Vec<String>);
+
+const RESULT_REPLY: (&str, &str, &str, &str, &str, &str) =
+    ("status", "why", "category", "code", "context", "causes");
+
+/// A result reply, followed by the files of the recovery partition which failed verification.
+pub type VerifyResultReply = (
+    u8,
+    String,
+    u8,
+    u16,
+    HashMap<String, String>,
+    Vec<String>,
+    bool,
+    u32,
+    Vec<String>,
+    Vec<String>,
+);
+
+/// A result reply, followed by the packages or applications that the operation applied to.
+pub type ListResultReply =
+    (u8, String, u8, u16, HashMap<String, String>, Vec<String>, Vec<String>);
+
+/// The name, previous version, and new version of each changed package.
+pub type PackageChanges = Vec<(String, String, String)>;
+
+const PACKAGE_DIFF_REPLY: (&str, &str, &str, &str, &str, &str, &str) =
+    ("available", "operation", "added", "removed", "upgraded", "downgraded", "manual_removed");
+
+fn package_changes(changes: Vec<PackageChange>) -> PackageChanges {
+    changes.into_iter().map(|change| (change.name, change.from, change.to)).collect()
+}
+
+const UPGRADE_REPORT_REPLY: (&str, &str, &str, &str, &str, &str, &str, &str) =
+    ("available", "from", "to", "detected", "error", "missing", "unconfigured", "staged");
+
+pub fn result_signal<E: ErrorCoded>(result: Result<&(), &E>) -> ResultReply {
     let status = match result {
         Ok(_) => 0u8,
         Err(_) => 1,
@@ -1031,7 +1671,15 @@ pub fn result_signal<E: ::std::fmt::Display>(result: Result<&(), &E>) -> (u8, St
 
     let why: String = result.err().map(|why| fomat!((why))).unwrap_or_default();
 
-    (status, why)
+    let (category, code, context, causes) =
+        result.err().map(ErrorReport::new).unwrap_or_default().into_dbus();
+
+    (status, why, category, code, context, causes)
+}
+
+fn result_message<E: ErrorCoded>(name: &'static str, result: Result<&(), &E>) -> Message {
+    let (status, why, category, code, context, causes) = result_signal(result);
+    Daemon::signal_message(name).append3(status, why, category).append3(code, context, causes)
 }
 
 // Creates the notification dismissal file.
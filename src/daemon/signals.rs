@@ -1,5 +1,5 @@
 use crate::{
-    recovery::{RecoveryError, RecoveryEvent},
+    recovery::{RecoveryError, RecoveryEvent, RecoveryIntegrity, SyncProgress},
     release::{ReleaseError, UpgradeEvent},
 };
 use apt_cmd::AptUpgradeEvent;
@@ -10,15 +10,22 @@ pub const PACKAGE_FETCH_RESULT: &str = "PackageFetchResult";
 pub const PACKAGE_FETCHING: &str = "PackageFetching";
 pub const PACKAGE_FETCHED: &str = "PackageFetched";
 
+pub const PACKAGE_REINSTALL_RESULT: &str = "PackageReinstallResult";
+
 pub const PACKAGE_UPGRADE: &str = "PackageUpgrade";
 
 pub const RECOVERY_DOWNLOAD_PROGRESS: &str = "RecoveryDownloadProgress";
 pub const RECOVERY_EVENT: &str = "RecoveryUpgradeEvent";
 pub const RECOVERY_RESULT: &str = "RecoveryUpgradeResult";
+pub const RECOVERY_SYNC_PROGRESS: &str = "RecoverySyncProgress";
+pub const RECOVERY_VERIFY_PROGRESS: &str = "RecoveryVerifyProgress";
+pub const RECOVERY_VERIFY_RESULT: &str = "RecoveryVerifyResult";
 
 pub const RELEASE_EVENT: &str = "ReleaseUpgradeEvent";
 pub const RELEASE_RESULT: &str = "ReleaseUpgradeResult";
 
+pub const REFRESH_RESTORE_RESULT: &str = "RefreshRestoreResult";
+
 pub const REPO_COMPAT_ERROR: &str = "RepoCompatError";
 
 pub const NO_CONNECTION: &str = "NoConnection";
@@ -28,10 +35,17 @@ pub enum SignalEvent {
     FetchResult(Result<(), ReleaseError>),
     Fetched(String, u32, u32),
     Fetching(String),
-    NoConnection,
+    /// The packages which were reinstalled, if the reinstall succeeded.
+    PackageReinstallResult(Result<Vec<String>, ReleaseError>),
+    NoConnection(Vec<String>),
     RecoveryDownloadProgress(u64, u64),
     RecoveryUpgradeEvent(RecoveryEvent),
     RecoveryUpgradeResult(Result<(), RecoveryError>),
+    RecoverySyncProgress(SyncProgress),
+    RecoveryVerifyProgress(u32, u32),
+    RecoveryVerifyResult(Result<RecoveryIntegrity, RecoveryError>),
+    /// The applications which could not be reinstalled, if the restore succeeded.
+    RefreshRestoreResult(Result<Vec<String>, ReleaseError>),
     ReleaseUpgradeEvent(UpgradeEvent),
     Upgrade(AptUpgradeEvent),
 }
@@ -45,7 +59,10 @@ impl Display for SignalEvent {
                 write!(fmt, "fetched {}/{}: {}", progress, total, package)
             }
             Fetching(package) => write!(fmt, "fetching {}", package),
-            NoConnection => write!(fmt, "internet connection required, but not available"),
+            PackageReinstallResult(result) => write!(fmt, "package reinstall result: {:?}", result),
+            NoConnection(hosts) => {
+                write!(fmt, "internet connection required, but unable to reach {:?}", hosts)
+            }
             RecoveryDownloadProgress(progress, total) => {
                 write!(fmt, "recovery download: {}/{} MiB", progress / 1024, total / 1024)
             }
@@ -53,6 +70,20 @@ impl Display for SignalEvent {
                 write!(fmt, "recovery upgrade: {}", <&'static str>::from(*event))
             }
             RecoveryUpgradeResult(result) => write!(fmt, "recovery upgrade result: {:?}", result),
+            RecoverySyncProgress(progress) => write!(
+                fmt,
+                "recovery sync: {}/{} files, {}/{} MiB: {}",
+                progress.files,
+                progress.files_total,
+                progress.current / 1024,
+                progress.total / 1024,
+                progress.file
+            ),
+            RecoveryVerifyProgress(checked, total) => {
+                write!(fmt, "recovery verify: {}/{} files", checked, total)
+            }
+            RecoveryVerifyResult(result) => write!(fmt, "recovery verify result: {:?}", result),
+            RefreshRestoreResult(result) => write!(fmt, "refresh restore result: {:?}", result),
             ReleaseUpgradeEvent(event) => {
                 write!(fmt, "release upgrade: {}", <&'static str>::from(*event))
             }
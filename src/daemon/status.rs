@@ -8,6 +8,9 @@ pub enum DaemonStatus {
     RecoveryUpgrade = 2,
     ReleaseUpgrade = 3,
     PackageUpgrade = 4,
+    RestoringApplications = 5,
+    RecoveryVerify = 6,
+    PackageReinstall = 7,
 }
 
 impl From<DaemonStatus> for &'static str {
@@ -18,6 +21,9 @@ impl From<DaemonStatus> for &'static str {
             DaemonStatus::RecoveryUpgrade => "upgrading recovery partition",
             DaemonStatus::ReleaseUpgrade => "upgrading distribution release",
             DaemonStatus::PackageUpgrade => "upgrading packages",
+            DaemonStatus::RestoringApplications => "reinstalling preserved applications",
+            DaemonStatus::RecoveryVerify => "verifying recovery partition",
+            DaemonStatus::PackageReinstall => "reinstalling removed packages",
         }
     }
 }
@@ -0,0 +1,303 @@
+use crate::{
+    recovery::{RecoveryError, SignatureError},
+    release::ReleaseError,
+};
+use num_traits::FromPrimitive;
+use std::{collections::HashMap, error::Error as ErrorTrait, io};
+
+/// The broad class of failure that an error code belongs to.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
+pub enum ErrorCategory {
+    None = 0,
+    System = 1,
+    Network = 2,
+    Storage = 3,
+    PackageManager = 4,
+    Bootloader = 5,
+    Recovery = 6,
+    Permission = 7,
+    Release = 8,
+    Cancelled = 9,
+}
+
+/// Stable numeric error codes, which are transmitted over D-Bus.
+///
+/// The category of an error code is its value divided by 100. Existing values must never be
+/// changed, as clients rely upon them to provide localized messages and remediation hints.
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
+pub enum ErrorCode {
+    None = 0,
+
+    Unknown = 100,
+    Command = 101,
+    SystemRepair = 102,
+
+    NoConnection = 200,
+    DownloadFailed = 201,
+    ApiUnavailable = 202,
+
+    NoSpace = 300,
+    ReadOnly = 301,
+    Io = 302,
+
+    DpkgBroken = 400,
+    AptLocked = 401,
+    PackageConflict = 402,
+    AptUpdate = 403,
+    AptUpgrade = 404,
+    Simulation = 405,
+    Repositories = 406,
+    PackageHold = 407,
+    UpgradeVerification = 408,
+
+    BootloaderConfig = 500,
+    BootloaderMissing = 501,
+    UpgradeFilesMissing = 502,
+
+    RecoveryNotFound = 600,
+    RecoveryConfig = 601,
+    ChecksumMismatch = 602,
+    EfiNotFound = 603,
+    // 604 is reserved, and must not be reused.
+    IsoNotFound = 605,
+    SignatureInvalid = 606,
+
+    NotRoot = 700,
+
+    ReleaseCheck = 800,
+    NoBuildAvailable = 801,
+
+    Cancelled = 900,
+}
+
+impl ErrorCode {
+    pub fn category(self) -> ErrorCategory {
+        ErrorCategory::from_u16(self as u16 / 100).unwrap_or(ErrorCategory::System)
+    }
+}
+
+impl Default for ErrorCode {
+    fn default() -> Self { ErrorCode::None }
+}
+
+/// Errors which may be described by a stable error code.
+pub trait ErrorCoded: ErrorTrait + 'static {
+    /// The code which best describes this error.
+    fn error_code(&self) -> ErrorCode;
+
+    /// Additional details about the error, such as the path or URL involved.
+    fn error_context(&self, _context: &mut HashMap<String, String>) {}
+}
+
+/// A structured description of an error, for transmission to clients.
+#[derive(Clone, Debug, Default)]
+pub struct ErrorReport {
+    pub category: u8,
+    pub code:     u16,
+    pub context:  HashMap<String, String>,
+    pub causes:   Vec<String>,
+}
+
+impl ErrorReport {
+    /// Describes an error, and each of the errors which caused it.
+    ///
+    /// Low-level causes such as a full disk take precedence over the code of the error itself.
+    pub fn new<E: ErrorCoded>(error: &E) -> Self {
+        let mut context = HashMap::new();
+        error.error_context(&mut context);
+
+        let mut causes = Vec::new();
+        let mut code = None;
+        let mut source: Option<&(dyn ErrorTrait + 'static)> = error.source();
+
+        while let Some(why) = source {
+            causes.push(fomat!((why)));
+
+            if code.is_none() {
+                code = cause_code(why, &mut context);
+            }
+
+            source = why.source();
+        }
+
+        let code = code.unwrap_or_else(|| error.error_code());
+
+        ErrorReport { category: code.category() as u8, code: code as u16, context, causes }
+    }
+
+    /// The error code, if it is known to this build.
+    pub fn code(&self) -> Option<ErrorCode> { ErrorCode::from_u16(self.code) }
+
+    /// The error category, if it is known to this build.
+    pub fn category(&self) -> Option<ErrorCategory> { ErrorCategory::from_u8(self.category) }
+
+    pub fn into_dbus(self) -> (u8, u16, HashMap<String, String>, Vec<String>) {
+        (self.category, self.code, self.context, self.causes)
+    }
+}
+
+/// Determines if a cause of an error has a more specific code than the error itself.
+fn cause_code(
+    why: &(dyn ErrorTrait + 'static),
+    context: &mut HashMap<String, String>,
+) -> Option<ErrorCode> {
+    if let Some(why) = why.downcast_ref::<io::Error>() {
+        let code = match why.raw_os_error()? {
+            libc::ENOSPC | libc::EDQUOT => ErrorCode::NoSpace,
+            libc::EROFS => ErrorCode::ReadOnly,
+            _ => return None,
+        };
+
+        context.insert("os_error".into(), fomat!((why)));
+        return Some(code);
+    }
+
+    if let Some(why) = why.downcast_ref::<isahc::Error>() {
+        if why.is_network() {
+            return Some(ErrorCode::NoConnection);
+        }
+    }
+
+    None
+}
+
+impl ErrorCoded for ReleaseError {
+    fn error_code(&self) -> ErrorCode {
+        use ReleaseError::*;
+        match self {
+            AptList(_) | PackageFetch(_) | Unfetched(_) => ErrorCode::DownloadFailed,
+            AptPurge(_) | ConflictRemoval(_) | InstallCore(_) => ErrorCode::PackageConflict,
+            BackupPPAs(_) | DisablePPAs(_) | OldReleaseSwitch(_) => ErrorCode::Repositories,
+            Check(_) | ReleaseArch(_) | ReleaseVersion(_) => ErrorCode::ReleaseCheck,
+            Command(_) => ErrorCode::Command,
+            CurrentUpdate(_) | ReleaseUpdate(_) => ErrorCode::AptUpdate,
+            DpkgConfigure(_) | FixBroken(_) => ErrorCode::DpkgBroken,
+            HoldPopUpgrade(_)
+            | UnholdPopUpgrade(_)
+            | TransitionalSnapFetch(_)
+            | TransitionalSnapHold(_)
+            | TransitionalSnapRecord(_) => ErrorCode::PackageHold,
+            Lock(_) => ErrorCode::AptLocked,
+            NoConnection(_) => ErrorCode::NoConnection,
+            NotRoot => ErrorCode::NotRoot,
+            PreUpgrade(_) | Repair(_) => ErrorCode::SystemRepair,
+            ReadingPartitions(_) | RefreshPreserve(_) | StartupFileCreation(_) => ErrorCode::Io,
+            RecoveryConf(_)
+            | RecoveryConfOpen(_)
+            | RecoveryModeUnsupported(_)
+            | RecoveryUpdate(_) => ErrorCode::RecoveryConfig,
+            RecoveryNotFound => ErrorCode::RecoveryNotFound,
+            RecoveryUpgrade(why) => why.error_code(),
+            OfflineUpgrade(_)
+            | RefreshRestore(_)
+            | ReinstallRemoved(_)
+            | ReleaseUpgrade(_)
+            | Upgrade(_) => ErrorCode::AptUpgrade,
+            Simulation(_) => ErrorCode::Simulation,
+            Verify(_) => ErrorCode::UpgradeVerification,
+            SystemdUpgradeFilesMissing(_) => ErrorCode::UpgradeFilesMissing,
+            Bootloader(_) | MissingRecoveryEntry => ErrorCode::BootloaderConfig,
+            BootloaderNotFound | SystemdBootEfiPathNotFound => ErrorCode::BootloaderMissing,
+        }
+    }
+
+    fn error_context(&self, context: &mut HashMap<String, String>) {
+        match self {
+            ReleaseError::NoConnection(hosts) => {
+                context.insert("hosts".into(), hosts.join(", "));
+            }
+            ReleaseError::RecoveryUpgrade(why) => why.error_context(context),
+            ReleaseError::SystemdUpgradeFilesMissing(files) => {
+                context.insert("files".into(), files.join(", "));
+            }
+            _ => (),
+        }
+    }
+}
+
+impl ErrorCoded for RecoveryError {
+    fn error_code(&self) -> ErrorCode {
+        use RecoveryError::*;
+        match self {
+            ApiError(_) => ErrorCode::ApiUnavailable,
+            Anyhow(_) => ErrorCode::Unknown,
+            Cancelled => ErrorCode::Cancelled,
+            Checksum { .. } | Staging(_) => ErrorCode::ChecksumMismatch,
+            Download(why) => why.error_code(),
+            Fetch { .. } => ErrorCode::DownloadFailed,
+            IsoNotFound => ErrorCode::IsoNotFound,
+            IsoRead(_) | Mounts(_) | Sync(_) | TempDir(_) | WriteVersion(_) => ErrorCode::Io,
+            NoBuildAvailable => ErrorCode::NoBuildAvailable,
+            RecoveryNotFound => ErrorCode::RecoveryNotFound,
+            Repair(_) => ErrorCode::SystemRepair,
+            Signature(_) => ErrorCode::SignatureInvalid,
+            StagingSpace { .. } => ErrorCode::NoSpace,
+            EfiNotFound => ErrorCode::EfiNotFound,
+            Downgrade { .. } | ReleaseArch(_) | ReleaseVersion(_) => ErrorCode::ReleaseCheck,
+        }
+    }
+
+    fn error_context(&self, context: &mut HashMap<String, String>) {
+        match self {
+            RecoveryError::Checksum { path, .. } => {
+                context.insert("path".into(), path.display().to_string());
+            }
+            RecoveryError::Download(why) => why.error_context(context),
+            RecoveryError::Fetch { url, .. } => {
+                context.insert("url".into(), url.clone());
+            }
+            RecoveryError::Signature(SignatureError::Fetch { url, .. })
+            | RecoveryError::Signature(SignatureError::NotPublished { url }) => {
+                context.insert("url".into(), url.clone());
+            }
+            RecoveryError::Staging(path) => {
+                context.insert("path".into(), path.clone());
+            }
+            RecoveryError::StagingSpace { path, .. } => {
+                context.insert("path".into(), path.display().to_string());
+            }
+            RecoveryError::Sync(why) => {
+                context.insert("path".into(), why.path().display().to_string());
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories() {
+        assert_eq!(ErrorCode::None.category(), ErrorCategory::None);
+        assert_eq!(ErrorCode::NoSpace.category(), ErrorCategory::Storage);
+        assert_eq!(ErrorCode::AptLocked.category(), ErrorCategory::PackageManager);
+        assert_eq!(ErrorCode::Cancelled.category(), ErrorCategory::Cancelled);
+    }
+
+    #[test]
+    fn no_space_takes_precedence() {
+        let error = ReleaseError::StartupFileCreation(io::Error::from_raw_os_error(libc::ENOSPC));
+        let report = ErrorReport::new(&error);
+
+        assert_eq!(report.code(), Some(ErrorCode::NoSpace));
+        assert_eq!(report.category(), Some(ErrorCategory::Storage));
+        assert_eq!(report.causes.len(), 1);
+        assert!(report.context.contains_key("os_error"));
+    }
+
+    #[test]
+    fn reserved() {
+        assert_eq!(ErrorCode::from_u16(604), None);
+        assert_eq!(ErrorCode::from_u16(605), Some(ErrorCode::IsoNotFound));
+    }
+
+    #[test]
+    fn own_code() {
+        let report = ErrorReport::new(&ReleaseError::Lock(io::ErrorKind::Other.into()));
+        assert_eq!(report.code(), Some(ErrorCode::AptLocked));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::connectivity;
+use rand::Rng;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    time::Duration,
+};
+
+/// Groups of equivalent mirrors, one group per line, separated by whitespace.
+const MIRRORS_CONFIG: &str = "/etc/pop-upgrade/mirrors";
+
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Tracks mirrors which serve the same content, and the health of each host.
+#[derive(Debug, Default)]
+pub struct Mirrors {
+    groups:      Vec<MirrorGroup>,
+    failures:    HashMap<String, u32>,
+    unreachable: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct MirrorGroup {
+    /// URI prefixes of packages which may be fetched from this group.
+    prefixes: Vec<String>,
+    /// Base URLs of each mirror in the group.
+    mirrors:  Vec<String>,
+}
+
+impl Mirrors {
+    /// Loads mirrors from apt's `mirror+file:` sources, and `/etc/pop-upgrade/mirrors`.
+    pub fn load() -> Self {
+        let mut groups = Vec::new();
+
+        for source in connectivity::apt_sources() {
+            let path = match source.strip_prefix("mirror+file:") {
+                Some(path) => path,
+                None => continue,
+            };
+
+            match fs::read_to_string(path) {
+                Ok(list) => {
+                    let mirrors = parse_mirror_list(&list);
+                    let mut prefixes = vec![with_slash(&source)];
+                    prefixes.extend(mirrors.iter().cloned());
+                    groups.push(MirrorGroup { prefixes, mirrors });
+                }
+                Err(why) => warn!("failed to read mirror list at {}: {}", path, why),
+            }
+        }
+
+        if let Ok(config) = fs::read_to_string(MIRRORS_CONFIG) {
+            groups.extend(
+                parse_config(&config)
+                    .into_iter()
+                    .map(|mirrors| MirrorGroup { prefixes: mirrors.clone(), mirrors }),
+            );
+        }
+
+        Mirrors { groups, ..Default::default() }
+    }
+
+    /// URIs from which this package may be fetched, with the healthiest hosts first.
+    pub fn candidates(&self, uri: &str) -> Vec<String> {
+        let found = self.groups.iter().find_map(|group| {
+            group
+                .prefixes
+                .iter()
+                .find(|prefix| uri.starts_with(prefix.as_str()))
+                .map(|prefix| (group, &uri[prefix.len()..]))
+        });
+
+        let (group, path) = match found {
+            Some(found) => found,
+            None => return vec![uri.to_owned()],
+        };
+
+        let mut candidates = Vec::with_capacity(group.mirrors.len() + 1);
+
+        // Mirror list URIs can only be fetched through a mirror.
+        if !uri.starts_with("mirror") {
+            candidates.push(uri.to_owned());
+        }
+
+        for mirror in &group.mirrors {
+            let candidate = [mirror.as_str(), path].concat();
+            if !candidates.contains(&candidate) {
+                candidates.push(candidate);
+            }
+        }
+
+        // A stable sort retains the configured order of equally-healthy mirrors.
+        candidates.sort_by_key(|candidate| self.failures(candidate));
+        candidates
+    }
+
+    /// Whether the package can be fetched from at least one reachable host.
+    pub fn available(&self, uri: &str) -> bool {
+        self.candidates(uri).iter().any(|candidate| {
+            connectivity::base_url(candidate).map_or(true, |host| !self.unreachable.contains(&host))
+        })
+    }
+
+    /// Records that a fetch from this URI failed.
+    pub fn failed(&mut self, uri: &str) {
+        if let Some(host) = connectivity::base_url(uri) {
+            *self.failures.entry(host).or_insert(0) += 1;
+        }
+    }
+
+    /// Records that a fetch from this URI succeeded.
+    pub fn succeeded(&mut self, uri: &str) {
+        if let Some(host) = connectivity::base_url(uri) {
+            if let Some(failures) = self.failures.get_mut(&host) {
+                *failures = failures.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Records that these hosts failed a connectivity check.
+    pub fn mark_unreachable(&mut self, hosts: &[String]) {
+        for host in hosts {
+            *self.failures.entry(host.clone()).or_insert(0) += 1;
+            self.unreachable.insert(host.clone());
+        }
+    }
+
+    fn failures(&self, uri: &str) -> u32 {
+        connectivity::base_url(uri)
+            .and_then(|host| self.failures.get(&host).cloned())
+            .unwrap_or(0)
+    }
+}
+
+/// Exponential backoff with jitter, for the given number of previous attempts.
+pub fn backoff(attempt: u32) -> Duration {
+    let delay = BACKOFF_BASE.checked_mul(1u32 << attempt.min(16)).map_or(BACKOFF_MAX, |delay| {
+        delay.min(BACKOFF_MAX)
+    });
+
+    let half = delay.as_millis() as u64 / 2;
+    Duration::from_millis(half + rand::thread_rng().gen_range(0..=half))
+}
+
+/// Parses the contents of an apt `mirror+file:` list.
+///
+/// Each line contains the URL of a mirror, optionally followed by tab-separated metadata.
+fn parse_mirror_list(list: &str) -> Vec<String> {
+    list.lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|url| !url.starts_with('#'))
+        .map(with_slash)
+        .collect()
+}
+
+/// Parses groups of equivalent mirrors, where each line is a group.
+fn parse_config(config: &str) -> Vec<Vec<String>> {
+    config
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .map(|line| line.split_whitespace().map(with_slash).collect::<Vec<String>>())
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+fn with_slash(url: &str) -> String {
+    if url.ends_with('/') {
+        url.to_owned()
+    } else {
+        [url, "/"].concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL: &str = "pool/main/a/apt/apt_2.0.2_amd64.deb";
+
+    fn mirrors() -> Mirrors {
+        let mirrors = parse_mirror_list(
+            "# Ubuntu mirrors\n\
+             http://one.example.com/ubuntu\tpriority:1\n\
+             http://two.example.com/ubuntu/\n",
+        );
+
+        let mut prefixes = vec!["mirror+file:/etc/apt/mirrors.txt/".to_owned()];
+        prefixes.extend(mirrors.iter().cloned());
+
+        let mut groups = vec![MirrorGroup { prefixes, mirrors }];
+
+        let config = parse_config("http://apt.pop-os.org/release http://pop.example.com/release");
+        groups.extend(
+            config.into_iter().map(|mirrors| MirrorGroup { prefixes: mirrors.clone(), mirrors }),
+        );
+
+        Mirrors { groups, ..Default::default() }
+    }
+
+    #[test]
+    fn mirror_list() {
+        let mirrors = mirrors();
+        assert_eq!(mirrors.candidates(&["mirror+file:/etc/apt/mirrors.txt/", POOL].concat()), vec![
+            ["http://one.example.com/ubuntu/", POOL].concat(),
+            ["http://two.example.com/ubuntu/", POOL].concat(),
+        ]);
+    }
+
+    #[test]
+    fn config() {
+        let mirrors = mirrors();
+        assert_eq!(mirrors.candidates(&["http://apt.pop-os.org/release/", POOL].concat()), vec![
+            ["http://apt.pop-os.org/release/", POOL].concat(),
+            ["http://pop.example.com/release/", POOL].concat(),
+        ]);
+    }
+
+    #[test]
+    fn unknown() {
+        let uri = ["http://other.example.com/", POOL].concat();
+        assert_eq!(mirrors().candidates(&uri), vec![uri]);
+    }
+
+    #[test]
+    fn unhealthy_last() {
+        let mut mirrors = mirrors();
+        let uri = ["http://one.example.com/ubuntu/", POOL].concat();
+        mirrors.failed(&uri);
+
+        assert_eq!(mirrors.candidates(&uri), vec![
+            ["http://two.example.com/ubuntu/", POOL].concat(),
+            uri.clone(),
+        ]);
+
+        mirrors.mark_unreachable(&["http://two.example.com/".into()]);
+        assert!(mirrors.available(&uri));
+
+        mirrors.mark_unreachable(&["http://one.example.com/".into()]);
+        assert!(!mirrors.available(&uri));
+    }
+
+    #[test]
+    fn backoff_bounds() {
+        for attempt in 0..40 {
+            let delay = backoff(attempt);
+            let max = BACKOFF_BASE.checked_mul(1u32 << attempt.min(16)).unwrap().min(BACKOFF_MAX);
+            assert!(delay >= max / 2 && delay <= max, "{}: {:?}", attempt, delay);
+        }
+    }
+}
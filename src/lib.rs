@@ -32,9 +32,18 @@ pub mod checksum;
 /// Features specific to the client for the upgrade daemon
 pub mod client;
 
+/// Options for the daemon, which are read from `/etc/pop-upgrade/pop-upgrade.conf`
+pub mod config;
+
+/// Checks for connectivity to the apt repositories and the release API
+pub mod connectivity;
+
 /// Features specific to the upgrade daemon
 pub mod daemon;
 
+/// Stable numeric codes which describe why an operation failed
+pub mod error_code;
+
 /// Functions for determining when the OS was installed
 pub mod install;
 
@@ -65,17 +74,23 @@ pub mod system_environment;
 mod external;
 mod fetch;
 mod gnome_extensions;
-
-use std::path::Path;
+mod http_client;
 
 pub static DBUS_NAME: &str = "com.system76.PopUpgrade";
 pub static DBUS_PATH: &str = "/com/system76/PopUpgrade";
 pub static DBUS_IFACE: &str = "com.system76.PopUpgrade";
 
+/// Version of the D-Bus interface implemented by this build.
+///
+/// Incremented whenever methods or signals are added to, or changed in, the interface.
+pub const DBUS_INTERFACE_VERSION: u32 = 14;
+
+/// The oldest version of the D-Bus interface that this build is able to interoperate with.
+pub const DBUS_INTERFACE_MIN: u32 = 0;
+
+/// Selects the development channel if no channel is set, as it did before channels existed.
 pub const DEVELOPMENT_RELEASE_FILE: &str = "/etc/pop-upgrade/devel";
 
 pub const VAR_LIB_DIR: &str = "/var/lib/pop-upgrade";
 pub const TRANSITIONAL_SNAPS: &str = "/var/lib/pop-upgrade/transitional_snaps";
 pub const RESTART_SCHEDULED: &str = "/var/lib/pop-upgrade/restarting";
-
-pub fn development_releases_enabled() -> bool { Path::new(DEVELOPMENT_RELEASE_FILE).exists() }
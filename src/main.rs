@@ -18,7 +18,7 @@ mod logging;
 mod notify;
 
 use crate::{cli::Client, logging::setup_logging};
-use pop_upgrade::{daemon::Daemon, sighandler};
+use pop_upgrade::{daemon::Daemon, release, sighandler};
 
 pub mod error {
     use pop_upgrade::{
@@ -87,7 +87,7 @@ pub fn main() {
                 // Reboot into the recovery partition.
                 .subcommand(
                     SubCommand::with_name("default-boot")
-                        .about("set the recovery partition as the default boot target")
+                        .about("boot into the recovery partition on the next boot")
                         .arg(
                             Arg::with_name("reboot")
                                 .help("immediately reboot the system into the recovery partition")
@@ -122,6 +122,29 @@ pub fn main() {
                 .subcommand(
                     SubCommand::with_name("check")
                         .about("check the status of the recovery partition"),
+                )
+                .subcommand(
+                    SubCommand::with_name("verify")
+                        .about("check the files of the recovery partition for corruption"),
+                )
+                // Boot the recovery partition into a specific mode.
+                .subcommand(
+                    SubCommand::with_name("mode")
+                        .about("show, set, or cancel the pending recovery partition mode")
+                        .subcommand(
+                            SubCommand::with_name("set")
+                                .about("boot into the recovery partition in this mode on next boot")
+                                .arg(
+                                    Arg::with_name("MODE")
+                                        .help("mode to boot the recovery partition into")
+                                        .possible_values(&["repair", "install"])
+                                        .required(true),
+                                ),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("unset")
+                                .about("cancel the pending mode, and restore the default boot"),
+                        ),
                 ),
         )
         // Distribution release tools
@@ -129,9 +152,36 @@ pub fn main() {
             SubCommand::with_name("release")
                 .about("check for new distribution releases, or upgrade to a new release")
                 .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("channel")
+                        .about("show or set the channel that release upgrades are taken from")
+                        .subcommand(
+                            SubCommand::with_name("set")
+                                .about("take release upgrades from this channel")
+                                .arg(
+                                    Arg::with_name("CHANNEL")
+                                        .help("channel to take release upgrades from")
+                                        .possible_values(&["stable", "beta", "development"])
+                                        .required(true),
+                                ),
+                        ),
+                )
                 .subcommand(
                     SubCommand::with_name("check").about("check for a new distribution release"),
                 )
+                .subcommand(
+                    SubCommand::with_name("changes")
+                        .about("show how the last upgrade changed the installed packages")
+                        .arg(
+                            Arg::with_name("reinstall")
+                                .help(
+                                    "reinstall packages installed by the user which the upgrade \
+                                     removed",
+                                )
+                                .short("r")
+                                .long("reinstall"),
+                        ),
+                )
                 .subcommand(
                     SubCommand::with_name("dismiss")
                         .about("dismiss the current release notification (LTS only)"),
@@ -152,12 +202,30 @@ pub fn main() {
                     SubCommand::with_name("refresh")
                         .about("refresh the existing OS (requires recovery partition)")
                         .subcommand(SubCommand::with_name("disable"))
-                        .subcommand(SubCommand::with_name("enable")),
+                        .subcommand(SubCommand::with_name("enable"))
+                        .subcommand(
+                            SubCommand::with_name("restore")
+                                .about("reinstall the applications preserved before the refresh"),
+                        )
+                        .subcommand(
+                            SubCommand::with_name("discard")
+                                .about("discard the applications preserved before the refresh"),
+                        ),
                 )
                 .subcommand(
                     SubCommand::with_name("repair")
                         .about("search for issues in the system, and repair them"),
                 )
+                .subcommand(
+                    SubCommand::with_name("report")
+                        .about("show the outcome of the last release upgrade")
+                        .arg(
+                            Arg::with_name("dismiss")
+                                .help("do not report this release upgrade again")
+                                .short("d")
+                                .long("dismiss"),
+                        ),
+                )
                 .subcommand(
                     SubCommand::with_name("upgrade")
                         .about("update the system, and fetch the packages for the next release")
@@ -170,9 +238,32 @@ pub fn main() {
                                 .short("f")
                                 .long("force-next")
                                 .global(true),
+                        )
+                        .arg(
+                            Arg::with_name("live")
+                                .help(
+                                    "Install the new release in the running system, instead of \
+                                     on the next boot",
+                                )
+                                .short("l")
+                                .long("live")
+                                .conflicts_with("recovery"),
+                        )
+                        .arg(
+                            Arg::with_name("recovery")
+                                .help(
+                                    "Upgrade the recovery partition, and install the new release \
+                                     from it on the next boot",
+                                )
+                                .short("r")
+                                .long("recovery"),
                         ),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("offline-apply")
+                .about("apply a staged offline upgrade (run by pop-upgrade-init.service)"),
+        )
         .subcommand(
             SubCommand::with_name("status").about("get the status of the pop upgrade daemon"),
         );
@@ -196,6 +287,7 @@ fn main_(matches: &ArgMatches) -> anyhow::Result<()> {
     match matches.subcommand() {
         ("cancel", _) => Client::new()?.cancel()?,
         ("daemon", _) => Daemon::init()?,
+        ("offline-apply", _) => async_io::block_on(release::offline::apply())?,
         (other, Some(matches)) => {
             let mut client = Client::new()?;
 
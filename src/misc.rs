@@ -29,6 +29,11 @@ pub async fn cp<'a>(src: &'a Path, dst: &'a Path) -> io::Result<u64> {
     })
 }
 
+/// The file name of the path, or an empty string if it has none.
+pub fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+}
+
 pub fn format_build_number(value: i16, buffer: &mut String) -> &str {
     if value < 0 {
         "false"
@@ -0,0 +1,359 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error;
+
+pub const RECOVERY_CONF: &str = "/recovery/recovery.conf";
+
+/// Keys which the recovery partition requires to locate the partitions of the OS.
+///
+/// `EFI_UUID` is not required, because legacy BIOS installs do not have an EFI partition.
+const REQUIRED: &[&str] = &["RECOVERY_UUID", "ROOT_UUID"];
+
+#[derive(Debug, Error)]
+pub enum RecoveryConfError {
+    #[error("invalid value for {}: {}", _0, _1)]
+    InvalidValue(&'static str, String),
+
+    #[error("line {} is not a KEY=VALUE pair", _0)]
+    Malformed(usize),
+
+    #[error("required key {} is missing", _0)]
+    MissingKey(&'static str),
+
+    #[error("PREV_BOOT must be set along with MODE")]
+    MissingPrevBoot,
+
+    #[error("unknown recovery mode: {}", _0)]
+    UnknownMode(String),
+
+    #[error("failed to read {:?}", _0)]
+    Read(PathBuf, #[source] io::Error),
+
+    #[error("failed to write {:?}", _0)]
+    Write(PathBuf, #[source] io::Error),
+}
+
+/// What the recovery partition will do when it is booted into.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
+pub enum RecoveryMode {
+    /// Reinstalls the OS, while keeping the home directories and user accounts.
+    Refresh = 1,
+    /// Installs the new release staged by a recovery upgrade.
+    Upgrade = 2,
+    /// Opens a repair shell for the installed OS.
+    Repair = 3,
+    /// Launches the installer, as if booted from the live disk.
+    Install = 4,
+}
+
+impl RecoveryMode {
+    pub const ALL: &'static [RecoveryMode] = &[
+        RecoveryMode::Refresh,
+        RecoveryMode::Upgrade,
+        RecoveryMode::Repair,
+        RecoveryMode::Install,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecoveryMode::Refresh => "refresh",
+            RecoveryMode::Upgrade => "upgrade",
+            RecoveryMode::Repair => "repair",
+            RecoveryMode::Install => "install",
+        }
+    }
+}
+
+impl FromStr for RecoveryMode {
+    type Err = RecoveryConfError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        RecoveryMode::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.as_str() == input)
+            .ok_or_else(|| RecoveryConfError::UnknownMode(input.to_owned()))
+    }
+}
+
+impl From<RecoveryMode> for &'static str {
+    fn from(mode: RecoveryMode) -> Self { mode.as_str() }
+}
+
+/// The configuration of the recovery partition, written by the installer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecoveryConf {
+    pub hostname:      String,
+    pub lang:          String,
+    pub kbd_layout:    String,
+    pub kbd_model:     String,
+    pub kbd_variant:   String,
+    pub efi_uuid:      String,
+    pub recovery_uuid: String,
+    pub root_uuid:     String,
+    pub luks_uuid:     String,
+    pub oem_mode:      bool,
+    /// The mode, if it is known to this version of the daemon. Unknown modes are kept in `other`.
+    pub mode:          Option<RecoveryMode>,
+    /// The boot entry to restore once the recovery partition has completed its mode.
+    pub prev_boot:     Option<String>,
+    /// Keys which are not known to this version of the daemon, preserved as is.
+    pub other:         BTreeMap<String, String>,
+}
+
+impl RecoveryConf {
+    /// Loads the configuration of the recovery partition.
+    pub fn load() -> Result<Self, RecoveryConfError> { Self::load_from(Path::new(RECOVERY_CONF)) }
+
+    pub fn load_from(path: &Path) -> Result<Self, RecoveryConfError> {
+        fs::read_to_string(path)
+            .map_err(|why| RecoveryConfError::Read(path.to_path_buf(), why))?
+            .parse::<Self>()
+    }
+
+    /// Verifies that the configuration is usable by the recovery partition.
+    pub fn validate(&self) -> Result<(), RecoveryConfError> {
+        let required = [&self.recovery_uuid, &self.root_uuid];
+
+        for (key, value) in REQUIRED.iter().zip(required.iter()) {
+            if value.is_empty() {
+                return Err(RecoveryConfError::MissingKey(*key));
+            }
+        }
+
+        if self.mode.is_some() && self.prev_boot.as_deref().map_or(true, str::is_empty) {
+            return Err(RecoveryConfError::MissingPrevBoot);
+        }
+
+        Ok(())
+    }
+
+    /// Sets the mode to boot the recovery partition into, and the boot entry to return to.
+    pub fn set_mode(&mut self, mode: RecoveryMode, prev_boot: &str) {
+        self.other.remove("MODE");
+        self.mode = Some(mode);
+        self.prev_boot = Some(prev_boot.to_owned());
+    }
+
+    pub fn unset_mode(&mut self) {
+        self.other.remove("MODE");
+        self.mode = None;
+        self.prev_boot = None;
+    }
+
+    /// Writes the configuration to the recovery partition.
+    pub fn write(&self) -> Result<(), RecoveryConfError> {
+        self.write_to(Path::new(RECOVERY_CONF))
+    }
+
+    /// Atomically replaces the configuration at the path, keeping a backup of the original.
+    pub fn write_to(&self, path: &Path) -> Result<(), RecoveryConfError> {
+        self.validate()?;
+
+        let temporary = path.with_extension("conf.tmp");
+        let backup = path.with_extension("conf.bak");
+
+        let write = || -> io::Result<()> {
+            let mut file = File::create(&temporary)?;
+            file.write_all(self.to_string().as_bytes())?;
+            file.sync_all()?;
+
+            if path.exists() {
+                fs::copy(path, &backup)?;
+            }
+
+            fs::rename(&temporary, path)
+        };
+
+        write().map_err(|why| {
+            let _ = fs::remove_file(&temporary);
+            RecoveryConfError::Write(path.to_path_buf(), why)
+        })
+    }
+
+    fn known(&self) -> [(&'static str, String); 12] {
+        [
+            ("HOSTNAME", self.hostname.clone()),
+            ("LANG", self.lang.clone()),
+            ("KBD_LAYOUT", self.kbd_layout.clone()),
+            ("KBD_MODEL", self.kbd_model.clone()),
+            ("KBD_VARIANT", self.kbd_variant.clone()),
+            ("EFI_UUID", self.efi_uuid.clone()),
+            ("RECOVERY_UUID", self.recovery_uuid.clone()),
+            ("ROOT_UUID", self.root_uuid.clone()),
+            ("LUKS_UUID", self.luks_uuid.clone()),
+            ("OEM_MODE", if self.oem_mode { "1" } else { "0" }.to_owned()),
+            ("MODE", self.mode.map_or_else(String::new, |mode| mode.as_str().to_owned())),
+            ("PREV_BOOT", self.prev_boot.clone().unwrap_or_default()),
+        ]
+    }
+}
+
+impl FromStr for RecoveryConf {
+    type Err = RecoveryConfError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut conf = RecoveryConf::default();
+
+        for (no, line) in input.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.splitn(2, '=');
+            let key = fields.next().unwrap_or("").trim();
+            let value = fields.next().ok_or(RecoveryConfError::Malformed(no + 1))?.trim();
+            let value = value.trim_matches('"').to_owned();
+
+            match key {
+                "HOSTNAME" => conf.hostname = value,
+                "LANG" => conf.lang = value,
+                "KBD_LAYOUT" => conf.kbd_layout = value,
+                "KBD_MODEL" => conf.kbd_model = value,
+                "KBD_VARIANT" => conf.kbd_variant = value,
+                "EFI_UUID" => conf.efi_uuid = value,
+                "RECOVERY_UUID" => conf.recovery_uuid = value,
+                "ROOT_UUID" => conf.root_uuid = value,
+                "LUKS_UUID" => conf.luks_uuid = value,
+                "OEM_MODE" => {
+                    conf.oem_mode = match value.as_str() {
+                        "" | "0" => false,
+                        "1" => true,
+                        _ => return Err(RecoveryConfError::InvalidValue("OEM_MODE", value)),
+                    }
+                }
+                "MODE" if value.is_empty() => conf.mode = None,
+                "MODE" => match value.parse() {
+                    Ok(mode) => conf.mode = Some(mode),
+                    Err(why) => {
+                        warn!("{}; keeping it as is", why);
+                        conf.other.insert(key.to_owned(), value);
+                    }
+                },
+                "PREV_BOOT" if value.is_empty() => conf.prev_boot = None,
+                "PREV_BOOT" => conf.prev_boot = Some(value),
+                "" => return Err(RecoveryConfError::Malformed(no + 1)),
+                _ => {
+                    conf.other.insert(key.to_owned(), value);
+                }
+            }
+        }
+
+        Ok(conf)
+    }
+}
+
+impl fmt::Display for RecoveryConf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (key, value) in self.known().iter() {
+            // Modes are removed from the file, rather than being left empty.
+            if value.is_empty() && (*key == "MODE" || *key == "PREV_BOOT") {
+                continue;
+            }
+
+            writeln!(f, "{}={}", key, value)?;
+        }
+
+        for (key, value) in &self.other {
+            writeln!(f, "{}={}", key, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONF: &str = "HOSTNAME=pop-os\n\
+                        LANG=en_US.UTF-8\n\
+                        KBD_LAYOUT=us\n\
+                        KBD_MODEL=\n\
+                        KBD_VARIANT=\n\
+                        EFI_UUID=PARTUUID=0a1b2c3d-01\n\
+                        RECOVERY_UUID=PARTUUID=0a1b2c3d-02\n\
+                        ROOT_UUID=UUID=7e2a6c0f-5b5e-4c8e-9c5a-2f0d1e3b4a5c\n\
+                        LUKS_UUID=\n\
+                        OEM_MODE=0\n\
+                        MODE=refresh\n\
+                        PREV_BOOT=Pop_OS-current\n\
+                        EXTRA=value\n";
+
+    #[test]
+    fn parse() {
+        let conf = CONF.parse::<RecoveryConf>().unwrap();
+
+        assert_eq!(conf.hostname, "pop-os");
+        assert_eq!(conf.root_uuid, "UUID=7e2a6c0f-5b5e-4c8e-9c5a-2f0d1e3b4a5c");
+        assert_eq!(conf.mode, Some(RecoveryMode::Refresh));
+        assert_eq!(conf.prev_boot.as_deref(), Some("Pop_OS-current"));
+        assert_eq!(conf.other.get("EXTRA").map(String::as_str), Some("value"));
+        assert!(conf.validate().is_ok());
+    }
+
+    #[test]
+    fn round_trip() {
+        let conf = CONF.parse::<RecoveryConf>().unwrap();
+        assert_eq!(conf.to_string(), CONF);
+        assert_eq!(conf.to_string().parse::<RecoveryConf>().unwrap(), conf);
+    }
+
+    #[test]
+    fn unset_mode() {
+        let mut conf = CONF.parse::<RecoveryConf>().unwrap();
+        conf.unset_mode();
+
+        let output = conf.to_string();
+        assert!(!output.contains("MODE=refresh"));
+        assert!(!output.contains("PREV_BOOT"));
+    }
+
+    #[test]
+    fn unknown_mode() {
+        let input = CONF.replace("MODE=refresh", "MODE=reboot");
+        let mut conf = input.parse::<RecoveryConf>().unwrap();
+
+        assert_eq!(conf.mode, None);
+        assert_eq!(conf.other.get("MODE").map(String::as_str), Some("reboot"));
+
+        let output = conf.to_string();
+        assert!(output.contains("MODE=reboot\n"));
+        assert_eq!(output.parse::<RecoveryConf>().unwrap(), conf);
+
+        conf.set_mode(RecoveryMode::Repair, "Pop_OS-current");
+        assert_eq!(conf.to_string().matches("\nMODE=").count(), 1);
+        assert!(conf.to_string().contains("\nMODE=repair\n"));
+
+        conf.unset_mode();
+        assert!(!conf.to_string().contains("\nMODE="));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(
+            "OEM_MODE\n".parse::<RecoveryConf>(),
+            Err(RecoveryConfError::Malformed(1))
+        ));
+
+        let mut conf = CONF.parse::<RecoveryConf>().unwrap();
+        conf.prev_boot = None;
+        assert!(matches!(conf.validate(), Err(RecoveryConfError::MissingPrevBoot)));
+
+        conf.unset_mode();
+        conf.efi_uuid.clear();
+        assert!(conf.validate().is_ok());
+
+        conf.root_uuid.clear();
+        assert!(matches!(conf.validate(), Err(RecoveryConfError::MissingKey("ROOT_UUID"))));
+    }
+}
@@ -0,0 +1,556 @@
+//! Assembles a new recovery ISO from the blocks of the current recovery partition, so that only
+//! the blocks which have changed since its last upgrade need to be downloaded.
+//!
+//! A block map is published beside each ISO as `<iso>.blockmap`, with the size of the ISO and its
+//! block size on the first line, followed by the weak and strong checksums of each block:
+//!
+//! ```text
+//! blockmap 1 <iso size> <block size>
+//! <rolling checksum as 8 hex digits> <sha256 of the block>
+//! ```
+//!
+//! Files of the ISO begin on 2048-byte sectors, so the files of the recovery partition are
+//! scanned for matching blocks at every sector, with a rolling checksum. Blocks which were not
+//! found, such as those which span two files of the ISO, are then fetched from the ISO with range
+//! requests.
+
+use super::{cancellation_check, RecResult};
+
+use anyhow::Context;
+use futures::prelude::*;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// The alignment of files in an ISO, which the files of the recovery partition are scanned at.
+///
+/// An ISO 9660 image begins each file on a sector, and block sizes are a multiple of the sector
+/// size, so a block of the ISO which lies within a file always begins at a multiple of the sector
+/// size from the start of that file.
+const STRIDE: usize = 2048;
+
+/// Ranges separated by fewer bytes than this are fetched in one request.
+const MERGE_GAP: u64 = 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+struct Block {
+    weak:   u32,
+    strong: [u8; 32],
+}
+
+#[derive(Debug, PartialEq)]
+struct BlockMap {
+    size:       u64,
+    block_size: usize,
+    blocks:     Vec<Block>,
+}
+
+impl BlockMap {
+    fn parse(map: &str) -> anyhow::Result<Self> {
+        let mut lines = map.lines();
+
+        let header = lines.next().context("block map is empty")?;
+        let mut fields = header.split_whitespace();
+
+        if fields.next() != Some("blockmap") || fields.next() != Some("1") {
+            return Err(anyhow!("unsupported block map header: {}", header));
+        }
+
+        let size = fields.next().and_then(|size| size.parse::<u64>().ok());
+        let block_size = fields.next().and_then(|size| size.parse::<usize>().ok());
+
+        let (size, block_size) = match (size, block_size) {
+            (Some(size), Some(block_size)) if block_size % STRIDE == 0 && block_size > 0 => {
+                (size, block_size)
+            }
+            _ => return Err(anyhow!("invalid block map header: {}", header)),
+        };
+
+        let mut blocks = Vec::new();
+
+        for line in lines.filter(|line| !line.is_empty()) {
+            let mut fields = line.split_whitespace();
+
+            let weak = fields.next().and_then(|weak| u32::from_str_radix(weak, 16).ok());
+            let mut strong = [0u8; 32];
+            let strong_ok = fields
+                .next()
+                .map_or(false, |field| hex::decode_to_slice(field, &mut strong).is_ok());
+
+            match weak {
+                Some(weak) if strong_ok => blocks.push(Block { weak, strong }),
+                _ => return Err(anyhow!("invalid block map entry: {}", line)),
+            }
+        }
+
+        if blocks.len() as u64 != (size + block_size as u64 - 1) / block_size as u64 {
+            return Err(anyhow!("block map has {} blocks for an ISO of {}", blocks.len(), size));
+        }
+
+        Ok(Self { size, block_size, blocks })
+    }
+
+    /// Blocks by their weak checksums, excluding a partial block at the end of the ISO.
+    fn index(&self) -> HashMap<u32, Vec<usize>> {
+        let full = (self.size / self.block_size as u64) as usize;
+        let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+
+        for (id, block) in self.blocks.iter().enumerate().take(full) {
+            index.entry(block.weak).or_default().push(id);
+        }
+
+        index
+    }
+}
+
+/// The rolling checksum of rsync and zsync.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Rsum {
+    a: u16,
+    b: u16,
+}
+
+impl Rsum {
+    fn new(data: &[u8]) -> Self {
+        let len = data.len();
+        let mut rsum = Rsum { a: 0, b: 0 };
+
+        for (i, &byte) in data.iter().enumerate() {
+            rsum.a = rsum.a.wrapping_add(u16::from(byte));
+            rsum.b = rsum.b.wrapping_add(((len - i) as u16).wrapping_mul(u16::from(byte)));
+        }
+
+        rsum
+    }
+
+    /// Moves the window forward by a byte.
+    fn roll(&mut self, old: u8, new: u8, len: usize) {
+        self.a = self.a.wrapping_sub(u16::from(old)).wrapping_add(u16::from(new));
+        let old = (len as u16).wrapping_mul(u16::from(old));
+        self.b = self.b.wrapping_sub(old).wrapping_add(self.a);
+    }
+
+    fn value(self) -> u32 { u32::from(self.b) << 16 | u32::from(self.a) }
+}
+
+/// Downloads the ISO at the URL to `dest`, reusing the blocks of files in `seed` where possible.
+///
+/// Returns `false` if no block map was published for the ISO.
+pub async fn fetch<F: Fn(u64, u64)>(
+    cancel: &(dyn Fn() -> bool + Send + Sync),
+    progress: &F,
+    url: &str,
+    dest: &Path,
+    seed: &Path,
+) -> RecResult<bool> {
+    let map = match fetch_block_map(url).await? {
+        Some(map) => map,
+        None => return Ok(false),
+    };
+
+    let mut have = vec![false; map.blocks.len()];
+    let mut file = File::create(dest).context("failed to create ISO file for writing")?;
+    file.set_len(map.size).context("failed to allocate ISO file")?;
+
+    let total = map.size / 1024;
+    let mut reused = 0;
+
+    info!("searching {} for blocks of the new ISO", seed.display());
+
+    let index = map.index();
+
+    for path in seed_files(seed) {
+        cancellation_check(cancel)?;
+
+        match seed_file(&map, &index, &path, &mut file, &mut have) {
+            Ok(bytes) => reused += bytes,
+            Err(why) => warn!("failed to read blocks from {}: {}", path.display(), why),
+        }
+
+        (*progress)(reused / 1024, total);
+    }
+
+    let ranges = missing_ranges(&have, map.block_size as u64, map.size, MERGE_GAP);
+    let missing: u64 = ranges.iter().map(|(start, end)| end - start).sum();
+
+    info!(
+        "reused {} MiB of the new ISO from the recovery partition; fetching {} MiB",
+        reused / 1024 / 1024,
+        missing / 1024 / 1024
+    );
+
+    let mut fetched = map.size - missing;
+
+    for (start, end) in ranges {
+        fetch_range(cancel, &mut file, url, start, end, &mut |bytes| {
+            fetched += bytes;
+            (*progress)(fetched / 1024, total);
+        })
+        .await?;
+    }
+
+    file.sync_all().context("failed to write recovery ISO")?;
+
+    Ok(true)
+}
+
+async fn fetch_block_map(url: &str) -> RecResult<Option<BlockMap>> {
+    let map_url = [url, ".blockmap"].concat();
+
+    let response = crate::http_client::for_url(&map_url)
+        .context("failed to request block map")?
+        .get_async(map_url.as_str())
+        .await
+        .context("failed to request block map")?;
+
+    if !response.status().is_success() {
+        info!("no block map is available at {} ({})", map_url, response.status());
+        return Ok(None);
+    }
+
+    let mut map = String::new();
+    response
+        .into_body()
+        .read_to_string(&mut map)
+        .await
+        .context("failed to download block map")?;
+
+    Ok(Some(BlockMap::parse(&map)?))
+}
+
+/// Fetches a range of the ISO, and writes it to the same range of the file.
+async fn fetch_range(
+    cancel: &(dyn Fn() -> bool + Send + Sync),
+    file: &mut File,
+    url: &str,
+    start: u64,
+    end: u64,
+    progress: &mut dyn FnMut(u64),
+) -> RecResult<()> {
+    use isahc::config::Configurable;
+
+    let request = isahc::Request::get(url)
+        .low_speed_timeout(1, Duration::from_secs(15))
+        .header("Range", fomat!("bytes=" (start) "-" (end - 1)))
+        .body(())
+        .context("failed to build range request")?;
+
+    let response = crate::http_client::for_url(url)
+        .context("failed to request a range of the ISO")?
+        .send_async(request)
+        .await
+        .with_context(|| fomat!("failed to request bytes " (start) "-" (end) " of the ISO"))?;
+
+    if response.status() != isahc::http::StatusCode::PARTIAL_CONTENT {
+        return Err(anyhow!("range request failed with status {}", response.status()).into());
+    }
+
+    file.seek(SeekFrom::Start(start)).context("failed to seek in ISO file")?;
+
+    let mut body = response.into_body();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut position = start;
+    let mut last = Instant::now();
+    let mut pending = 0;
+
+    while position < end {
+        let read = body.read(&mut buf).await.context("failed to download ISO range")?;
+
+        if read == 0 {
+            break;
+        }
+
+        let read = read.min((end - position) as usize);
+        file.write_all(&buf[..read]).context("failed to write ISO range")?;
+        position += read as u64;
+        pending += read as u64;
+
+        if last.elapsed().as_secs() > 1 {
+            last = Instant::now();
+            progress(pending);
+            pending = 0;
+        }
+
+        cancellation_check(cancel)?;
+    }
+
+    progress(pending);
+
+    if position != end {
+        return Err(anyhow!("range of the ISO ended {} bytes early", end - position).into());
+    }
+
+    Ok(())
+}
+
+/// Regular files in the seed directory, without following symlinks.
+fn seed_files(dir: &Path) -> Vec<std::path::PathBuf> {
+    let mut files = Vec::new();
+
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.filter_map(Result::ok) {
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => files.extend(seed_files(&entry.path())),
+                Ok(kind) if kind.is_file() => files.push(entry.path()),
+                _ => (),
+            }
+        }
+    }
+
+    files
+}
+
+/// Writes the blocks of the new ISO which are found in the file, and returns their total size.
+///
+/// The file is scanned at every sector, up to the end of its last sector, which the ISO pads with
+/// zeros.
+fn seed_file(
+    map: &BlockMap,
+    index: &HashMap<u32, Vec<usize>>,
+    path: &Path,
+    out: &mut File,
+    have: &mut [bool],
+) -> io::Result<u64> {
+    let len = map.block_size;
+    let mut window = Window::new(File::open(path)?);
+    let mut reused = 0;
+
+    if !window.fill(len)? {
+        return Ok(0);
+    }
+
+    let mut rsum = Rsum::new(window.get(len));
+
+    loop {
+        let mut advance = STRIDE;
+
+        if let Some(candidates) = index.get(&rsum.value()) {
+            if candidates.iter().any(|&id| !have[id]) {
+                let data = window.get(len);
+                let strong = Sha256::digest(data);
+
+                for &id in candidates {
+                    if !have[id] && map.blocks[id].strong[..] == strong[..] {
+                        out.seek(SeekFrom::Start(id as u64 * len as u64))?;
+                        out.write_all(data)?;
+                        have[id] = true;
+                        reused += len as u64;
+                        advance = len;
+                    }
+                }
+            }
+        }
+
+        if !window.fill(advance + len)? {
+            break;
+        }
+
+        if advance == len {
+            window.advance(len);
+            rsum = Rsum::new(window.get(len));
+        } else {
+            for i in 0..advance {
+                let (old, new) = window.pair(i, len);
+                rsum.roll(old, new, len);
+            }
+
+            window.advance(advance);
+        }
+    }
+
+    Ok(reused)
+}
+
+/// A window which slides over a file, which is padded with zeros to the end of its last sector.
+struct Window {
+    file:    File,
+    buf:     Vec<u8>,
+    start:   usize,
+    /// Bytes which were removed from the front of the buffer.
+    dropped: u64,
+    /// The file has been read to its end, and padded.
+    end:     bool,
+}
+
+impl Window {
+    fn new(file: File) -> Self { Self { file, buf: Vec::new(), start: 0, dropped: 0, end: false } }
+
+    /// Reads until `len` bytes are available from the window, or returns `false` at the end.
+    fn fill(&mut self, len: usize) -> io::Result<bool> {
+        if self.start > 16 * 1024 * 1024 {
+            self.buf.drain(..self.start);
+            self.dropped += self.start as u64;
+            self.start = 0;
+        }
+
+        let mut chunk = [0u8; 64 * 1024];
+
+        while self.buf.len() - self.start < len {
+            if self.end {
+                return Ok(false);
+            }
+
+            match self.file.read(&mut chunk)? {
+                0 => {
+                    let size = self.dropped + self.buf.len() as u64;
+                    let padding = (STRIDE as u64 - size % STRIDE as u64) % STRIDE as u64;
+                    self.buf.resize(self.buf.len() + padding as usize, 0);
+                    self.end = true;
+                }
+                read => self.buf.extend_from_slice(&chunk[..read]),
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn get(&self, len: usize) -> &[u8] { &self.buf[self.start..self.start + len] }
+
+    /// The byte leaving, and the byte entering, the window at an offset.
+    fn pair(&self, offset: usize, len: usize) -> (u8, u8) {
+        (self.buf[self.start + offset], self.buf[self.start + offset + len])
+    }
+
+    fn advance(&mut self, by: usize) { self.start += by; }
+}
+
+/// Byte ranges of the blocks which are missing, where ranges closer than `gap` are merged.
+fn missing_ranges(have: &[bool], block_size: u64, size: u64, gap: u64) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+
+    for (id, _) in have.iter().enumerate().filter(|&(_, &found)| !found) {
+        let start = id as u64 * block_size;
+        let end = (start + block_size).min(size);
+
+        match ranges.last_mut() {
+            Some(last) if start - last.1 <= gap => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: usize = STRIDE * 2;
+
+    fn block_map(iso: &[u8]) -> String {
+        let mut map = fomat!("blockmap 1 " (iso.len()) " " (BLOCK) "\n");
+
+        for block in iso.chunks(BLOCK) {
+            let strong = Sha256::digest(block);
+            map.push_str(&fomat!({:08x}(Rsum::new(block).value()) " " {:x}(strong) "\n"));
+        }
+
+        map
+    }
+
+    #[test]
+    fn rolling() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(BLOCK + STRIDE).collect();
+        let mut rsum = Rsum::new(&data[..BLOCK]);
+
+        for i in 0..STRIDE {
+            rsum.roll(data[i], data[i + BLOCK], BLOCK);
+        }
+
+        assert_eq!(rsum, Rsum::new(&data[STRIDE..STRIDE + BLOCK]));
+    }
+
+    #[test]
+    fn parse() {
+        let iso = vec![7u8; BLOCK * 2 + 10];
+        let map = BlockMap::parse(&block_map(&iso)).unwrap();
+
+        assert_eq!(map.size, iso.len() as u64);
+        assert_eq!(map.blocks.len(), 3);
+        assert_eq!(map.index().get(&Rsum::new(&iso[..BLOCK]).value()), Some(&vec![0, 1]));
+
+        assert!(BlockMap::parse("blockmap 1 100 1000\n").is_err());
+        assert!(BlockMap::parse("zsync 1 4096 4096\n00000000 00\n").is_err());
+    }
+
+    #[test]
+    fn ranges() {
+        let have = [true, false, false, true, false, true, true, true, false];
+        assert_eq!(missing_ranges(&have, 10, 85, 10), vec![(10, 50), (80, 85)]);
+        assert_eq!(missing_ranges(&have, 10, 85, 0), vec![(10, 30), (40, 50), (80, 85)]);
+        assert!(missing_ranges(&[true, true], 10, 20, 0).is_empty());
+    }
+
+    #[test]
+    fn seed() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // The new ISO shares a file with the recovery partition, shifted by a sector.
+        let shared: Vec<u8> = (0..BLOCK * 3).map(|i| (i * 7 % 251) as u8).collect();
+        let mut iso = vec![1u8; STRIDE];
+        iso.extend_from_slice(&shared);
+        iso.extend_from_slice(&[2u8; BLOCK]);
+
+        let mut seed = vec![3u8; STRIDE * 3];
+        seed.extend_from_slice(&shared);
+        fs::write(dir.path().join("seed"), &seed).unwrap();
+
+        let map = BlockMap::parse(&block_map(&iso)).unwrap();
+        let mut have = vec![false; map.blocks.len()];
+        let mut out = tempfile::tempfile().unwrap();
+        out.set_len(iso.len() as u64).unwrap();
+
+        let reused =
+            seed_file(&map, &map.index(), &dir.path().join("seed"), &mut out, &mut have).unwrap();
+
+        // Every block which lies within the shared file is reused.
+        assert_eq!(have, vec![false, true, true, false, false]);
+        assert_eq!(reused, BLOCK as u64 * 2);
+
+        let mut assembled = vec![0u8; iso.len()];
+        out.seek(SeekFrom::Start(0)).unwrap();
+        out.read_exact(&mut assembled).unwrap();
+        assert_eq!(assembled[BLOCK..BLOCK * 3], iso[BLOCK..BLOCK * 3]);
+    }
+
+    #[test]
+    fn iso_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let lengths = [BLOCK * 2 + 100, BLOCK + STRIDE, BLOCK * 3 - 1];
+        let mut iso = Vec::new();
+
+        // Files begin on sectors, and the rest of their last sector is zeroed.
+        for (no, &length) in lengths.iter().enumerate() {
+            let data: Vec<u8> = (0..length).map(|i| ((i * 31 + no * 17) % 253 + 1) as u8).collect();
+            fs::write(dir.path().join(no.to_string()), &data).unwrap();
+
+            iso.extend_from_slice(&data);
+            iso.resize((iso.len() + STRIDE - 1) / STRIDE * STRIDE, 0);
+        }
+
+        let map = BlockMap::parse(&block_map(&iso)).unwrap();
+        let index = map.index();
+        let mut have = vec![false; map.blocks.len()];
+        let mut out = tempfile::tempfile().unwrap();
+        out.set_len(iso.len() as u64).unwrap();
+
+        for no in 0..lengths.len() {
+            let path = dir.path().join(no.to_string());
+            seed_file(&map, &index, &path, &mut out, &mut have).unwrap();
+        }
+
+        // Only the block which spans the first two files is missing. The second file's block
+        // begins a sector into it, and the third file's last block ends in its padding.
+        assert_eq!(have, vec![true, true, false, true, true, true, true]);
+
+        let mut assembled = vec![0u8; iso.len()];
+        out.seek(SeekFrom::Start(0)).unwrap();
+        out.read_exact(&mut assembled).unwrap();
+        assert_eq!(assembled[BLOCK * 3..], iso[BLOCK * 3..]);
+    }
+}
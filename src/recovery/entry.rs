@@ -0,0 +1,124 @@
+//! The boot entry which boots the casper image of the recovery partition.
+
+use anyhow::Context;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Where systemd-boot reads its boot entries from.
+pub const LOADER_ENTRIES: &str = "/boot/efi/loader/entries";
+
+/// Where the kernel and initrd of the recovery partition are copied to for systemd-boot.
+pub const EFI_DIR: &str = "/boot/efi/EFI";
+
+pub const TITLE: &str = "Pop!_OS Recovery";
+
+/// The kernel command line which boots the casper image of the recovery partition.
+pub fn casper_options(uuid: &str) -> String {
+    fomat!(
+        "boot=casper hostname=recovery userfullname=Recovery username=recovery "
+        "live-media-path=/casper-" (uuid) " live-media=/dev/disk/by-uuid/" (uuid) " noprompt"
+    )
+}
+
+/// The directories in the EFI partition that the kernel and initrd are copied to in turn, so that
+/// an upgrade switches between them by replacing the loader entry.
+pub fn efi_dir_names(uuid: &str) -> [String; 2] {
+    [fomat!("Recovery-" (uuid)), fomat!("Recovery-" (uuid) "-B")]
+}
+
+/// The directory in the EFI partition that the loader entry boots the kernel and initrd from.
+pub fn efi_dir(uuid: &str) -> PathBuf {
+    let entry = fs::read_to_string(loader_entry_path(uuid)).unwrap_or_default();
+    Path::new(EFI_DIR).join(active_efi_dir(&entry, uuid))
+}
+
+/// Which of the `efi_dir_names` a loader entry boots from, which is the first if the entry is
+/// missing or boots from neither.
+pub fn active_efi_dir(entry: &str, uuid: &str) -> String {
+    let [first, second] = efi_dir_names(uuid);
+
+    let linux = entry.lines().filter_map(|line| line.trim().strip_prefix("linux ")).next();
+    let boots = |name: &str| linux.map_or(false, |linux| linux.trim() == kernel_path(name));
+
+    if !boots(&first) && boots(&second) {
+        second
+    } else {
+        first
+    }
+}
+
+/// The file name of the systemd-boot entry of the recovery partition.
+pub fn loader_entry_name(uuid: &str) -> String { fomat!("Recovery-" (uuid) ".conf") }
+
+/// The systemd-boot entry of the recovery partition.
+pub fn loader_entry_path(uuid: &str) -> PathBuf {
+    Path::new(LOADER_ENTRIES).join(loader_entry_name(uuid))
+}
+
+/// A systemd-boot entry which boots the kernel and initrd copied to a directory of the EFI
+/// partition.
+pub fn loader_entry(uuid: &str, efi_dir: &str) -> String {
+    fomat!(
+        "title " (TITLE) "\n"
+        "linux " (kernel_path(efi_dir)) "\n"
+        "initrd /EFI/" (efi_dir) "/initrd.gz\n"
+        "options " (casper_options(uuid)) "\n"
+    )
+}
+
+fn kernel_path(efi_dir: &str) -> String { fomat!("/EFI/" (efi_dir) "/vmlinuz.efi") }
+
+/// Creates the systemd-boot entry of the recovery partition, if it is missing.
+///
+/// Returns `true` if the entry was created.
+pub fn ensure_loader_entry(uuid: &str) -> anyhow::Result<bool> {
+    let path = loader_entry_path(uuid);
+
+    if path.exists() {
+        return Ok(false);
+    }
+
+    info!("creating the missing recovery entry at {}", path.display());
+
+    fs::create_dir_all(LOADER_ENTRIES)
+        .with_context(|| fomat!("failed to create " (LOADER_ENTRIES)))?;
+
+    let [efi_dir, _] = efi_dir_names(uuid);
+
+    fs::write(&path, loader_entry(uuid, &efi_dir))
+        .with_context(|| fomat!("failed to write " (path.display())))?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry() {
+        assert_eq!(
+            loader_entry("ABCD-1234", "Recovery-ABCD-1234"),
+            "title Pop!_OS Recovery\n\
+             linux /EFI/Recovery-ABCD-1234/vmlinuz.efi\n\
+             initrd /EFI/Recovery-ABCD-1234/initrd.gz\n\
+             options boot=casper hostname=recovery userfullname=Recovery username=recovery \
+             live-media-path=/casper-ABCD-1234 live-media=/dev/disk/by-uuid/ABCD-1234 noprompt\n"
+        );
+
+        assert_eq!(
+            loader_entry_path("ABCD-1234"),
+            Path::new("/boot/efi/loader/entries/Recovery-ABCD-1234.conf")
+        );
+    }
+
+    #[test]
+    fn active_dir() {
+        let second = loader_entry("ABCD", "Recovery-ABCD-B");
+        assert_eq!(active_efi_dir(&second, "ABCD"), "Recovery-ABCD-B");
+        assert_eq!(active_efi_dir(&loader_entry("ABCD", "Recovery-ABCD"), "ABCD"), "Recovery-ABCD");
+        assert_eq!(active_efi_dir("", "ABCD"), "Recovery-ABCD");
+    }
+}
@@ -1,3 +1,4 @@
+use super::{SignatureError, SyncError};
 use crate::{
     checksum::ValidateError, release_api::ApiError, release_architecture::ReleaseArchError,
     repair::RepairError,
@@ -23,6 +24,9 @@ pub enum RecoveryError {
     #[error("checksum for {:?} failed: {}", path, source)]
     Checksum { path: PathBuf, source: ValidateError },
 
+    #[error("recovery partition is already on {}, which is newer than {}", current, requested)]
+    Downgrade { current: String, requested: String },
+
     #[error("failed to download ISO")]
     Download(#[source] Box<RecoveryError>),
 
@@ -32,6 +36,9 @@ pub enum RecoveryError {
     #[error("ISO does not exist at path")]
     IsoNotFound,
 
+    #[error("failed to read the recovery ISO")]
+    IsoRead(#[source] io::Error),
+
     #[error("failed to fetch mount points")]
     Mounts(#[source] io::Error),
 
@@ -56,8 +63,22 @@ pub enum RecoveryError {
     #[error("failed to fetch release versions")]
     ReleaseVersion(#[from] VersionError),
 
-    #[error("the recovery feature is limited to EFI installs")]
-    Unsupported,
+    #[error("failed to verify the signature of the release ISO")]
+    Signature(#[from] SignatureError),
+
+    #[error("failed to sync the ISO to the recovery partition")]
+    Sync(#[from] SyncError),
+
+    #[error("failed to stage the new recovery image at {}; the previous kernel was kept", _0)]
+    Staging(String),
+
+    #[error(
+        "{:?} has {} MiB available, but staging the new recovery image requires {} MiB",
+        path,
+        available / 1024 / 1024,
+        required / 1024 / 1024
+    )]
+    StagingSpace { path: PathBuf, required: u64, available: u64 },
 
     #[error("failed to write version of ISO now stored on the recovery partition")]
     WriteVersion(#[source] io::Error),
@@ -0,0 +1,746 @@
+//! Reads files from an ISO9660 image, so that the recovery ISO may be synced to the recovery
+//! partition without loop-mounting it.
+//!
+//! Names are read from the Rock Ridge extensions when they are present, which also provide
+//! symlinks, or else from the Joliet extensions. Deep directory relocation is not supported, as
+//! the directories of the recovery ISO are not nested deeply enough to require it.
+
+use super::sync::Source;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Component, Path},
+};
+
+/// The sector of the first volume descriptor.
+const DESCRIPTORS: u64 = 16;
+
+const SECTOR: usize = 2048;
+
+const FLAG_DIRECTORY: u8 = 0x02;
+const FLAG_MULTI_EXTENT: u8 = 0x80;
+
+/// Limits the symlinks followed in a lookup, in case they form a loop.
+const MAX_SYMLINKS: usize = 40;
+
+/// Limits the continuation areas read for the Rock Ridge fields of a record.
+const MAX_CONTINUATIONS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Names {
+    Plain,
+    Joliet,
+    /// Rock Ridge fields begin after the given number of bytes in each record's system use area.
+    RockRidge(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    Directory,
+    File,
+    Symlink(String),
+}
+
+/// A file, directory, or symlink in the image.
+#[derive(Clone, Debug)]
+pub struct IsoEntry {
+    pub name: String,
+    kind:     Kind,
+    /// The logical block and length of each extent of the file.
+    extents:  Vec<(u32, u32)>,
+}
+
+impl IsoEntry {
+    pub fn is_dir(&self) -> bool { self.kind == Kind::Directory }
+
+    pub fn len(&self) -> u64 { self.extents.iter().map(|&(_, len)| u64::from(len)).sum() }
+}
+
+pub struct Iso<R> {
+    image:      R,
+    block_size: u64,
+    names:      Names,
+    root:       IsoEntry,
+    /// Directories which have been read, by the block of their first extent.
+    cache:      HashMap<u32, Vec<IsoEntry>>,
+}
+
+impl Iso<File> {
+    pub fn open(path: &Path) -> io::Result<Self> { File::open(path).and_then(Self::new) }
+}
+
+impl<R: Read + Seek> Iso<R> {
+    pub fn new(mut image: R) -> io::Result<Self> {
+        let mut primary = None;
+        let mut joliet = None;
+
+        for sector in DESCRIPTORS.. {
+            let mut descriptor = vec![0u8; SECTOR];
+            read_at(&mut image, sector * SECTOR as u64, &mut descriptor)?;
+
+            if &descriptor[1..6] != b"CD001" {
+                return Err(invalid("volume descriptor lacks the CD001 identifier"));
+            }
+
+            match descriptor[0] {
+                1 if primary.is_none() => primary = Some(descriptor),
+                2 if is_joliet(&descriptor) => joliet = Some(descriptor),
+                255 => break,
+                _ => (),
+            }
+        }
+
+        let primary = primary.ok_or_else(|| invalid("primary volume descriptor not found"))?;
+        let block_size = u64::from(u16::from_le_bytes([primary[128], primary[129]]));
+
+        let mut iso = Self {
+            image,
+            block_size,
+            names: Names::Plain,
+            root: root_entry(&primary)?,
+            cache: HashMap::new(),
+        };
+
+        if let Some(skip) = iso.rock_ridge()? {
+            iso.names = Names::RockRidge(skip);
+        } else if let Some(joliet) = joliet {
+            iso.root = root_entry(&joliet)?;
+            iso.names = Names::Joliet;
+        }
+
+        Ok(iso)
+    }
+
+    /// Finds the entry at the path, following symlinks.
+    pub fn lookup(&mut self, path: &Path) -> io::Result<IsoEntry> {
+        let mut remaining = Vec::new();
+
+        for component in path.components().rev() {
+            remaining.push(match component {
+                Component::RootDir | Component::Prefix(_) => String::from("/"),
+                Component::CurDir => continue,
+                Component::ParentDir => String::from(".."),
+                Component::Normal(name) => name.to_str().ok_or_else(not_found)?.to_owned(),
+            });
+        }
+
+        let mut parents = Vec::new();
+        let mut current = self.root.clone();
+        let mut followed = 0;
+
+        while let Some(component) = remaining.pop() {
+            match component.as_str() {
+                "" | "." => continue,
+                "/" => {
+                    parents.clear();
+                    current = self.root.clone();
+                    continue;
+                }
+                ".." => {
+                    if let Some(parent) = parents.pop() {
+                        current = parent;
+                    }
+                    continue;
+                }
+                _ if !current.is_dir() => return Err(not_found()),
+                _ => (),
+            }
+
+            let entry = self
+                .entries(&current)?
+                .into_iter()
+                .find(|entry| entry.name == component)
+                .ok_or_else(not_found)?;
+
+            if let Kind::Symlink(ref target) = entry.kind {
+                followed += 1;
+                if followed > MAX_SYMLINKS {
+                    return Err(invalid("too many levels of symbolic links"));
+                }
+
+                remaining.extend(target.split('/').rev().map(String::from));
+
+                if target.starts_with('/') {
+                    remaining.push(String::from("/"));
+                }
+
+                continue;
+            }
+
+            parents.push(std::mem::replace(&mut current, entry));
+        }
+
+        Ok(current)
+    }
+
+    /// The files, directories, and symlinks in a directory.
+    pub fn entries(&mut self, dir: &IsoEntry) -> io::Result<Vec<IsoEntry>> {
+        let first = dir.extents.first().map_or(0, |&(block, _)| block);
+
+        if let Some(entries) = self.cache.get(&first) {
+            return Ok(entries.clone());
+        }
+
+        let mut entries: Vec<IsoEntry> = Vec::new();
+        let mut continued = false;
+
+        for &(block, len) in &dir.extents {
+            let mut data = vec![0u8; len as usize];
+            read_at(&mut self.image, u64::from(block) * self.block_size, &mut data)?;
+
+            let mut offset = 0;
+
+            while offset < data.len() {
+                let length = data[offset] as usize;
+
+                // Records do not cross sectors, so the remainder of this sector is padding.
+                if length == 0 {
+                    offset = (offset / SECTOR + 1) * SECTOR;
+                    continue;
+                }
+
+                let record = data
+                    .get(offset..offset + length)
+                    .ok_or_else(|| invalid("directory record extends past its directory"))?;
+
+                offset += length;
+
+                let record = Record::parse(record)?;
+
+                if record.is_self_or_parent() {
+                    continue;
+                }
+
+                // The extents of a multi-extent file follow in consecutive records.
+                if continued {
+                    if let Some(last) = entries.last_mut() {
+                        last.extents.push((record.extent, record.len));
+                    }
+                } else {
+                    let entry = self.entry(&record)?;
+                    entries.push(entry);
+                }
+
+                continued = record.flags & FLAG_MULTI_EXTENT != 0;
+            }
+        }
+
+        self.cache.insert(first, entries.clone());
+
+        Ok(entries)
+    }
+
+    /// Reads the contents of a file.
+    pub fn reader(&mut self, entry: &IsoEntry) -> IsoReader<R> {
+        IsoReader {
+            image:      &mut self.image,
+            block_size: self.block_size,
+            extents:    entry.extents.clone(),
+            extent:     0,
+            position:   0,
+        }
+    }
+
+    /// Reads the file at the path to a string.
+    pub fn read_to_string(&mut self, path: &Path) -> io::Result<String> {
+        let entry = self.lookup(path)?;
+        let mut string = String::new();
+        self.reader(&entry).read_to_string(&mut string)?;
+        Ok(string)
+    }
+
+    fn entry(&mut self, record: &Record) -> io::Result<IsoEntry> {
+        let mut kind =
+            if record.flags & FLAG_DIRECTORY != 0 { Kind::Directory } else { Kind::File };
+
+        let name = match self.names {
+            Names::RockRidge(skip) => {
+                let fields = self.rock_ridge_fields(record.system_use.get(skip..).unwrap_or(&[]))?;
+
+                if let Some(target) = fields.symlink {
+                    kind = Kind::Symlink(target);
+                }
+
+                fields.name.unwrap_or_else(|| plain_name(record.identifier))
+            }
+            Names::Joliet => joliet_name(record.identifier),
+            Names::Plain => plain_name(record.identifier),
+        };
+
+        Ok(IsoEntry { name, kind, extents: vec![(record.extent, record.len)] })
+    }
+
+    /// The bytes to skip in each system use area, if the root has a Rock Ridge `SP` field.
+    fn rock_ridge(&mut self) -> io::Result<Option<usize>> {
+        let block = self.root.extents.first().map_or(0, |&(block, _)| block);
+        let mut sector = vec![0u8; SECTOR];
+        read_at(&mut self.image, u64::from(block) * self.block_size, &mut sector)?;
+
+        let length = sector[0] as usize;
+        let record = sector.get(..length).ok_or_else(|| invalid("root record is truncated"))?;
+        let system_use = Record::parse(record)?.system_use;
+
+        let is_rock_ridge = system_use.len() >= 7
+            && &system_use[..2] == b"SP"
+            && system_use[4] == 0xBE
+            && system_use[5] == 0xEF;
+
+        Ok(if is_rock_ridge { Some(system_use[6] as usize) } else { None })
+    }
+
+    /// Reads the name and symlink target from the Rock Ridge fields of a record.
+    fn rock_ridge_fields(&mut self, system_use: &[u8]) -> io::Result<RockRidge> {
+        let mut fields = RockRidge::default();
+        let mut name = None::<Vec<u8>>;
+        let mut continued = false;
+        let mut area = system_use.to_vec();
+
+        for _ in 0..MAX_CONTINUATIONS {
+            let mut continuation = None;
+            let mut offset = 0;
+
+            while offset + 4 <= area.len() {
+                let length = area[offset + 2] as usize;
+
+                if length < 4 || offset + length > area.len() {
+                    break;
+                }
+
+                let data = &area[offset + 4..offset + length];
+
+                match &area[offset..offset + 2] {
+                    b"NM" if !data.is_empty() => {
+                        name.get_or_insert_with(Vec::new).extend_from_slice(&data[1..])
+                    }
+                    b"SL" if !data.is_empty() => symlink_components(
+                        &data[1..],
+                        fields.symlink.get_or_insert_with(String::new),
+                        &mut continued,
+                    ),
+                    b"CE" if data.len() >= 20 => {
+                        continuation =
+                            Some((le32(&data[0..4]), le32(&data[8..12]), le32(&data[16..20])));
+                    }
+                    b"ST" => break,
+                    _ => (),
+                }
+
+                offset += length;
+            }
+
+            match continuation {
+                Some((block, offset, length)) => {
+                    area = vec![0u8; length as usize];
+                    let position = u64::from(block) * self.block_size + u64::from(offset);
+                    read_at(&mut self.image, position, &mut area)?;
+                }
+                None => break,
+            }
+        }
+
+        fields.name = name.map(|name| String::from_utf8_lossy(&name).into_owned());
+
+        Ok(fields)
+    }
+}
+
+impl<R: Read + Seek> Source for Iso<R> {
+    fn stat(&mut self, path: &Path) -> io::Result<Option<u64>> {
+        let entry = self.lookup(path)?;
+        Ok(if entry.is_dir() { None } else { Some(entry.len()) })
+    }
+
+    fn read_dir(&mut self, path: &Path) -> io::Result<Vec<OsString>> {
+        let dir = self.lookup(path)?;
+
+        if !dir.is_dir() {
+            return Err(invalid("not a directory"));
+        }
+
+        Ok(self.entries(&dir)?.into_iter().map(|entry| OsString::from(entry.name)).collect())
+    }
+
+    fn open_file<'a>(&'a mut self, path: &Path) -> io::Result<Box<dyn Read + 'a>> {
+        let entry = self.lookup(path)?;
+        Ok(Box::new(self.reader(&entry)))
+    }
+}
+
+/// Reads the extents of a file in the image.
+pub struct IsoReader<'a, R> {
+    image:      &'a mut R,
+    block_size: u64,
+    extents:    Vec<(u32, u32)>,
+    extent:     usize,
+    /// The position within the current extent.
+    position:   u64,
+}
+
+impl<'a, R: Read + Seek> Read for IsoReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(&(block, len)) = self.extents.get(self.extent) {
+            let remaining = u64::from(len) - self.position;
+
+            if remaining == 0 {
+                self.extent += 1;
+                self.position = 0;
+                continue;
+            }
+
+            if buf.is_empty() {
+                return Ok(0);
+            }
+
+            let max = remaining.min(buf.len() as u64) as usize;
+
+            self.image.seek(SeekFrom::Start(u64::from(block) * self.block_size + self.position))?;
+
+            let read = self.image.read(&mut buf[..max])?;
+
+            if read == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "file extends past the end of the image",
+                ));
+            }
+
+            self.position += read as u64;
+
+            return Ok(read);
+        }
+
+        Ok(0)
+    }
+}
+
+/// The fields of a directory record which are needed to read it.
+struct Record<'a> {
+    extent:     u32,
+    len:        u32,
+    flags:      u8,
+    identifier: &'a [u8],
+    system_use: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    fn parse(record: &'a [u8]) -> io::Result<Self> {
+        if record.len() < 34 {
+            return Err(invalid("directory record is truncated"));
+        }
+
+        let identifier_len = record[32] as usize;
+        let identifier_end = 33 + identifier_len;
+
+        // A padding byte follows identifiers of an even length.
+        let system_use = identifier_end + (identifier_len + 1) % 2;
+
+        if identifier_end > record.len() {
+            return Err(invalid("directory record identifier is truncated"));
+        }
+
+        Ok(Self {
+            extent:     le32(&record[2..6]),
+            len:        le32(&record[10..14]),
+            flags:      record[25],
+            identifier: &record[33..identifier_end],
+            system_use: record.get(system_use..).unwrap_or(&[]),
+        })
+    }
+
+    fn is_self_or_parent(&self) -> bool { self.identifier == [0] || self.identifier == [1] }
+}
+
+#[derive(Default)]
+struct RockRidge {
+    name:    Option<String>,
+    symlink: Option<String>,
+}
+
+/// Appends the component records of an `SL` field to the target of a symlink.
+fn symlink_components(mut records: &[u8], target: &mut String, continued: &mut bool) {
+    while records.len() >= 2 {
+        let flags = records[0];
+        let len = records[1] as usize;
+
+        let content = match records.get(2..2 + len) {
+            Some(content) => content,
+            None => break,
+        };
+
+        records = &records[2 + len..];
+
+        if !*continued && !target.is_empty() && !target.ends_with('/') {
+            target.push('/');
+        }
+
+        match flags & 0x0E {
+            0x02 => target.push('.'),
+            0x04 => target.push_str(".."),
+            0x08 => target.push('/'),
+            _ => target.push_str(&String::from_utf8_lossy(content)),
+        }
+
+        *continued = flags & 0x01 != 0;
+    }
+}
+
+fn root_entry(descriptor: &[u8]) -> io::Result<IsoEntry> {
+    let record = Record::parse(&descriptor[156..190])?;
+
+    Ok(IsoEntry {
+        name:    String::new(),
+        kind:    Kind::Directory,
+        extents: vec![(record.extent, record.len)],
+    })
+}
+
+/// Joliet volumes are identified by the UCS-2 escape sequences of the supplementary descriptor.
+fn is_joliet(descriptor: &[u8]) -> bool {
+    matches!(&descriptor[88..91], b"%/@" | b"%/C" | b"%/E")
+}
+
+fn joliet_name(identifier: &[u8]) -> String {
+    let units: Vec<u16> =
+        identifier.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect();
+
+    strip_version(&String::from_utf16_lossy(&units)).to_owned()
+}
+
+/// Names without extensions are mapped the same as `mount -o map=normal`.
+fn plain_name(identifier: &[u8]) -> String {
+    let name = String::from_utf8_lossy(identifier);
+    let name = strip_version(&name);
+    name.strip_suffix('.').unwrap_or(name).to_ascii_lowercase()
+}
+
+fn strip_version(name: &str) -> &str { name.rfind(';').map_or(name, |at| &name[..at]) }
+
+fn le32(bytes: &[u8]) -> u32 { u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) }
+
+fn read_at<R: Read + Seek>(image: &mut R, position: u64, buf: &mut [u8]) -> io::Result<()> {
+    image.seek(SeekFrom::Start(position))?;
+    image.read_exact(buf)
+}
+
+fn invalid(why: &'static str) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, why) }
+
+fn not_found() -> io::Error { io::ErrorKind::NotFound.into() }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    enum Node {
+        Dir(&'static str, Vec<Node>),
+        File(&'static str, Vec<u8>),
+        Link(&'static str, &'static str),
+    }
+
+    /// Generates an image with either Rock Ridge or Joliet names.
+    fn image(tree: &[Node], rock_ridge: bool) -> Vec<u8> {
+        let mut image = vec![0u8; SECTOR * (DESCRIPTORS as usize + 3)];
+        let root = write_dir(&mut image, tree, None, rock_ridge);
+        let root_record = record(root, SECTOR as u32, FLAG_DIRECTORY, &[0], &[]);
+
+        let primary: [(u8, &[u8]); 1] = [(1, b"")];
+        let joliet: [(u8, &[u8]); 2] = [(1, b""), (2, b"%/E")];
+        let descriptors: &[(u8, &[u8])] = if rock_ridge { &primary } else { &joliet };
+
+        let mut sector = DESCRIPTORS as usize;
+
+        for &(kind, escape) in descriptors.iter().chain(&[(255, &b""[..])]) {
+            let descriptor = &mut image[sector * SECTOR..(sector + 1) * SECTOR];
+            descriptor[0] = kind;
+            descriptor[1..6].copy_from_slice(b"CD001");
+            descriptor[6] = 1;
+            descriptor[88..88 + escape.len()].copy_from_slice(escape);
+            descriptor[128..130].copy_from_slice(&(SECTOR as u16).to_le_bytes());
+            if kind != 255 {
+                descriptor[156..190].copy_from_slice(&root_record);
+            }
+            sector += 1;
+        }
+
+        image
+    }
+
+    fn write_dir(image: &mut Vec<u8>, nodes: &[Node], parent: Option<u32>, rr: bool) -> u32 {
+        let block = allocate(image, 1);
+
+        // The Rock Ridge `SP` field is in the first record of the root directory.
+        let sp: &[u8] = if parent.is_none() && rr { b"SP\x07\x01\xBE\xEF\x00" } else { b"" };
+
+        let mut records = record(block, SECTOR as u32, FLAG_DIRECTORY, &[0], sp);
+        records.extend(record(parent.unwrap_or(block), SECTOR as u32, FLAG_DIRECTORY, &[1], &[]));
+
+        for node in nodes {
+            let (name, extent, len, flags, link) = match node {
+                Node::Dir(name, children) => {
+                    (name, write_dir(image, children, Some(block), rr), SECTOR, FLAG_DIRECTORY, "")
+                }
+                Node::File(name, data) => {
+                    let extent = allocate(image, (data.len() + SECTOR - 1) / SECTOR);
+                    let start = extent as usize * SECTOR;
+                    image[start..start + data.len()].copy_from_slice(data);
+                    (name, extent, data.len(), 0, "")
+                }
+                Node::Link(name, target) => (name, 0, 0, 0, *target),
+            };
+
+            let (identifier, system_use) = if rr {
+                let mut system_use = vec![b'N', b'M', 5 + name.len() as u8, 1, 0];
+                system_use.extend_from_slice(name.as_bytes());
+
+                if !link.is_empty() {
+                    let mut components = Vec::new();
+                    for component in link.split('/') {
+                        components.extend_from_slice(&[0, component.len() as u8]);
+                        components.extend_from_slice(component.as_bytes());
+                    }
+
+                    system_use.extend_from_slice(&[b'S', b'L', 5 + components.len() as u8, 1, 0]);
+                    system_use.extend(components);
+                }
+
+                (name.to_ascii_uppercase().into_bytes(), system_use)
+            } else {
+                let identifier = name.encode_utf16().flat_map(|unit| unit.to_be_bytes().to_vec());
+                (identifier.collect(), Vec::new())
+            };
+
+            records.extend(record(extent, len as u32, flags, &identifier, &system_use));
+        }
+
+        assert!(records.len() <= SECTOR);
+        let start = block as usize * SECTOR;
+        image[start..start + records.len()].copy_from_slice(&records);
+
+        block
+    }
+
+    fn allocate(image: &mut Vec<u8>, sectors: usize) -> u32 {
+        let block = image.len() / SECTOR;
+        image.resize(image.len() + sectors.max(1) * SECTOR, 0);
+        block as u32
+    }
+
+    fn record(extent: u32, len: u32, flags: u8, identifier: &[u8], system_use: &[u8]) -> Vec<u8> {
+        let mut record = vec![0u8; 33];
+        record[2..6].copy_from_slice(&extent.to_le_bytes());
+        record[6..10].copy_from_slice(&extent.to_be_bytes());
+        record[10..14].copy_from_slice(&len.to_le_bytes());
+        record[14..18].copy_from_slice(&len.to_be_bytes());
+        record[25] = flags;
+        record[28] = 1;
+        record[31] = 1;
+        record[32] = identifier.len() as u8;
+        record.extend_from_slice(identifier);
+
+        if identifier.len() % 2 == 0 {
+            record.push(0);
+        }
+
+        record.extend_from_slice(system_use);
+
+        if record.len() % 2 == 1 {
+            record.push(0);
+        }
+
+        record[0] = record.len() as u8;
+        record
+    }
+
+    fn tree() -> Vec<Node> {
+        vec![
+            Node::Dir(".disk", vec![Node::File("info", b"Pop!_OS 21.04".to_vec())]),
+            Node::Dir("casper", vec![Node::File(
+                "filesystem.squashfs",
+                (0..5000u32).map(|byte| byte as u8).collect(),
+            )]),
+            Node::Dir("dists", vec![
+                Node::Dir("hirsute", vec![Node::File("Release", b"Suite: hirsute".to_vec())]),
+                Node::Link("stable", "hirsute"),
+            ]),
+            Node::File("md5sum.txt", b"checksums".to_vec()),
+            Node::Link("ubuntu", "."),
+        ]
+    }
+
+    #[test]
+    fn rock_ridge() {
+        let mut iso = Iso::new(Cursor::new(image(&tree(), true))).unwrap();
+        assert_eq!(iso.names, Names::RockRidge(0));
+
+        let root = iso.read_dir(Path::new("/")).unwrap();
+        assert_eq!(root, vec![".disk", "casper", "dists", "md5sum.txt", "ubuntu"]);
+
+        assert_eq!(iso.stat(Path::new("casper")).unwrap(), None);
+        assert_eq!(iso.stat(Path::new("casper/filesystem.squashfs")).unwrap(), Some(5000));
+        assert_eq!(iso.read_to_string(Path::new("/.disk/info")).unwrap(), "Pop!_OS 21.04");
+
+        let mut squashfs = Vec::new();
+        iso.open_file(Path::new("casper/filesystem.squashfs"))
+            .unwrap()
+            .read_to_end(&mut squashfs)
+            .unwrap();
+        assert_eq!(squashfs, (0..5000u32).map(|byte| byte as u8).collect::<Vec<_>>());
+
+        // Symlinks are followed, including those which refer to their own directory.
+        assert_eq!(iso.read_dir(Path::new("dists/stable")).unwrap(), vec!["Release"]);
+        let release = iso.read_to_string(Path::new("ubuntu/dists/stable/Release")).unwrap();
+        assert_eq!(release, "Suite: hirsute");
+
+        let missing = iso.stat(Path::new("casper/vmlinuz.efi")).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn joliet() {
+        let tree = vec![Node::Dir("Casper", vec![Node::File("initrd.gz", b"initrd".to_vec())])];
+        let mut iso = Iso::new(Cursor::new(image(&tree, false))).unwrap();
+
+        assert_eq!(iso.names, Names::Joliet);
+        assert_eq!(iso.read_dir(Path::new("/")).unwrap(), vec!["Casper"]);
+        assert_eq!(iso.read_to_string(Path::new("Casper/initrd.gz")).unwrap(), "initrd");
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(plain_name(b"MD5SUM.TXT;1"), "md5sum.txt");
+        assert_eq!(plain_name(b"CASPER"), "casper");
+        assert_eq!(plain_name(b"README.;1"), "readme");
+        assert_eq!(joliet_name(&[0, b'a', 0, b'B', 0, b';', 0, b'1']), "aB");
+
+        let mut target = String::new();
+        let mut continued = false;
+        let records = b"\x08\x00\x00\x03usr\x01\x02sh\x00\x03are";
+        symlink_components(records, &mut target, &mut continued);
+        assert_eq!(target, "/usr/share");
+    }
+
+    #[test]
+    fn sync_iso() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut iso = Iso::new(Cursor::new(image(&tree(), true))).unwrap();
+
+        let transfers = [
+            (".disk", dir.path().join(".disk")),
+            ("dists", dir.path().join("dists")),
+            ("casper", dir.path().join("casper-ABCD")),
+        ];
+
+        let transfers: Vec<_> =
+            transfers.iter().map(|(from, to)| (Path::new(from).to_owned(), to.clone())).collect();
+
+        super::super::sync::sync(&mut iso, &transfers, &|_| ()).unwrap();
+
+        let squashfs = std::fs::read(dir.path().join("casper-ABCD/filesystem.squashfs")).unwrap();
+        assert_eq!(squashfs.len(), 5000);
+
+        let release = std::fs::read(dir.path().join("dists/stable/Release")).unwrap();
+        assert_eq!(release, b"Suite: hirsute");
+    }
+}
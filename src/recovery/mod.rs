@@ -1,24 +1,38 @@
+pub mod entry;
+pub mod stage;
+
+mod conf;
+mod delta;
 mod errors;
+mod iso;
+mod signature;
+mod sync;
+mod verify;
 mod version;
 
 use anyhow::Context;
-use async_process::Command;
 use futures::prelude::*;
 use std::{
-    io::SeekFrom,
+    fs, io,
     path::{Path, PathBuf},
     time::Instant,
 };
-use sys_mount::{Mount, MountFlags, Unmount, UnmountFlags};
 use tempfile::{tempdir, TempDir};
 
 use crate::{
-    checksum::validate_checksum, external::findmnt_uuid, release_api::Release,
-    release_architecture::detect_arch, system_environment::SystemEnvironment,
+    checksum::{validate_checksum, Checksum, HashingWriter},
+    config::Config, external::findmnt_uuid,
+    release::bootloader::BootloaderKind, release_api::Release, release_architecture::detect_arch,
+    system_environment::SystemEnvironment,
 };
 
 pub use self::{
+    conf::{RecoveryConf, RecoveryConfError, RecoveryMode, RECOVERY_CONF},
     errors::{RecResult, RecoveryError},
+    iso::{Iso, IsoEntry, IsoReader},
+    signature::SignatureError,
+    sync::{Source, SyncError, SyncProgress},
+    verify::{verify_integrity, RecoveryIntegrity, MD5SUMS},
     version::{recovery_file, version, RecoveryVersion, RecoveryVersionError, RECOVERY_VERSION},
 };
 
@@ -54,20 +68,18 @@ pub enum UpgradeMethod {
     FromRelease { version: Option<String>, arch: Option<String>, flags: ReleaseFlags },
 }
 
-pub async fn recovery<'a, F, E>(
+pub async fn recovery<'a, F, E, S>(
     cancel: &'a (dyn Fn() -> bool + Send + Sync),
     action: &'a UpgradeMethod,
     progress: F,
     event: E,
+    sync_progress: S,
 ) -> RecResult<()>
 where
     F: Fn(u64, u64) + 'static + Send + Sync,
     E: Fn(RecoveryEvent) + 'static,
+    S: Fn(SyncProgress) + 'static,
 {
-    if SystemEnvironment::detect() != SystemEnvironment::Efi {
-        return Err(RecoveryError::Unsupported);
-    }
-
     // Check the system and perform any repairs necessary for success.
     crate::repair::repair().await.map_err(RecoveryError::Repair)?;
 
@@ -91,7 +103,7 @@ where
     }
 
     if let Some((version, build)) =
-        fetch_iso(cancel, verify, &action, &progress, &event, "/recovery").await?
+        fetch_iso(cancel, verify, &action, &progress, &event, &sync_progress, "/recovery").await?
     {
         let data = fomat!((version) " " (build));
         async_fs::write(RECOVERY_VERSION, data.as_bytes())
@@ -121,6 +133,7 @@ async fn fetch_iso<'a, P: AsRef<Path>, F: Fn(u64, u64) + 'static + Send + Sync>(
     action: &'a UpgradeMethod,
     progress: &'a F,
     event: &'a dyn Fn(RecoveryEvent),
+    sync_progress: &'a dyn Fn(SyncProgress),
     recovery_path: P,
 ) -> RecResult<Option<(Box<str>, u16)>> {
     let recovery_path = recovery_path.as_ref();
@@ -131,32 +144,48 @@ async fn fetch_iso<'a, P: AsRef<Path>, F: Fn(u64, u64) + 'static + Send + Sync>(
         return Err(RecoveryError::RecoveryNotFound);
     }
 
-    let efi_path = Path::new("/boot/efi/EFI/");
-    if !efi_path.exists() {
+    // Legacy BIOS systems boot the recovery partition's kernel directly with GRUB.
+    let efi = SystemEnvironment::detect() == SystemEnvironment::Efi;
+
+    if efi && !Path::new(entry::EFI_DIR).exists() {
         return Err(RecoveryError::EfiNotFound);
     }
 
     let recovery_uuid =
         findmnt_uuid(recovery_path).await.context("cannot find UUID of recover partition")?;
 
-    let casper = ["casper-", &recovery_uuid].concat();
-    let recovery = ["Recovery-", &recovery_uuid].concat();
-    let efi_recovery = efi_path.join(&recovery);
+    let systemd_boot = efi && BootloaderKind::detect() == Some(BootloaderKind::SystemdBoot);
+    let staging = stage::Staging::new(recovery_path, &recovery_uuid, systemd_boot);
 
-    // TODO: Create recovery entry if it is missing
-    std::fs::create_dir_all(&efi_recovery).context("failed to create recovery entry directory")?;
+    // A previous upgrade may have been interrupted while switching to its staged files.
+    staging.recover();
 
     let mut temp_iso_dir = None;
     let (build, version, iso) = match action {
         UpgradeMethod::FromRelease { ref version, ref arch, flags } => {
-            let version_ = version.as_ref().map(String::as_str);
+            let version_ = match version {
+                Some(version) => Some(version.as_str()),
+                None if flags.contains(ReleaseFlags::NEXT) => Some(next_release()?),
+                None => None,
+            };
+
             let arch = arch.as_ref().map(String::as_str);
 
-            let (version, build) =
-                crate::release::check::current(version_).context("no build available")?;
+            let channel = Config::load().channel;
+            let (version, build) = crate::release::check::current(version_, channel)
+                .context("no build available")?;
 
             cancellation_check(&cancel)?;
 
+            if let Ok(current) = self::version() {
+                if is_downgrade(&current.version, &version) {
+                    return Err(RecoveryError::Downgrade {
+                        current:   current.version,
+                        requested: version.into(),
+                    });
+                }
+            }
+
             if verify(&version, build) {
                 info!("recovery partition is already upgraded to {}b{}", version, build);
                 return Ok(None);
@@ -164,9 +193,16 @@ async fn fetch_iso<'a, P: AsRef<Path>, F: Fn(u64, u64) + 'static + Send + Sync>(
 
             cancellation_check(&cancel)?;
 
-            let iso =
-                from_release(cancel, &mut temp_iso_dir, progress, event, &version, arch, *flags)
-                    .await?;
+            let iso = from_release(
+                cancel,
+                &mut temp_iso_dir,
+                progress,
+                event,
+                &version,
+                arch,
+                recovery_path,
+            )
+            .await?;
             (build, version, iso)
         }
         UpgradeMethod::FromFile(ref _path) => {
@@ -177,50 +213,56 @@ async fn fetch_iso<'a, P: AsRef<Path>, F: Fn(u64, u64) + 'static + Send + Sync>(
     cancellation_check(&cancel)?;
 
     (*event)(RecoveryEvent::Syncing);
-    let tempdir = tempfile::tempdir().map_err(RecoveryError::TempDir)?;
-    let _iso_mount = Mount::new(iso, tempdir.path(), "iso9660", MountFlags::RDONLY, None)
-        .context("failed to mount recovery ISO")?
-        .into_unmount_drop(UnmountFlags::DETACH);
-
-    let disk = tempdir.path().join(".disk");
-    let dists = tempdir.path().join("dists");
-    let pool = tempdir.path().join("pool");
-    let casper_p = tempdir.path().join("casper/");
-
-    let efi_initrd = efi_recovery.join("initrd.gz");
-    let efi_vmlinuz = efi_recovery.join("vmlinuz.efi");
-    let casper_initrd = recovery_path.join([&casper, "/initrd.gz"].concat());
-    let casper_vmlinuz = recovery_path.join([&casper, "/vmlinuz.efi"].concat());
-    let recovery_str = recovery_path.to_str().unwrap();
-
-    let mut cmd = cascade! {
-        Command::new("rsync");
-        ..args(&[&disk, &dists, &pool]);
-        ..arg(recovery_str);
-        ..args(&["-KLavc", "--inplace", "--delete"]);
-    };
 
-    cmd.status().await.context("rsync failed to copy")?;
+    // The ISO is read in-process, rather than loop-mounted.
+    let mut image = Iso::open(&iso).map_err(RecoveryError::IsoRead)?;
 
-    let mut cmd = cascade! {
-        Command::new("rsync");
-        ..args(&[&casper_p]);
-        ..arg(&[recovery_str, "/", &casper].concat());
-        ..args(&["-KLavc", "--inplace", "--delete"]);
-    };
+    let checksums = image.read_to_string(Path::new(MD5SUMS)).map_err(RecoveryError::IsoRead)?;
+    let source: &mut dyn Source = &mut image;
 
-    cmd.status().await.context("rsync failed to copy casper")?;
+    // Stage the kernel of the new release beside the current one, so that the recovery partition
+    // boots the current kernel until the new release has been validated and switched to.
+    staging.check_space(source)?;
 
-    let cp1 = crate::misc::cp(&casper_initrd, &efi_initrd);
-    let cp2 = crate::misc::cp(&casper_vmlinuz, &efi_vmlinuz);
+    if let Err(why) = staging.stage(source, &checksums, sync_progress).await {
+        staging.rollback();
+        return Err(why);
+    }
 
-    futures::try_join!(cp1, cp2).context("failed to copy kernel to recovery")?;
+    staging.switch()?;
+    staging.finish();
 
     (*event)(RecoveryEvent::Complete);
 
     Ok(Some((version, build)))
 }
 
+/// The release after the current release, if it has a build available.
+fn next_release() -> RecResult<&'static str> {
+    let status = crate::release::check::next(Config::load().channel, false)?;
+
+    if !status.build.is_ok() {
+        return Err(RecoveryError::NoBuildAvailable);
+    }
+
+    info!("upgrading the recovery partition to the next release, {}", status.next);
+
+    Ok(status.next)
+}
+
+/// Whether the requested release is older than the release of the recovery partition.
+fn is_downgrade(current: &str, requested: &str) -> bool {
+    fn parse(version: &str) -> Option<(u8, u8)> {
+        let mut fields = version.splitn(2, '.');
+        Some((fields.next()?.parse().ok()?, fields.next()?.parse().ok()?))
+    }
+
+    match (parse(current), parse(requested)) {
+        (Some(current), Some(requested)) => requested < current,
+        _ => false,
+    }
+}
+
 /// Fetches the release ISO remotely from api.pop-os.org.
 async fn from_release<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     cancel: &'a (dyn Fn() -> bool + Send + Sync),
@@ -229,15 +271,25 @@ async fn from_release<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     event: &'a dyn Fn(RecoveryEvent),
     version: &'a str,
     arch: Option<&'a str>,
-    _flags: ReleaseFlags,
+    seed: &'a Path,
 ) -> RecResult<PathBuf> {
     let arch = match arch {
         Some(ref arch) => arch,
         None => detect_arch()?,
     };
 
-    let release = Release::get_release(version, arch).map_err(RecoveryError::ApiError)?;
-    let iso_path = from_remote(cancel, temp, progress, event, &release.url, &release.sha_sum)
+    let release = Release::get_release(version, arch, Config::load().channel)
+        .map_err(RecoveryError::ApiError)?;
+
+    if let Err(why) = signature::verify(&release.url, &release.sha_sum).await {
+        if !why.is_unsigned() || Config::load().require_signatures {
+            return Err(RecoveryError::Signature(why));
+        }
+
+        warn!("the release ISO is not signed, and REQUIRE_SIGNATURES is disabled: {}", why);
+    }
+
+    let iso_path = from_remote(cancel, temp, progress, event, &release.url, &release.sha_sum, seed)
         .await
         .map_err(|why| RecoveryError::Download(Box::new(why)))?;
 
@@ -246,7 +298,9 @@ async fn from_release<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
 
 /// Downloads the ISO from a remote location, to a temporary local directory.
 ///
-/// Once downloaded, the ISO will be verfied against the given checksum.
+/// Once downloaded, the ISO will be verfied against the given checksum. If a block map is
+/// published for the ISO, the blocks which are already in the files of `seed` are reused, and
+/// only the remaining blocks are downloaded.
 async fn from_remote<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     cancel: &'a (dyn Fn() -> bool + Send + Sync),
     temp_dir: &'a mut Option<TempDir>,
@@ -254,31 +308,57 @@ async fn from_remote<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     event: &'a dyn Fn(RecoveryEvent),
     url: &'a str,
     checksum: &'a str,
+    seed: &'a Path,
 ) -> RecResult<PathBuf> {
     info!("downloading ISO from remote at {}", url);
     let temp = tempdir().map_err(RecoveryError::TempDir)?;
     let path = temp.path().join("new.iso");
 
-    let mut file = async_fs::OpenOptions::new()
+    match delta::fetch(cancel, progress, url, &path, seed).await {
+        Ok(true) => {
+            (*event)(RecoveryEvent::Verifying);
+
+            let mut file =
+                async_fs::File::open(&path).await.context("failed to open recovery ISO")?;
+
+            match validate_checksum(&mut file, checksum).await {
+                Ok(()) => {
+                    *temp_dir = Some(temp);
+                    return Ok(path);
+                }
+                Err(why) => warn!("ISO assembled from its block map is invalid: {}", why),
+            }
+        }
+        Ok(false) => (),
+        Err(RecoveryError::Cancelled) => return Err(RecoveryError::Cancelled),
+        Err(why) => {
+            warn!("failed to fetch ISO with its block map: {}", crate::misc::format_error(&why))
+        }
+    }
+
+    let expected = Checksum::parse(checksum)
+        .map_err(|source| RecoveryError::Checksum { path: path.clone(), source })?;
+
+    let file = async_fs::OpenOptions::new()
         .create(true)
         .write(true)
-        .read(true)
         .truncate(true)
         .open(&path)
         .await
         .context("failed to create ISO file for writing")?;
 
+    // The ISO is hashed as it is written, rather than read back from the disk afterwards.
+    let mut file = HashingWriter::new(file, expected.algorithm);
     let mut total = 0;
 
     (async {
         use isahc::config::Configurable;
 
-        let req = isahc::HttpClient::builder()
+        let request = isahc::Request::get(url)
             .low_speed_timeout(1, std::time::Duration::from_secs(15))
-            .build()
-            .expect("failed to build HTTP client")
-            .get_async(url)
-            .await?;
+            .body(())?;
+
+        let req = crate::http_client::for_url(url)?.send_async(request).await?;
 
         let status = req.status();
         if !status.is_success() {
@@ -328,15 +408,10 @@ async fn from_remote<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     (*progress)(total, total);
     (*event)(RecoveryEvent::Verifying);
 
-    async {
-        file.flush().await?;
-        file.seek(SeekFrom::Start(0)).await
-    }
-    .await
-    .context("failed to write recovery ISO")?;
+    file.close().await.context("failed to write recovery ISO")?;
 
-    validate_checksum(&mut file, checksum)
-        .await
+    expected
+        .check(&file.finalize())
         .map_err(|source| RecoveryError::Checksum { path: path.clone(), source })?;
 
     cancellation_check(cancel)?;
@@ -345,6 +420,16 @@ async fn from_remote<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     Ok(path)
 }
 
+/// Removes a file or directory, if it exists, without following symlinks.
+fn remove_path(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(why) => Err(why),
+    }
+}
+
 fn cancellation_check(cancel: &(dyn Fn() -> bool + Send + Sync)) -> RecResult<()> {
     if cancel() {
         Err(RecoveryError::Cancelled)
@@ -352,3 +437,17 @@ fn cancellation_check(cancel: &(dyn Fn() -> bool + Send + Sync)) -> RecResult<()
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downgrade() {
+        assert!(is_downgrade("21.04", "20.04"));
+        assert!(is_downgrade("21.10", "21.04"));
+        assert!(!is_downgrade("20.04", "21.04"));
+        assert!(!is_downgrade("21.04", "21.04"));
+        assert!(!is_downgrade("unknown", "20.04"));
+    }
+}
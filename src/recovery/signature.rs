@@ -0,0 +1,190 @@
+//! Verifies the checksum of a release ISO, as given by the release API, against the `SHA256SUMS`
+//! published beside the ISO. The `SHA256SUMS` must carry a detached signature in `SHA256SUMS.gpg`
+//! from a key in the Pop!_OS archive keyring, which is checked with `gpgv`.
+
+use crate::checksum::Checksum;
+use async_process::Command;
+use futures::prelude::*;
+use std::{io, path::Path};
+use tempfile::tempdir;
+use thiserror::Error;
+
+/// The Pop!_OS archive keyring, which is bundled with pop-upgrade so that ISOs are verified
+/// against the keys it was built with, rather than any key trusted by apt.
+pub const KEYRING: &str = "/usr/share/pop-upgrade/pop-archive-keyring.gpg";
+
+const SUMS: &str = "SHA256SUMS";
+const SIGNATURE: &str = "SHA256SUMS.gpg";
+
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    #[error("the signature of {} is not valid: {}", SUMS, _0)]
+    BadSignature(String),
+
+    #[error("failed to fetch {}", url)]
+    Fetch { url: String, source: anyhow::Error },
+
+    #[error("failed to run gpgv")]
+    Gpgv(#[source] io::Error),
+
+    #[error("the signed checksum of {} is {}, but the release API gave {}", iso, signed, api)]
+    Mismatch { iso: String, signed: String, api: String },
+
+    #[error("{} has no checksum for {}", SUMS, _0)]
+    NotListed(String),
+
+    #[error("{} is not published for this release", url)]
+    NotPublished { url: String },
+
+    #[error("failed to store {} for verification", SUMS)]
+    Temp(#[source] io::Error),
+}
+
+impl SignatureError {
+    /// Whether the release was not signed, rather than signed incorrectly.
+    pub fn is_unsigned(&self) -> bool { matches!(self, SignatureError::NotPublished { .. }) }
+}
+
+/// Checks that the checksum of the ISO at `url` is signed by the Pop!_OS archive key.
+pub async fn verify(url: &str, checksum: &str) -> Result<(), SignatureError> {
+    let (base, iso) = match url.rfind('/') {
+        Some(pos) => url.split_at(pos + 1),
+        None => ("", url),
+    };
+
+    let sums_url = [base, SUMS].concat();
+    let signature_url = [base, SIGNATURE].concat();
+
+    let sums = fetch(&sums_url).await?;
+    let signature = fetch(&signature_url).await?;
+
+    let temp = tempdir().map_err(SignatureError::Temp)?;
+    let sums_path = temp.path().join(SUMS);
+    let signature_path = temp.path().join(SIGNATURE);
+
+    async_fs::write(&sums_path, &sums).await.map_err(SignatureError::Temp)?;
+    async_fs::write(&signature_path, &signature).await.map_err(SignatureError::Temp)?;
+
+    gpgv(&signature_path, &sums_path).await?;
+
+    check_signed(&String::from_utf8_lossy(&sums), iso, checksum)?;
+
+    info!("the checksum of {} is signed by the Pop!_OS archive key", iso);
+
+    Ok(())
+}
+
+/// Checks that the checksum of the ISO in the signed `SHA256SUMS` matches the release API's.
+fn check_signed(sums: &str, iso: &str, checksum: &str) -> Result<(), SignatureError> {
+    let signed = find_checksum(sums, iso).ok_or_else(|| SignatureError::NotListed(iso.to_owned()))?;
+
+    if !same_checksum(signed, checksum) {
+        return Err(SignatureError::Mismatch {
+            iso:    iso.to_owned(),
+            signed: signed.to_owned(),
+            api:    checksum.to_owned(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Whether the checksums are the same digest, where either may be prefixed by its algorithm.
+fn same_checksum(signed: &str, api: &str) -> bool {
+    match (Checksum::parse(signed), Checksum::parse(api)) {
+        (Ok(signed), Ok(api)) => signed.algorithm == api.algorithm && signed.digest == api.digest,
+        _ => false,
+    }
+}
+
+async fn fetch(url: &str) -> Result<Vec<u8>, SignatureError> {
+    let error = |source: anyhow::Error| SignatureError::Fetch { url: url.to_owned(), source };
+
+    let response = crate::http_client::for_url(url)
+        .map_err(|why| error(why.into()))?
+        .get_async(url)
+        .await
+        .map_err(|why| error(why.into()))?;
+
+    let status = response.status();
+
+    if status == isahc::http::StatusCode::NOT_FOUND {
+        return Err(SignatureError::NotPublished { url: url.to_owned() });
+    } else if !status.is_success() {
+        return Err(error(anyhow!("server returned {}", status)));
+    }
+
+    let mut data = Vec::new();
+    response.into_body().read_to_end(&mut data).await.map_err(|why| error(why.into()))?;
+
+    Ok(data)
+}
+
+async fn gpgv(signature: &Path, sums: &Path) -> Result<(), SignatureError> {
+    let output = Command::new("gpgv")
+        .arg("--keyring")
+        .arg(KEYRING)
+        .arg(signature)
+        .arg(sums)
+        .output()
+        .await
+        .map_err(SignatureError::Gpgv)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(SignatureError::BadSignature(stderr.trim().to_owned()));
+    }
+
+    Ok(())
+}
+
+/// The checksum of a file in the output of `sha256sum`, which may be in binary mode.
+fn find_checksum<'a>(sums: &'a str, file: &str) -> Option<&'a str> {
+    sums.lines().find_map(|line| {
+        let mut fields = line.splitn(2, char::is_whitespace);
+        let checksum = fields.next()?;
+        let name = fields.next()?.trim_start();
+        let name = name.strip_prefix('*').unwrap_or(name);
+
+        if name == file {
+            Some(checksum)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        let sums = "0a1b  pop-os_21.04_amd64_intel_4.iso\n\
+                    2c3d *pop-os_21.04_amd64_nvidia_4.iso\n\
+                    malformed\n";
+
+        assert_eq!(find_checksum(sums, "pop-os_21.04_amd64_intel_4.iso"), Some("0a1b"));
+        assert_eq!(find_checksum(sums, "pop-os_21.04_amd64_nvidia_4.iso"), Some("2c3d"));
+        assert_eq!(find_checksum(sums, "pop-os_21.04_amd64_intel_5.iso"), None);
+    }
+
+    #[test]
+    fn signed_forms() {
+        let hex = "ab".repeat(32);
+        let iso = "pop-os_21.04_amd64_intel_4.iso";
+
+        let prefixed = fomat!("SHA256:" (hex) "  " (iso) "\n");
+        assert!(check_signed(&prefixed, iso, &hex).is_ok());
+
+        let binary = fomat!((hex.to_ascii_uppercase()) " *" (iso) "\n");
+        assert!(check_signed(&binary, iso, &hex).is_ok());
+        assert!(check_signed(&binary, iso, &fomat!("sha256:" (hex))).is_ok());
+
+        let other = fomat!(("cd".repeat(32)) " *" (iso) "\n");
+        assert!(matches!(check_signed(&other, iso, &hex), Err(SignatureError::Mismatch { .. })));
+
+        let md5 = fomat!("MD5:" ("ab".repeat(16)) "  " (iso) "\n");
+        assert!(matches!(check_signed(&md5, iso, &hex), Err(SignatureError::Mismatch { .. })));
+    }
+}
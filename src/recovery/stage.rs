@@ -0,0 +1,581 @@
+//! Upgrades the recovery partition to a new release, such that the previous kernel and initrd
+//! remain bootable until the new release has been synced and validated.
+//!
+//! A recovery partition cannot hold two copies of a release, so the casper image, the package
+//! archive, and the checksums of the ISO are synced in place, where only the blocks which differ
+//! are written. The kernel and initrd are staged to a `.new` directory beside the casper
+//! directory, and on systemd-boot, are copied to whichever of the two recovery directories of the
+//! EFI partition is not booted, along with a staged loader entry which boots them. Once every file
+//! has been validated against the checksums of the ISO, the current kernel and initrd are moved
+//! aside to `.old`, and the staged ones renamed into their place. On systemd-boot, the upgrade is
+//! committed by renaming the staged loader entry over the current one; until then, the `.old`
+//! files are renamed back if any step fails or is interrupted.
+//!
+//! The previous kernel and initrd are kept in a `.previous` directory, and on systemd-boot, in
+//! the recovery directory of the EFI partition which is no longer booted, until they are replaced
+//! by the next upgrade.
+
+use super::{
+    entry, remove_path, sync, verify, RecResult, RecoveryError, Source, SyncProgress, MD5SUMS,
+};
+use crate::misc::file_name;
+
+use anyhow::Context;
+use std::{
+    ffi::{CString, OsString},
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+const STAGED: &str = ".new";
+const PREVIOUS: &str = ".old";
+const FALLBACK: &str = ".previous";
+
+/// Files of the ISO which are synced in place, besides the casper image. The checksums are last,
+/// so that they are only updated once the files which they describe have been synced.
+const ISO_FILES: &[&str] = &[".disk", "dists", "pool", MD5SUMS];
+
+/// Files of casper which are staged rather than synced in place, and which are copied to the EFI
+/// partition for systemd-boot.
+const KERNEL_FILES: &[&str] = &["vmlinuz.efi", "initrd.gz"];
+
+pub struct Staging {
+    /// The root of the recovery partition.
+    root:   PathBuf,
+    /// The casper directory that the recovery partition boots from.
+    casper: PathBuf,
+    /// The loader entry and kernel directories of systemd-boot.
+    efi:    Option<Efi>,
+}
+
+struct Efi {
+    uuid:    String,
+    /// The directory that systemd-boot reads its boot entries from.
+    entries: PathBuf,
+    /// The directory of the EFI partition that holds the kernel directories.
+    dirs:    PathBuf,
+}
+
+impl Efi {
+    fn entry(&self) -> PathBuf { self.entries.join(entry::loader_entry_name(&self.uuid)) }
+
+    fn staged_entry(&self) -> PathBuf { with_suffix(&self.entry(), STAGED) }
+
+    /// The kernel directory which the current loader entry does not boot from.
+    fn inactive_dir(&self) -> String {
+        let current = fs::read_to_string(self.entry()).unwrap_or_default();
+        let active = entry::active_efi_dir(&current, &self.uuid);
+        let [first, second] = entry::efi_dir_names(&self.uuid);
+
+        if active == first {
+            second
+        } else {
+            first
+        }
+    }
+}
+
+impl Staging {
+    /// Stages the recovery partition at the path, where `systemd_boot` also stages its kernel in
+    /// the EFI partition.
+    pub fn new(recovery_path: &Path, uuid: &str, systemd_boot: bool) -> Self {
+        let esp = if systemd_boot {
+            Some((Path::new(entry::LOADER_ENTRIES), Path::new(entry::EFI_DIR)))
+        } else {
+            None
+        };
+
+        Self::with_paths(recovery_path, uuid, esp)
+    }
+
+    fn with_paths(recovery_path: &Path, uuid: &str, esp: Option<(&Path, &Path)>) -> Self {
+        Self {
+            root:   recovery_path.to_owned(),
+            casper: recovery_path.join(["casper-", uuid].concat()),
+            efi:    esp.map(|(entries, dirs)| Efi {
+                uuid:    uuid.to_owned(),
+                entries: entries.to_owned(),
+                dirs:    dirs.to_owned(),
+            }),
+        }
+    }
+
+    /// Completes or reverts an earlier upgrade which was interrupted, and removes its leftovers.
+    pub fn recover(&self) {
+        if self.is_committed() {
+            self.finish();
+        } else {
+            warn!("reverting an interrupted upgrade of the recovery partition");
+            self.rollback();
+        }
+    }
+
+    /// Fails unless the recovery and EFI partitions have room for the files which grow or are
+    /// added by the upgrade. The previous kernel and initrd are removed if that makes room.
+    pub fn check_space(&self, source: &mut dyn Source) -> RecResult<()> {
+        let mut required = 0;
+
+        for (from, to) in self.transfers(source)? {
+            required += sync::growth(source, &from, &to).map_err(RecoveryError::IsoRead)?;
+        }
+
+        let fallback = with_suffix(&self.casper, FALLBACK);
+
+        if ensure_space(&self.root, required).is_err() && fallback.exists() {
+            warn!("removing the previous recovery kernel to make room for the new release");
+            remove_path(&fallback)
+                .with_context(|| fomat!("failed to remove " (fallback.display())))?;
+        }
+
+        ensure_space(&self.root, required)?;
+
+        if let Some(ref efi) = self.efi {
+            let mut required = 0;
+
+            for file in KERNEL_FILES {
+                let path = Path::new("casper").join(file);
+                required += sync::size(source, &path).map_err(RecoveryError::IsoRead)?;
+            }
+
+            // The kernel directory which is not booted is replaced by the staged kernel.
+            let replaced = disk_usage(&efi.dirs.join(efi.inactive_dir())).unwrap_or(0);
+
+            ensure_space(&efi.dirs, required.saturating_sub(replaced))?;
+        }
+
+        Ok(())
+    }
+
+    /// Syncs the files of the ISO in place, and the kernel and initrd to their staged paths, and
+    /// validates them.
+    pub async fn stage(
+        &self,
+        source: &mut dyn Source,
+        checksums: &str,
+        progress: &dyn Fn(SyncProgress),
+    ) -> RecResult<()> {
+        let names = casper_names(source)?;
+
+        for entry in fs::read_dir(&self.casper).into_iter().flatten().filter_map(Result::ok) {
+            if !names.contains(&entry.file_name()) {
+                let path = entry.path();
+                info!("removing {}", path.display());
+                remove_path(&path).with_context(|| fomat!("failed to remove " (path.display())))?;
+            }
+        }
+
+        fs::create_dir_all(with_suffix(&self.casper, STAGED))
+            .context("failed to create the staged casper directory")?;
+
+        sync::sync(source, &self.transfers(source)?, progress)?;
+
+        let casper = file_name(&self.casper);
+
+        for (expected, path) in verify::parse_md5sums(checksums, &casper) {
+            let found = verify::md5sum(&self.synced_path(&path)).await.map_err(|why| {
+                RecoveryError::Anyhow(anyhow!("failed to checksum {}: {}", path, why))
+            })?;
+
+            if found != expected {
+                return Err(RecoveryError::Staging(path));
+            }
+        }
+
+        if let Some(ref efi) = self.efi {
+            let name = efi.inactive_dir();
+            let dir = efi.dirs.join(&name);
+            let staged_entry = efi.staged_entry();
+
+            fs::create_dir_all(&efi.entries)
+                .with_context(|| fomat!("failed to create " (efi.entries.display())))?;
+
+            // The staged entry marks the kernel directory as replaced, until it is committed.
+            write_synced(&staged_entry, &entry::loader_entry(&efi.uuid, &name))
+                .with_context(|| fomat!("failed to stage " (staged_entry.display())))?;
+
+            remove_path(&dir).with_context(|| fomat!("failed to remove " (dir.display())))?;
+            fs::create_dir_all(&dir).context("failed to create recovery entry directory")?;
+
+            for file in KERNEL_FILES {
+                let source = with_suffix(&self.casper, STAGED).join(file);
+                let dest = dir.join(file);
+
+                crate::misc::cp(&source, &dest)
+                    .await
+                    .with_context(|| fomat!("failed to stage " (dest.display())))?;
+
+                if verify::md5sum(&source).await.ok() != verify::md5sum(&dest).await.ok() {
+                    return Err(RecoveryError::Staging(dest.display().to_string()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves the current kernel and initrd aside, and the staged ones into their place, then
+    /// commits the upgrade. The previous files are restored if this fails.
+    pub fn switch(&self) -> RecResult<()> {
+        info!("switching the recovery partition to the staged release");
+
+        let _ = fs::create_dir_all(with_suffix(&self.casper, PREVIOUS));
+
+        for (current, staged, previous) in self.switched() {
+            if !staged.exists() {
+                continue;
+            }
+
+            let result = (|| {
+                remove_path(&previous)?;
+
+                if current.exists() {
+                    fs::rename(&current, &previous)?;
+                }
+
+                fs::rename(&staged, &current)
+            })();
+
+            if let Err(why) = result {
+                error!("failed to switch {}: {}", current.display(), why);
+                self.rollback();
+                return Err(RecoveryError::Staging(current.display().to_string()));
+            }
+        }
+
+        // The loader entry boots the staged kernel once it is renamed into place.
+        if let Some(ref efi) = self.efi {
+            let entry = efi.entry();
+
+            if let Err(why) = fs::rename(efi.staged_entry(), &entry) {
+                error!("failed to switch {}: {}", entry.display(), why);
+                self.rollback();
+                return Err(RecoveryError::Staging(entry.display().to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Restores the previous kernel and initrd, and removes the staged files, of an uncommitted
+    /// upgrade. The files which were synced in place are not restored.
+    pub fn rollback(&self) {
+        for (current, _, previous) in self.switched() {
+            if previous.exists() {
+                let result = remove_path(&current).and_then(|_| fs::rename(&previous, &current));
+
+                if let Err(why) = result {
+                    error!("failed to restore {}: {}", current.display(), why);
+                }
+            }
+        }
+
+        let _ = remove_path(&with_suffix(&self.casper, STAGED));
+
+        // Only removed once empty, so that a file which could not be restored is not lost.
+        let _ = fs::remove_dir(with_suffix(&self.casper, PREVIOUS));
+
+        if let Some(ref efi) = self.efi {
+            let staged_entry = efi.staged_entry();
+
+            if staged_entry.exists() {
+                let _ = remove_path(&efi.dirs.join(efi.inactive_dir()));
+                let _ = remove_path(&staged_entry);
+            }
+        }
+    }
+
+    /// Keeps the previous kernel and initrd in place of those of an earlier upgrade, and removes
+    /// the staged files, once the upgrade has been committed.
+    pub fn finish(&self) {
+        let previous = with_suffix(&self.casper, PREVIOUS);
+        let fallback = with_suffix(&self.casper, FALLBACK);
+        let staged = with_suffix(&self.casper, STAGED);
+
+        if previous.exists() {
+            let result = remove_path(&fallback).and_then(|_| fs::rename(&previous, &fallback));
+
+            if let Err(why) = result {
+                warn!("failed to keep {}: {}", previous.display(), why);
+            }
+        }
+
+        if let Err(why) = remove_path(&staged) {
+            warn!("failed to remove {}: {}", staged.display(), why);
+        }
+    }
+
+    /// Whether the current files are those which should be booted: on systemd-boot, once the
+    /// staged loader entry has replaced the current one, and otherwise, once no staged files
+    /// remain to be switched.
+    fn is_committed(&self) -> bool {
+        match self.efi {
+            Some(ref efi) => !efi.staged_entry().exists(),
+            None => self.switched().iter().all(|(_, staged, _)| !staged.exists()),
+        }
+    }
+
+    /// The files of the ISO, and the paths which they are synced to.
+    fn transfers(&self, source: &mut dyn Source) -> RecResult<Vec<(PathBuf, PathBuf)>> {
+        let staged = with_suffix(&self.casper, STAGED);
+
+        let casper = casper_names(source)?.into_iter().map(|name| {
+            let dest = if KERNEL_FILES.iter().any(|file| name == *file) {
+                staged.join(&name)
+            } else {
+                self.casper.join(&name)
+            };
+
+            (Path::new("casper").join(name), dest)
+        });
+
+        let iso = ISO_FILES.iter().map(|file| (PathBuf::from(file), self.root.join(file)));
+
+        Ok(casper.chain(iso).collect())
+    }
+
+    /// The path that a path relative to the recovery partition is synced to.
+    fn synced_path(&self, path: &str) -> PathBuf {
+        let path = self.root.join(path);
+
+        match path.strip_prefix(&self.casper) {
+            Ok(file) if KERNEL_FILES.iter().any(|name| file == Path::new(name)) => {
+                with_suffix(&self.casper, STAGED).join(file)
+            }
+            _ => path,
+        }
+    }
+
+    /// The kernel and initrd, their staged paths, and where they are moved aside to.
+    fn switched(&self) -> Vec<(PathBuf, PathBuf, PathBuf)> {
+        KERNEL_FILES
+            .iter()
+            .map(|file| {
+                let staged = with_suffix(&self.casper, STAGED).join(file);
+                let previous = with_suffix(&self.casper, PREVIOUS).join(file);
+                (self.casper.join(file), staged, previous)
+            })
+            .collect()
+    }
+}
+
+/// The names of the files in the casper directory of the ISO.
+fn casper_names(source: &mut dyn Source) -> RecResult<Vec<OsString>> {
+    let mut names = source.read_dir(Path::new("casper")).map_err(RecoveryError::IsoRead)?;
+    names.sort();
+
+    // Names come from the image, and must not escape the casper directory when joined to it.
+    if let Some(name) = names.iter().find(|name| !sync::is_file_name(name)) {
+        return Err(sync::SyncError::InvalidName { path: Path::new("casper").join(name) }.into());
+    }
+
+    Ok(names)
+}
+
+fn ensure_space(path: &Path, required: u64) -> RecResult<()> {
+    let available = available_space(path).unwrap_or(0);
+
+    info!("staging at {} requires {} of {} bytes available", path.display(), required, available);
+
+    if required < available {
+        Ok(())
+    } else {
+        Err(RecoveryError::StagingSpace { path: path.to_owned(), required, available })
+    }
+}
+
+/// The total size of the files at the path.
+fn disk_usage(path: &Path) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut total = 0;
+
+    for entry in fs::read_dir(path)? {
+        total += disk_usage(&entry?.path())?;
+    }
+
+    Ok(total)
+}
+
+/// Writes a file, and flushes it to the disk before it is renamed into place.
+fn write_synced(path: &Path, contents: &str) -> io::Result<()> {
+    fs::write(path, contents)?;
+    fs::File::open(path)?.sync_all()
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn available_space(path: &Path) -> io::Result<u64> {
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, why))?;
+
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A recovery partition with one release installed, and the kernel of another staged.
+    fn staged(casper: &Path) {
+        fs::create_dir_all(casper).unwrap();
+        fs::create_dir_all(with_suffix(casper, STAGED)).unwrap();
+        fs::write(casper.join("filesystem.squashfs"), b"new").unwrap();
+
+        for file in KERNEL_FILES {
+            fs::write(casper.join(file), b"old").unwrap();
+            fs::write(with_suffix(casper, STAGED).join(file), b"new").unwrap();
+        }
+    }
+
+    fn read(path: PathBuf) -> Vec<u8> { fs::read(path).unwrap() }
+
+    #[test]
+    fn switch_and_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let casper = dir.path().join("casper-ABCD");
+        let staging = Staging::with_paths(dir.path(), "ABCD", None);
+        staged(&casper);
+
+        staging.switch().unwrap();
+
+        for file in KERNEL_FILES {
+            assert_eq!(read(casper.join(file)), b"new");
+            assert_eq!(read(dir.path().join("casper-ABCD.old").join(file)), b"old");
+        }
+
+        assert!(!dir.path().join("casper-ABCD.new/vmlinuz.efi").exists());
+
+        staging.rollback();
+
+        for file in KERNEL_FILES {
+            assert_eq!(read(casper.join(file)), b"old");
+        }
+
+        assert_eq!(read(casper.join("filesystem.squashfs")), b"new");
+        assert!(!dir.path().join("casper-ABCD.old").exists());
+        assert!(!dir.path().join("casper-ABCD.new").exists());
+    }
+
+    #[test]
+    fn recover() {
+        let dir = tempfile::tempdir().unwrap();
+        let casper = dir.path().join("casper-ABCD");
+        let fallback = dir.path().join("casper-ABCD.previous");
+        let staging = Staging::with_paths(dir.path(), "ABCD", None);
+
+        // Interrupted while switching, with the kernel moved aside.
+        staged(&casper);
+        fs::create_dir_all(dir.path().join("casper-ABCD.old")).unwrap();
+        fs::rename(casper.join("vmlinuz.efi"), dir.path().join("casper-ABCD.old/vmlinuz.efi"))
+            .unwrap();
+        staging.recover();
+
+        assert_eq!(read(casper.join("vmlinuz.efi")), b"old");
+        assert!(!dir.path().join("casper-ABCD.old").exists());
+        assert!(!dir.path().join("casper-ABCD.new").exists());
+        assert!(!fallback.exists());
+
+        // Interrupted after switching, so the previous kernel is kept.
+        staged(&casper);
+        staging.switch().unwrap();
+        staging.recover();
+
+        assert_eq!(read(casper.join("vmlinuz.efi")), b"new");
+        assert_eq!(read(fallback.join("vmlinuz.efi")), b"old");
+        assert!(!dir.path().join("casper-ABCD.old").exists());
+
+        // A failed upgrade keeps the previous kernel, which the next upgrade replaces.
+        staged(&casper);
+        staging.rollback();
+        assert_eq!(read(fallback.join("vmlinuz.efi")), b"old");
+
+        staged(&casper);
+        fs::write(casper.join("vmlinuz.efi"), b"current").unwrap();
+        staging.switch().unwrap();
+        staging.finish();
+
+        assert_eq!(read(casper.join("vmlinuz.efi")), b"new");
+        assert_eq!(read(fallback.join("vmlinuz.efi")), b"current");
+    }
+
+    #[test]
+    fn synced_paths() {
+        let staging = Staging::with_paths(Path::new("/recovery"), "ABCD", None);
+
+        let paths = [
+            ("casper-ABCD/vmlinuz.efi", "/recovery/casper-ABCD.new/vmlinuz.efi"),
+            ("casper-ABCD/initrd.gz", "/recovery/casper-ABCD.new/initrd.gz"),
+            ("casper-ABCD/filesystem.squashfs", "/recovery/casper-ABCD/filesystem.squashfs"),
+            ("pool/main/a.deb", "/recovery/pool/main/a.deb"),
+        ];
+
+        for &(path, synced) in &paths {
+            assert_eq!(staging.synced_path(path), Path::new(synced));
+        }
+    }
+
+    #[test]
+    fn loader_entry_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = dir.path().join("loader/entries");
+        let efi_dirs = dir.path().join("EFI");
+        let root = dir.path().join("recovery");
+        let casper = root.join("casper-ABCD");
+
+        let esp = Some((entries.as_path(), efi_dirs.as_path()));
+        let staging = Staging::with_paths(&root, "ABCD", esp);
+        let entry = entries.join("Recovery-ABCD.conf");
+
+        fs::create_dir_all(&entries).unwrap();
+        fs::create_dir_all(efi_dirs.join("Recovery-ABCD")).unwrap();
+        fs::write(&entry, entry::loader_entry("ABCD", "Recovery-ABCD")).unwrap();
+        fs::create_dir_all(efi_dirs.join("Recovery-ABCD-B")).unwrap();
+        staged(&casper);
+
+        // Failed before the kernel directory was replaced, which is kept.
+        staging.rollback();
+        assert!(efi_dirs.join("Recovery-ABCD-B").exists());
+
+        // Interrupted before the loader entry was switched.
+        staged(&casper);
+        fs::write(with_suffix(&entry, STAGED), entry::loader_entry("ABCD", "Recovery-ABCD-B"))
+            .unwrap();
+        fs::create_dir_all(root.join("casper-ABCD.old")).unwrap();
+        fs::rename(casper.join("initrd.gz"), root.join("casper-ABCD.old/initrd.gz")).unwrap();
+        fs::rename(root.join("casper-ABCD.new/initrd.gz"), casper.join("initrd.gz")).unwrap();
+        staging.recover();
+
+        assert_eq!(read(casper.join("initrd.gz")), b"old");
+        assert_eq!(read(entry.clone()), entry::loader_entry("ABCD", "Recovery-ABCD").as_bytes());
+        assert!(!efi_dirs.join("Recovery-ABCD-B").exists());
+        assert!(efi_dirs.join("Recovery-ABCD").exists());
+
+        // Committed by the loader entry, which boots from the other kernel directory.
+        fs::create_dir_all(efi_dirs.join("Recovery-ABCD-B")).unwrap();
+        fs::write(with_suffix(&entry, STAGED), entry::loader_entry("ABCD", "Recovery-ABCD-B"))
+            .unwrap();
+        staged(&casper);
+        staging.switch().unwrap();
+        staging.finish();
+
+        assert_eq!(read(casper.join("initrd.gz")), b"new");
+        assert_eq!(read(entry), entry::loader_entry("ABCD", "Recovery-ABCD-B").as_bytes());
+        assert!(efi_dirs.join("Recovery-ABCD").exists());
+        assert!(efi_dirs.join("Recovery-ABCD-B").exists());
+    }
+}
@@ -0,0 +1,483 @@
+//! Syncs the files of the recovery ISO to the recovery partition.
+//!
+//! Files are compared block by block, and only the blocks which differ are written, so that a
+//! recovery partition which is already mostly up to date is synced with few writes. Files and
+//! directories which do not exist in the source are deleted, and written files are flushed to
+//! disk before the sync is considered complete.
+
+use std::{
+    ffi::OsString,
+    fs, io,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    time::Instant,
+};
+use thiserror::Error;
+
+const BLOCK: usize = 64 * 1024;
+
+/// Where the files to sync are read from, such as an ISO image.
+pub trait Source {
+    /// The size of the file at the path, or `None` if it is a directory. Symlinks are followed.
+    fn stat(&mut self, path: &Path) -> io::Result<Option<u64>>;
+
+    /// The names of the files in the directory at the path.
+    fn read_dir(&mut self, path: &Path) -> io::Result<Vec<OsString>>;
+
+    /// Opens the file at the path for reading.
+    fn open_file<'a>(&'a mut self, path: &Path) -> io::Result<Box<dyn Read + 'a>>;
+}
+
+/// The progress of a sync, emitted as each file is started and periodically as it is synced.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncProgress {
+    /// The file which is currently being synced.
+    pub file:        String,
+    /// The number of files which have been synced.
+    pub files:       u32,
+    pub files_total: u32,
+    /// KiB of files which have been synced.
+    pub current:     u64,
+    pub total:       u64,
+}
+
+#[derive(Debug, Error)]
+pub enum SyncError {
+    #[error("failed to create directory {:?}", path)]
+    CreateDir { path: PathBuf, source: io::Error },
+
+    #[error("failed to sync {:?} to disk", path)]
+    Fsync { path: PathBuf, source: io::Error },
+
+    #[error("{:?} has a file name which is not a single path component", path)]
+    InvalidName { path: PathBuf },
+
+    #[error("failed to read {:?}", path)]
+    Read { path: PathBuf, source: io::Error },
+
+    #[error("failed to remove {:?}", path)]
+    Remove { path: PathBuf, source: io::Error },
+
+    #[error("failed to write {:?}", path)]
+    Write { path: PathBuf, source: io::Error },
+}
+
+impl SyncError {
+    /// The file that the error occurred on.
+    pub fn path(&self) -> &Path {
+        match self {
+            SyncError::CreateDir { path, .. }
+            | SyncError::Fsync { path, .. }
+            | SyncError::InvalidName { path }
+            | SyncError::Read { path, .. }
+            | SyncError::Remove { path, .. }
+            | SyncError::Write { path, .. } => path,
+        }
+    }
+}
+
+/// A file to be synced, and its size in bytes.
+struct Entry {
+    source: PathBuf,
+    dest:   PathBuf,
+    size:   u64,
+}
+
+/// A directory to be synced, and the names of the files in its source.
+struct Directory {
+    dest:  PathBuf,
+    names: Vec<OsString>,
+}
+
+/// Syncs each source to its destination, where a source may be a file or a directory.
+///
+/// Symlinks in the source are followed, and files in a destination directory which are absent
+/// from its source are removed.
+pub fn sync(
+    source: &mut dyn Source,
+    transfers: &[(PathBuf, PathBuf)],
+    progress: &dyn Fn(SyncProgress),
+) -> Result<(), SyncError> {
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+
+    for (from, to) in transfers {
+        plan(source, from, to, &mut files, &mut dirs)?;
+    }
+
+    let mut state = SyncProgress {
+        files_total: files.len() as u32,
+        total: files.iter().map(|entry| entry.size).sum::<u64>() / 1024,
+        ..SyncProgress::default()
+    };
+
+    let mut synced = 0;
+    let mut written = 0;
+
+    for dir in &dirs {
+        remove_extra(dir)?;
+    }
+
+    for entry in &files {
+        state.file = entry.dest.display().to_string();
+        progress(state.clone());
+
+        if sync_file(source, entry, &mut synced, &mut state, progress)? {
+            written += 1;
+        }
+
+        state.files += 1;
+    }
+
+    for dir in &dirs {
+        fsync_dir(&dir.dest)?;
+    }
+
+    state.current = state.total;
+    progress(state);
+
+    info!("synced {} files, of which {} were changed", files.len(), written);
+
+    Ok(())
+}
+
+/// The total size of the files at the path, following symlinks.
+pub fn size(source: &mut dyn Source, path: &Path) -> io::Result<u64> {
+    if let Some(size) = source.stat(path)? {
+        return Ok(size);
+    }
+
+    let mut total = 0;
+
+    for name in source.read_dir(path)? {
+        total += size(source, &path.join(name))?;
+    }
+
+    Ok(total)
+}
+
+/// The number of bytes which syncing the path adds to its destination: the size of each file which
+/// does not exist in the destination, and the growth of each which does.
+pub fn growth(source: &mut dyn Source, from: &Path, to: &Path) -> io::Result<u64> {
+    if let Some(size) = source.stat(from)? {
+        let existing = fs::metadata(to).ok().filter(|metadata| metadata.is_file());
+        return Ok(size.saturating_sub(existing.map_or(0, |metadata| metadata.len())));
+    }
+
+    let mut total = 0;
+
+    for name in source.read_dir(from)? {
+        total += growth(source, &from.join(&name), &to.join(&name))?;
+    }
+
+    Ok(total)
+}
+
+/// Collects the files to sync, and creates the destination directories which are missing.
+fn plan(
+    source: &mut dyn Source,
+    from: &Path,
+    to: &Path,
+    files: &mut Vec<Entry>,
+    dirs: &mut Vec<Directory>,
+) -> Result<(), SyncError> {
+    let read_error = |why| SyncError::Read { path: from.into(), source: why };
+
+    if let Some(size) = source.stat(from).map_err(read_error)? {
+        files.push(Entry { source: from.into(), dest: to.into(), size });
+        return Ok(());
+    }
+
+    if !to.is_dir() {
+        remove(to)?;
+        fs::create_dir_all(to)
+            .map_err(|why| SyncError::CreateDir { path: to.into(), source: why })?;
+    }
+
+    let mut names = source.read_dir(from).map_err(read_error)?;
+    names.sort();
+
+    // Names come from the image, and must not escape the destination when they are joined to it.
+    if let Some(name) = names.iter().find(|name| !is_file_name(name)) {
+        return Err(SyncError::InvalidName { path: from.join(name) });
+    }
+
+    for name in &names {
+        plan(source, &from.join(name), &to.join(name), files, dirs)?;
+    }
+
+    dirs.push(Directory { dest: to.into(), names });
+
+    Ok(())
+}
+
+/// Whether the name is a single normal path component, and so may not be `.`, `..`, or contain `/`.
+pub(super) fn is_file_name(name: &OsString) -> bool {
+    let mut components = Path::new(name).components();
+
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(component)), None) => component == name.as_os_str(),
+        _ => false,
+    }
+}
+
+/// Removes files in the destination directory which do not exist in the source.
+fn remove_extra(dir: &Directory) -> Result<(), SyncError> {
+    let read_error = |why| SyncError::Read { path: dir.dest.clone(), source: why };
+
+    for entry in fs::read_dir(&dir.dest).map_err(read_error)? {
+        let name = entry.map_err(read_error)?.file_name();
+
+        if !dir.names.contains(&name) {
+            let path = dir.dest.join(&name);
+            info!("removing {}", path.display());
+            remove(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the blocks of the destination which differ from the source.
+///
+/// Returns `true` if the destination was changed.
+fn sync_file(
+    source: &mut dyn Source,
+    entry: &Entry,
+    synced: &mut u64,
+    state: &mut SyncProgress,
+    progress: &dyn Fn(SyncProgress),
+) -> Result<bool, SyncError> {
+    let read_error = |why| SyncError::Read { path: entry.source.clone(), source: why };
+    let write_error = |why| SyncError::Write { path: entry.dest.clone(), source: why };
+
+    if entry.dest.is_dir() {
+        remove(&entry.dest)?;
+    }
+
+    let mut reader = source.open_file(&entry.source).map_err(read_error)?;
+
+    let mut dest = fs::OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .open(&entry.dest)
+        .map_err(write_error)?;
+
+    let mut source_buf = vec![0u8; BLOCK];
+    let mut dest_buf = vec![0u8; BLOCK];
+    let mut offset = 0u64;
+    let mut changed = false;
+    let mut last = Instant::now();
+
+    loop {
+        let read = read_block(&mut reader, &mut source_buf).map_err(read_error)?;
+
+        if read == 0 {
+            break;
+        }
+
+        let existing = read_block(&mut dest, &mut dest_buf[..read]).map_err(write_error)?;
+
+        if existing != read || source_buf[..read] != dest_buf[..read] {
+            dest.seek(SeekFrom::Start(offset))
+                .and_then(|_| dest.write_all(&source_buf[..read]))
+                .map_err(write_error)?;
+
+            changed = true;
+        }
+
+        offset += read as u64;
+        *synced += read as u64;
+
+        if last.elapsed().as_secs() > 1 {
+            last = Instant::now();
+            state.current = *synced / 1024;
+            progress(state.clone());
+        }
+    }
+
+    let len = dest.metadata().map_err(write_error)?.len();
+
+    if len != offset {
+        dest.set_len(offset).map_err(write_error)?;
+        changed = true;
+    }
+
+    if changed {
+        dest.sync_all().map_err(|why| SyncError::Fsync { path: entry.dest.clone(), source: why })?;
+    }
+
+    state.current = *synced / 1024;
+
+    Ok(changed)
+}
+
+/// Fills the buffer, unless the end of the file is reached first.
+fn read_block(reader: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(bytes) => read += bytes,
+            Err(ref why) if why.kind() == io::ErrorKind::Interrupted => (),
+            Err(why) => return Err(why),
+        }
+    }
+
+    Ok(read)
+}
+
+fn fsync_dir(path: &Path) -> Result<(), SyncError> {
+    fs::File::open(path)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|why| SyncError::Fsync { path: path.into(), source: why })
+}
+
+fn remove(path: &Path) -> Result<(), SyncError> {
+    super::remove_path(path).map_err(|why| SyncError::Remove { path: path.into(), source: why })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, collections::BTreeMap};
+
+    /// Files kept in memory, where directories are implied by the paths of their files.
+    struct Memory(BTreeMap<PathBuf, Vec<u8>>);
+
+    impl Source for Memory {
+        fn stat(&mut self, path: &Path) -> io::Result<Option<u64>> {
+            match self.0.get(path) {
+                Some(data) => Ok(Some(data.len() as u64)),
+                None if self.0.keys().any(|file| file.starts_with(path)) => Ok(None),
+                None => Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+
+        fn read_dir(&mut self, path: &Path) -> io::Result<Vec<OsString>> {
+            let mut names: Vec<OsString> = self
+                .0
+                .keys()
+                .filter_map(|file| file.strip_prefix(path).ok()?.iter().next())
+                .map(OsString::from)
+                .collect();
+
+            names.dedup();
+            Ok(names)
+        }
+
+        fn open_file<'a>(&'a mut self, path: &Path) -> io::Result<Box<dyn Read + 'a>> {
+            let data = self.0.get(path).ok_or(io::ErrorKind::NotFound)?;
+            Ok(Box::new(data.as_slice()))
+        }
+    }
+
+    #[test]
+    fn sync_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("dest");
+
+        let mut source = Memory(
+            vec![
+                (PathBuf::from("iso/pool/main/a.deb"), vec![1u8; BLOCK * 2 + 7]),
+                (PathBuf::from("iso/info"), b"Pop!_OS 21.04".to_vec()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        fs::create_dir_all(dest.join("dists")).unwrap();
+        fs::create_dir_all(dest.join("pool/main")).unwrap();
+        fs::write(dest.join("pool/main/a.deb"), vec![1u8; BLOCK * 3]).unwrap();
+        fs::write(dest.join("info"), b"Pop!_OS 20.10 and more").unwrap();
+        fs::write(dest.join("extra"), b"extra").unwrap();
+
+        let events = RefCell::new(Vec::new());
+        let progress = |progress: SyncProgress| events.borrow_mut().push(progress);
+
+        sync(&mut source, &[(PathBuf::from("iso"), dest.clone())], &progress).unwrap();
+
+        assert_eq!(fs::read(dest.join("info")).unwrap(), b"Pop!_OS 21.04");
+        assert_eq!(fs::read(dest.join("pool/main/a.deb")).unwrap(), vec![1u8; BLOCK * 2 + 7]);
+        assert!(!dest.join("extra").exists());
+        assert!(!dest.join("dists").exists());
+
+        let events = events.into_inner();
+        let last = events.last().unwrap();
+        assert_eq!(last.files, 2);
+        assert_eq!(last.files_total, 2);
+        assert_eq!(last.current, last.total);
+
+        assert_eq!(size(&mut source, Path::new("iso")).unwrap(), BLOCK as u64 * 2 + 20);
+    }
+
+    #[test]
+    fn growth_of_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("dest");
+
+        let mut source = Memory(
+            vec![
+                (PathBuf::from("iso/casper/filesystem.squashfs"), vec![0u8; 300]),
+                (PathBuf::from("iso/casper/vmlinuz.efi"), vec![0u8; 50]),
+                (PathBuf::from("iso/pool/b.deb"), vec![0u8; 20]),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        fs::create_dir_all(dest.join("casper")).unwrap();
+        fs::write(dest.join("casper/filesystem.squashfs"), vec![1u8; 200]).unwrap();
+        fs::write(dest.join("casper/vmlinuz.efi"), vec![1u8; 80]).unwrap();
+
+        // The squashfs grows by 100 bytes, the kernel shrinks, and the package is added.
+        assert_eq!(growth(&mut source, Path::new("iso"), &dest).unwrap(), 120);
+    }
+
+    #[test]
+    fn sync_single_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("md5sum.txt");
+
+        let mut source = Memory(
+            vec![(PathBuf::from("md5sum.txt"), b"checksums".to_vec())].into_iter().collect(),
+        );
+
+        sync(&mut source, &[(PathBuf::from("md5sum.txt"), dest.clone())], &|_| ()).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"checksums");
+
+        let error = sync(&mut source, &[(PathBuf::from("missing"), dest)], &|_| ());
+        assert!(matches!(error, Err(SyncError::Read { .. })));
+    }
+
+    /// A directory whose names are given as-is, as a malformed image may give them.
+    struct Names(Vec<&'static str>);
+
+    impl Source for Names {
+        fn stat(&mut self, _path: &Path) -> io::Result<Option<u64>> { Ok(None) }
+
+        fn read_dir(&mut self, _path: &Path) -> io::Result<Vec<OsString>> {
+            Ok(self.0.iter().map(OsString::from).collect())
+        }
+
+        fn open_file<'a>(&'a mut self, _path: &Path) -> io::Result<Box<dyn Read + 'a>> {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    }
+
+    #[test]
+    fn reject_invalid_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("dest");
+
+        for name in &["..", ".", "", "casper/../../etc", "/etc"] {
+            let mut source = Names(vec![*name]);
+            let error = sync(&mut source, &[(PathBuf::from("iso"), dest.clone())], &|_| ());
+            assert!(matches!(error, Err(SyncError::InvalidName { .. })), "{:?}", name);
+        }
+
+        assert!(!dir.path().join("etc").exists());
+    }
+}
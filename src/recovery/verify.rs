@@ -0,0 +1,192 @@
+//! Verifies the files of the recovery partition against the checksums of the ISO it was synced
+//! from, which are kept in the `md5sum.txt` of the partition.
+
+use super::{entry, RecResult, RecoveryError};
+use crate::{
+    checksum::{self, Algorithm},
+    external::findmnt_uuid,
+};
+
+use anyhow::Context;
+use async_fs::File;
+use std::{io, path::Path};
+
+/// The checksums of the files on the ISO, copied to the root of the recovery partition.
+pub const MD5SUMS: &str = "md5sum.txt";
+
+/// Directories of the ISO which are synced to the recovery partition.
+const SYNCED: &[&str] = &[".disk/", "dists/", "pool/", "casper/"];
+
+/// Files which were found to be missing or corrupt on the recovery partition.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecoveryIntegrity {
+    /// The partition has no checksums to verify its files with.
+    pub unverifiable: bool,
+    /// The number of files which were checked.
+    pub checked:      u32,
+    pub missing:      Vec<String>,
+    pub corrupt:      Vec<String>,
+}
+
+impl RecoveryIntegrity {
+    pub fn is_intact(&self) -> bool {
+        !self.unverifiable && self.missing.is_empty() && self.corrupt.is_empty()
+    }
+
+    fn check(&mut self, path: String, found: io::Result<String>, expected: &str) {
+        self.checked += 1;
+
+        match found {
+            Ok(ref found) if found == expected => (),
+            Ok(_) => self.corrupt.push(path),
+            Err(ref why) if why.kind() == io::ErrorKind::NotFound => self.missing.push(path),
+            Err(why) => {
+                error!("failed to checksum {}: {}", path, why);
+                self.corrupt.push(path);
+            }
+        }
+    }
+}
+
+/// Checks every file of the recovery partition against the checksums of its ISO, as well as
+/// the copies of the kernel and initrd in the EFI partition.
+///
+/// Progress is reported as the number of files checked, out of the total to check.
+pub async fn verify_integrity(
+    recovery_path: &Path,
+    progress: impl Fn(u32, u32),
+) -> RecResult<RecoveryIntegrity> {
+    if !recovery_path.exists() {
+        return Err(RecoveryError::RecoveryNotFound);
+    }
+
+    let checksums = match async_fs::read_to_string(recovery_path.join(MD5SUMS)).await {
+        Ok(checksums) => checksums,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => {
+            warn!("the recovery partition has no checksums to verify it with");
+            return Ok(RecoveryIntegrity { unverifiable: true, ..RecoveryIntegrity::default() });
+        }
+        Err(why) => return Err(RecoveryError::Anyhow(why.into())),
+    };
+
+    let uuid =
+        findmnt_uuid(recovery_path).await.context("cannot find UUID of recovery partition")?;
+    let casper = ["casper-", &uuid].concat();
+
+    info!("verifying the recovery partition at {}", recovery_path.display());
+
+    let checksums = parse_md5sums(&checksums, &casper);
+
+    // The kernel and initrd are booted from copies in the EFI partition on systemd-boot.
+    let efi_dir = entry::efi_dir(&uuid);
+    let efi_files: &[&str] = if efi_dir.exists() { &["vmlinuz.efi", "initrd.gz"] } else { &[] };
+
+    let total = (checksums.len() + efi_files.len()) as u32;
+    let mut integrity = RecoveryIntegrity::default();
+
+    for (expected, path) in checksums {
+        let found = md5sum(&recovery_path.join(&path)).await;
+        integrity.check(path, found, &expected);
+        progress(integrity.checked, total);
+    }
+
+    for file in efi_files {
+        let expected = md5sum(&recovery_path.join(&casper).join(file)).await;
+        let path = efi_dir.join(file);
+        let found = md5sum(&path).await;
+
+        if let Ok(expected) = expected {
+            integrity.check(path.display().to_string(), found, &expected);
+            progress(integrity.checked, total);
+        }
+    }
+
+    info!(
+        "checked {} files of the recovery partition: {} missing, {} corrupt",
+        integrity.checked,
+        integrity.missing.len(),
+        integrity.corrupt.len()
+    );
+
+    Ok(integrity)
+}
+
+/// The checksums of the synced files, and their paths relative to the recovery partition.
+pub(super) fn parse_md5sums(checksums: &str, casper: &str) -> Vec<(String, String)> {
+    checksums
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(2, char::is_whitespace);
+            let checksum = fields.next()?;
+            let path = fields.next()?.trim_start();
+            let path = path.strip_prefix("./").unwrap_or(path);
+
+            if !SYNCED.iter().any(|dir| path.starts_with(dir)) {
+                return None;
+            }
+
+            let path = match path.strip_prefix("casper/") {
+                Some(file) => [casper, "/", file].concat(),
+                None => path.to_owned(),
+            };
+
+            Some((checksum.to_ascii_lowercase(), path))
+        })
+        .collect()
+}
+
+pub(super) async fn md5sum(path: &Path) -> io::Result<String> {
+    let file = File::open(path).await?;
+    checksum::digest(file, Algorithm::Md5).await.map(hex::encode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn md5sums() {
+        let checksums = "d41d8cd98f00b204e9800998ecf8427e  ./.disk/info\n\
+                         0CC175B9C0F1B6A831C399E269772661  ./casper/vmlinuz.efi\n\
+                         92eb5ffee6ae2fec3ad71c777531578f  ./pool/main/l/linux/linux.deb\n\
+                         4a8a08f09d37b73795649038408b5f33  ./boot/grub/grub.cfg\n\
+                         malformed\n";
+
+        assert_eq!(parse_md5sums(checksums, "casper-ABCD"), vec![
+            ("d41d8cd98f00b204e9800998ecf8427e".into(), ".disk/info".into()),
+            ("0cc175b9c0f1b6a831c399e269772661".into(), "casper-ABCD/vmlinuz.efi".into()),
+            ("92eb5ffee6ae2fec3ad71c777531578f".into(), "pool/main/l/linux/linux.deb".into()),
+        ]);
+    }
+
+    #[test]
+    fn checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a");
+        std::fs::write(&path, b"a").unwrap();
+
+        let found = async_io::block_on(md5sum(&path)).unwrap();
+        assert_eq!(found, "0cc175b9c0f1b6a831c399e269772661");
+
+        let mut integrity = RecoveryIntegrity::default();
+        integrity.check("a".into(), Ok(found), "0cc175b9c0f1b6a831c399e269772661");
+        integrity.check("b".into(), async_io::block_on(md5sum(&dir.path().join("b"))), "");
+        integrity.check("c".into(), Ok("d41d8cd98f00b204e9800998ecf8427e".into()), "00");
+
+        assert_eq!(integrity.checked, 3);
+        assert_eq!(integrity.missing, vec!["b".to_owned()]);
+        assert_eq!(integrity.corrupt, vec!["c".to_owned()]);
+        assert!(!integrity.is_intact());
+    }
+
+    #[test]
+    fn unverifiable() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let integrity = async_io::block_on(verify_integrity(dir.path(), |_, _| ())).unwrap();
+
+        assert!(integrity.unverifiable);
+        assert_eq!(integrity.checked, 0);
+        assert!(!integrity.is_intact());
+    }
+}
@@ -20,7 +20,7 @@ pub enum RecoveryVersionError {
     #[error("recovery has unknown release codename")]
     Codename(#[from] ubuntu_version::CodenameParseError),
 
-    #[error("recovery partition is corrupt")]
+    #[error("recovery partition is corrupt: `pop-upgrade recovery verify` lists the damaged files")]
     Unknown,
 }
 
@@ -0,0 +1,49 @@
+//! Manages boot entries independently of the bootloader that the system was installed with.
+
+use super::{
+    grub::{Grub, GRUB_CFG},
+    systemd::{BootConf, LoaderEntry},
+    SYSTEMD_BOOT_LOADER,
+};
+use std::path::Path;
+
+/// The bootloaders which are able to boot into the recovery partition.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BootloaderKind {
+    SystemdBoot,
+    Grub,
+}
+
+impl BootloaderKind {
+    /// Detects the bootloader that the system boots with.
+    pub fn detect() -> Option<Self> {
+        if Path::new(SYSTEMD_BOOT_LOADER).exists() {
+            Some(BootloaderKind::SystemdBoot)
+        } else if Path::new(GRUB_CFG).exists() {
+            Some(BootloaderKind::Grub)
+        } else {
+            None
+        }
+    }
+}
+
+/// Boot entry management that is common to each bootloader.
+pub trait Bootloader {
+    /// The entry that is booted by default.
+    fn default_boot(&self) -> &str;
+
+    /// Boots the entry on the next boot only.
+    fn boot_once(&mut self, entry: LoaderEntry) -> anyhow::Result<()>;
+
+    /// Cancels a pending one-shot boot, and boots the current OS by default.
+    fn boot_cancel(&mut self) -> anyhow::Result<()>;
+}
+
+/// Loads the configuration of the bootloader that the system boots with.
+pub fn load() -> anyhow::Result<Box<dyn Bootloader>> {
+    match BootloaderKind::detect() {
+        Some(BootloaderKind::SystemdBoot) => Ok(Box::new(BootConf::load()?)),
+        Some(BootloaderKind::Grub) => Ok(Box::new(Grub::load()?)),
+        None => Err(anyhow!("neither systemd-boot nor GRUB were found")),
+    }
+}
@@ -1,4 +1,7 @@
-use crate::release_api::{ApiError, Release};
+use crate::{
+    release_api::{ApiError, Channel, Release},
+    release_architecture::detect_arch,
+};
 use anyhow::Context;
 use ubuntu_version::{Version, VersionError};
 
@@ -61,23 +64,34 @@ pub struct ReleaseStatus {
     pub next:    &'static str,
     pub build:   BuildStatus,
     pub is_lts:  bool,
+    /// The build was taken from the cache, because the release API could not be reached.
+    pub stale:   bool,
 }
 
 impl ReleaseStatus {
     pub fn is_lts(&self) -> bool { self.is_lts }
 }
 
-pub fn next(development: bool) -> Result<ReleaseStatus, VersionError> {
+/// The next release, where `development` permits upgrades to releases in development even if
+/// the channel does not.
+pub fn next(channel: Channel, development: bool) -> Result<ReleaseStatus, VersionError> {
+    let development = development || channel.allows_development();
+
     Version::detect().map(|current| {
-        next_(current, development, |build| Release::build_exists(build, "intel").into())
+        let arch = arch();
+
+        next_(current, development, |build| match Release::get_release(build, arch, channel) {
+            Ok(release) => (BuildStatus::Build(release.build), release.stale),
+            Err(why) => (BuildStatus::from(Err(why)), false),
+        })
     })
 }
 
-pub fn current(version: Option<&str>) -> anyhow::Result<(Box<str>, u16)> {
+pub fn current(version: Option<&str>, channel: Channel) -> anyhow::Result<(Box<str>, u16)> {
     info!("Checking for current release of {:?}", version);
 
     if let Some(version) = version {
-        let build = Release::build_exists(version, "intel")
+        let build = Release::build_exists(version, arch(), channel)
             .with_context(|| fomat!("failed to find build for "(version)))?;
 
         return Ok((version.into(), build));
@@ -86,12 +100,21 @@ pub fn current(version: Option<&str>) -> anyhow::Result<(Box<str>, u16)> {
     let current = Version::detect().context("cannot detect current version of Pop")?;
     let release_str = release_str(current.major, current.minor);
 
-    let build = Release::build_exists(release_str, "intel")
+    let build = Release::build_exists(release_str, arch(), channel)
         .with_context(|| fomat!("failed to find build for "(release_str)))?;
 
     Ok((release_str.into(), build))
 }
 
+/// The architecture of the builds for this system, which are assumed to be `intel` if the
+/// graphics hardware cannot be probed.
+fn arch() -> &'static str {
+    detect_arch().unwrap_or_else(|why| {
+        warn!("failed to detect the release architecture, assuming intel: {}", why);
+        "intel"
+    })
+}
+
 const BIONIC: &str = "18.04";
 const FOCAL: &str = "20.04";
 const GROOVY: &str = "20.10";
@@ -114,22 +137,24 @@ pub fn release_str(major: u8, minor: u8) -> &'static str {
 fn next_(
     current: Version,
     development: bool,
-    release_check: impl Fn(&str) -> BuildStatus,
+    release_check: impl Fn(&str) -> (BuildStatus, bool),
 ) -> ReleaseStatus {
     // Enables a release upgrade from current to next, if a next ISO exists
     let available = |is_lts: bool, current: &'static str, next: &'static str| {
-        ReleaseStatus { build: release_check(next), current, is_lts, next }
+        let (build, stale) = release_check(next);
+        ReleaseStatus { build, current, is_lts, next, stale }
     };
 
     // Disables any form of upgrades from occurring on this release
     let blacklisted = |is_lts: bool, current: &'static str, next: &'static str| {
-        ReleaseStatus { build: BuildStatus::Blacklisted, current, is_lts, next }
+        ReleaseStatus { build: BuildStatus::Blacklisted, current, is_lts, next, stale: false }
     };
 
     // Only permits an upgrade if the development flag is passed
     let development_enabled = |is_lts: bool, current: &'static str, next: &'static str| {
-        let build = if development { release_check(next) } else { BuildStatus::Blacklisted };
-        ReleaseStatus { build, current, is_lts, next }
+        let (build, stale) =
+            if development { release_check(next) } else { (BuildStatus::Blacklisted, false) };
+        ReleaseStatus { build, current, is_lts, next, stale }
     };
 
     match (current.major, current.minor) {
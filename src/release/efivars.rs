@@ -0,0 +1,194 @@
+//! Reads and writes the EFI variables of the systemd-boot loader through efivarfs.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+/// Where the kernel exposes the EFI variables.
+pub const EFIVARS: &str = "/sys/firmware/efi/efivars";
+
+/// Vendor GUID of the variables defined by the Boot Loader Interface.
+const LOADER_GUID: &str = "4a67b082-0a4c-41cf-b6c7-440b29bb8c4f";
+
+/// `EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS`
+const ATTRIBUTES: u32 = 0x07;
+
+/// Set in `LoaderFeatures` when the loader honors `LoaderEntryOneShot`.
+const FEATURE_ENTRY_ONESHOT: u64 = 1 << 3;
+
+const ENTRY_ONESHOT: &str = "LoaderEntryOneShot";
+const FEATURES: &str = "LoaderFeatures";
+
+// efivarfs marks variables as immutable, which must be cleared before replacing them.
+const FS_IOC_GETFLAGS: libc::c_ulong = 0x8008_6601;
+const FS_IOC_SETFLAGS: libc::c_ulong = 0x4008_6602;
+const FS_IMMUTABLE_FL: libc::c_int = 0x10;
+
+/// The loader variables of an efivarfs mount.
+pub struct EfiVars {
+    root: PathBuf,
+}
+
+impl Default for EfiVars {
+    fn default() -> Self { Self::new(EFIVARS) }
+}
+
+impl EfiVars {
+    /// Accesses the variables of an efivarfs mounted at `root`.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self { Self { root: root.into() } }
+
+    /// Whether the running loader is able to boot an entry once, without changing the default.
+    pub fn supports_oneshot(&self) -> bool {
+        self.get(FEATURES)
+            .ok()
+            .and_then(|data| decode_u64(&data))
+            .map_or(false, |features| features & FEATURE_ENTRY_ONESHOT != 0)
+    }
+
+    /// The entry that the loader will boot on the next boot only, if one is set.
+    pub fn oneshot(&self) -> io::Result<Option<String>> {
+        match self.get(ENTRY_ONESHOT) {
+            Ok(data) => Ok(Some(decode_string(&data))),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(why) => Err(why),
+        }
+    }
+
+    /// Instructs the loader to boot the entry on the next boot only.
+    pub fn set_oneshot(&self, entry: &str) -> io::Result<()> {
+        self.set(ENTRY_ONESHOT, &encode_string(entry))
+    }
+
+    /// Removes a pending one-shot boot entry, if one was set.
+    pub fn clear_oneshot(&self) -> io::Result<()> { self.remove(ENTRY_ONESHOT) }
+
+    fn path(&self, name: &str) -> PathBuf { self.root.join(fomat!((name) "-" (LOADER_GUID))) }
+
+    /// Reads the data of a variable, without its attributes.
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        let mut data = fs::read(self.path(name))?;
+
+        if data.len() < 4 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "EFI variable is truncated"));
+        }
+
+        Ok(data.split_off(4))
+    }
+
+    fn set(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        self.remove(name)?;
+
+        let mut buffer = Vec::with_capacity(4 + data.len());
+        buffer.extend_from_slice(&ATTRIBUTES.to_le_bytes());
+        buffer.extend_from_slice(data);
+
+        // efivarfs requires the attributes and data to be written in a single call.
+        let mut file = OpenOptions::new().write(true).create_new(true).open(self.path(name))?;
+        let written = file.write(&buffer)?;
+
+        if written != buffer.len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "EFI variable write was short"));
+        }
+
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        let path = self.path(name);
+
+        if !path.exists() {
+            return Ok(());
+        }
+
+        make_mutable(&path)?;
+        fs::remove_file(&path)
+    }
+}
+
+/// Clears the immutable flag that efivarfs sets on each variable.
+fn make_mutable(path: &Path) -> io::Result<()> {
+    let file = File::open(path)?;
+    let fd = file.as_raw_fd();
+    let mut flags: libc::c_int = 0;
+
+    // Other file systems, such as those used for testing, do not support the flags.
+    if unsafe { libc::ioctl(fd, FS_IOC_GETFLAGS, &mut flags) } != 0
+        || flags & FS_IMMUTABLE_FL == 0
+    {
+        return Ok(());
+    }
+
+    flags &= !FS_IMMUTABLE_FL;
+
+    if unsafe { libc::ioctl(fd, FS_IOC_SETFLAGS, &flags) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Loader strings are NUL-terminated UTF-16LE.
+fn encode_string(value: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity((value.len() + 1) * 2);
+
+    for unit in value.encode_utf16().chain(std::iter::once(0)) {
+        data.extend_from_slice(&unit.to_le_bytes());
+    }
+
+    data
+}
+
+fn decode_string(data: &[u8]) -> String {
+    let units = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect::<Vec<u16>>();
+
+    String::from_utf16_lossy(&units)
+}
+
+fn decode_u64(data: &[u8]) -> Option<u64> {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(data.get(..8)?);
+    Some(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings() {
+        let encoded = encode_string("Recovery-1A2B");
+        assert_eq!(&encoded[..4], &[b'R', 0, b'e', 0]);
+        assert_eq!(&encoded[encoded.len() - 2..], &[0, 0]);
+        assert_eq!(decode_string(&encoded), "Recovery-1A2B");
+    }
+
+    #[test]
+    fn oneshot() {
+        let root = tempfile::tempdir().unwrap();
+        let efivars = EfiVars::new(root.path());
+
+        assert!(!efivars.supports_oneshot());
+        assert_eq!(efivars.oneshot().unwrap(), None);
+
+        let features = [&ATTRIBUTES.to_le_bytes()[..], &0x0fu64.to_le_bytes()].concat();
+        fs::write(efivars.path(FEATURES), features).unwrap();
+        assert!(efivars.supports_oneshot());
+
+        efivars.set_oneshot("Recovery-1A2B").unwrap();
+        efivars.set_oneshot("Pop_OS-current").unwrap();
+
+        let data = fs::read(efivars.path(ENTRY_ONESHOT)).unwrap();
+        assert_eq!(&data[..4], &[0x07, 0, 0, 0]);
+        assert_eq!(efivars.oneshot().unwrap().as_deref(), Some("Pop_OS-current"));
+
+        efivars.clear_oneshot().unwrap();
+        assert_eq!(efivars.oneshot().unwrap(), None);
+    }
+}
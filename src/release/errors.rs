@@ -1,4 +1,8 @@
-use crate::{release_architecture::ReleaseArchError, repair::RepairError};
+use crate::{
+    recovery::{RecoveryConfError, RecoveryError},
+    release_architecture::ReleaseArchError,
+    repair::RepairError,
+};
 use std::io;
 use ubuntu_version::VersionError;
 
@@ -43,9 +47,15 @@ pub enum ReleaseError {
     #[error("unable to hold apt/dpkg lock files")]
     Lock(#[source] io::Error),
 
+    #[error("unable to connect to {}", _0.join(", "))]
+    NoConnection(Vec<String>),
+
     #[error("root is required for this action: rerun with `sudo`")]
     NotRoot,
 
+    #[error("offline upgrade failed: {}", _0)]
+    OfflineUpgrade(Box<str>),
+
     #[error("failed to switch Ubuntu repos to old-releases")]
     OldReleaseSwitch(#[source] io::Error),
 
@@ -62,14 +72,29 @@ pub enum ReleaseError {
     RecoveryConf(#[source] anyhow::Error),
 
     #[error("failed to open the recovery configuration file")]
-    RecoveryConfOpen(#[source] io::Error),
+    RecoveryConfOpen(#[source] RecoveryConfError),
 
     #[error("failed to update the recovery configuration file")]
-    RecoveryUpdate(#[source] io::Error),
+    RecoveryUpdate(#[source] RecoveryConfError),
+
+    #[error("the {} recovery mode cannot be set directly", _0)]
+    RecoveryModeUnsupported(&'static str),
 
     #[error("recovery parttiion was not found")]
     RecoveryNotFound,
 
+    #[error("failed to upgrade the recovery partition to the new release")]
+    RecoveryUpgrade(#[source] RecoveryError),
+
+    #[error("failed to preserve the applications installed by the user")]
+    RefreshPreserve(#[source] anyhow::Error),
+
+    #[error("failed to reinstall the applications preserved for the refresh")]
+    RefreshRestore(#[source] anyhow::Error),
+
+    #[error("failed to reinstall the packages removed by the last upgrade")]
+    ReinstallRemoved(#[source] anyhow::Error),
+
     #[error("failed to fetch release architecture")]
     ReleaseArch(#[from] ReleaseArchError),
 
@@ -82,6 +107,9 @@ pub enum ReleaseError {
     #[error("failed to fetch release versions")]
     ReleaseVersion(#[from] VersionError),
 
+    #[error("the new release was installed, but the system failed verification")]
+    Verify(#[source] anyhow::Error),
+
     #[error("failed to apply system repair before upgrade")]
     Repair(#[from] RepairError),
 
@@ -97,6 +125,9 @@ pub enum ReleaseError {
     #[error("failed to perform apt upgrade of the current release")]
     Upgrade(#[source] io::Error),
 
+    #[error("packages were not fetched: {}", _0.join(", "))]
+    Unfetched(Vec<String>),
+
     #[error(
         "unable to install core packages: a package may be preventing pop-desktop from being \
          installed"
@@ -106,16 +137,16 @@ pub enum ReleaseError {
     #[error("failed to create /pop-upgrade file")]
     StartupFileCreation(#[source] io::Error),
 
-    #[error("failed to modify systemd-boot configuration: {}", _0)]
-    SystemdBoot(anyhow::Error),
+    #[error("failed to modify the bootloader configuration: {}", _0)]
+    Bootloader(anyhow::Error),
 
     #[error(
         "attempted recovery-based upgrade method, but the systemd efi loader path was not found"
     )]
     SystemdBootEfiPathNotFound,
 
-    #[error("attempted recovery-based upgrade method, but the systemd boot loader was not found")]
-    SystemdBootLoaderNotFound,
+    #[error("attempted recovery-based upgrade method, but no supported boot loader was found")]
+    BootloaderNotFound,
 
     #[error("failed to get transitional snap packages")]
     TransitionalSnapFetch(#[source] anyhow::Error),
@@ -126,6 +157,6 @@ pub enum ReleaseError {
     #[error("failed to record held transitional snap packages")]
     TransitionalSnapRecord(#[source] io::Error),
 
-    #[error("recovery entry not found in systemd-boot loader config")]
+    #[error("recovery entry not found in the bootloader config")]
     MissingRecoveryEntry,
 }
@@ -0,0 +1,139 @@
+//! Boot entry management for systems which boot with GRUB, such as legacy BIOS installs.
+
+use super::{bootloader::Bootloader, systemd::LoaderEntry};
+use crate::{external::findmnt_uuid, recovery::entry};
+
+use anyhow::Context;
+use as_result::MapResult;
+use std::{fs, io, path::Path, process::Command};
+
+pub const GRUB_CFG: &str = "/boot/grub/grub.cfg";
+
+const GRUB_ENV: &str = "/boot/grub/grubenv";
+
+/// Generates the menu entry for the recovery partition when `update-grub` is run.
+const RECOVERY_SCRIPT: &str = "/etc/grub.d/42_pop_recovery";
+
+/// The `--id` of the recovery partition's menu entry, given to `grub-reboot`.
+const RECOVERY_ID: &str = "pop-recovery";
+
+pub struct Grub {
+    default: String,
+}
+
+impl Grub {
+    const DEFAULT_BOOT: &'static str = "0";
+
+    pub fn load() -> anyhow::Result<Self> {
+        if !Path::new(GRUB_CFG).exists() {
+            return Err(anyhow!("GRUB configuration was not found at {}", GRUB_CFG));
+        }
+
+        let default = fs::read_to_string(GRUB_ENV)
+            .ok()
+            .and_then(|env| saved_entry(&env))
+            .unwrap_or_else(|| Self::DEFAULT_BOOT.to_owned());
+
+        Ok(Self { default })
+    }
+
+    /// Adds a menu entry for the recovery partition, if the menu is missing one.
+    fn ensure_recovery_entry(&self) -> anyhow::Result<()> {
+        let uuid = async_io::block_on(findmnt_uuid("/recovery"))
+            .context("cannot find UUID of recovery partition")?;
+
+        let script = recovery_script(&uuid);
+
+        if fs::read_to_string(RECOVERY_SCRIPT).ok().as_deref() == Some(script.as_str()) {
+            return Ok(());
+        }
+
+        info!("adding the recovery partition to the GRUB menu");
+
+        fs::write(RECOVERY_SCRIPT, script.as_bytes())
+            .and_then(|_| executable(Path::new(RECOVERY_SCRIPT)))
+            .with_context(|| fomat!("failed to write " (RECOVERY_SCRIPT)))?;
+
+        Command::new("update-grub").status().map_result().context("failed to run update-grub")
+    }
+}
+
+impl Bootloader for Grub {
+    fn default_boot(&self) -> &str { &self.default }
+
+    fn boot_once(&mut self, entry: LoaderEntry) -> anyhow::Result<()> {
+        match entry {
+            LoaderEntry::Current => self.boot_cancel(),
+            LoaderEntry::Recovery => {
+                self.ensure_recovery_entry()?;
+
+                info!("booting into {} on the next boot", RECOVERY_ID);
+                Command::new("grub-reboot")
+                    .arg(RECOVERY_ID)
+                    .status()
+                    .map_result()
+                    .context("failed to run grub-reboot")
+            }
+        }
+    }
+
+    fn boot_cancel(&mut self) -> anyhow::Result<()> {
+        Command::new("grub-editenv")
+            .args(&[GRUB_ENV, "unset", "next_entry"])
+            .status()
+            .map_result()
+            .context("failed to clear the next GRUB entry")
+    }
+}
+
+fn executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+/// The default entry stored in the GRUB environment block by `grub-set-default`.
+fn saved_entry(env: &str) -> Option<String> {
+    env.lines()
+        .find_map(|line| line.strip_prefix("saved_entry="))
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+}
+
+/// A `grub.d` script which boots the casper image of the recovery partition.
+fn recovery_script(uuid: &str) -> String {
+    fomat!(
+        "#!/bin/sh\n"
+        "exec tail -n +3 $0\n"
+        "menuentry '" (entry::TITLE) "' --id " (RECOVERY_ID) " {\n"
+        "    insmod part_gpt\n"
+        "    insmod part_msdos\n"
+        "    insmod fat\n"
+        "    insmod ext2\n"
+        "    search --no-floppy --fs-uuid --set=root " (uuid) "\n"
+        "    linux /casper-" (uuid) "/vmlinuz.efi " (entry::casper_options(uuid)) "\n"
+        "    initrd /casper-" (uuid) "/initrd.gz\n"
+        "}\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grubenv() {
+        let env = "# GRUB Environment Block\nsaved_entry=gnulinux-simple-1234\nnext_entry=\n####";
+        assert_eq!(saved_entry(env).as_deref(), Some("gnulinux-simple-1234"));
+        assert_eq!(saved_entry("# GRUB Environment Block\nsaved_entry=\n"), None);
+    }
+
+    #[test]
+    fn script() {
+        let script = recovery_script("ABCD-1234");
+        assert!(script.starts_with("#!/bin/sh\nexec tail -n +3 $0\nmenuentry"));
+        assert!(script.contains("--id pop-recovery {"));
+        assert!(script.contains("search --no-floppy --fs-uuid --set=root ABCD-1234\n"));
+        assert!(script.contains("linux /casper-ABCD-1234/vmlinuz.efi boot=casper"));
+        assert!(script.contains("live-media-path=/casper-ABCD-1234 "));
+    }
+}
@@ -0,0 +1,85 @@
+//! Installs the new release in the running system, rather than staging it for the next boot.
+
+use super::{
+    manifest::PackageDiff, verify::UpgradeReport, ReleaseError, RelResult, UpgradeEvent,
+    RELEASE_FETCH_FILE, REPAIR_ATTEMPTS, STARTUP_UPGRADE_FILE,
+};
+use crate::daemon::DaemonRuntime;
+use apt_cmd::{lock::apt_lock_wait, AptGet, AptMark, AptUpgradeEvent, Dpkg};
+use futures::prelude::*;
+use std::fs;
+
+impl DaemonRuntime {
+    /// Upgrades the running system to the new release, whose packages were already fetched.
+    pub(crate) async fn live_upgrade(
+        &mut self,
+        logger: &dyn Fn(UpgradeEvent),
+        upgrade: &dyn Fn(AptUpgradeEvent),
+        from: &str,
+        to: &str,
+    ) -> RelResult<()> {
+        (*logger)(UpgradeEvent::AttemptingLiveUpgrade);
+
+        // From this point, the new release sources must not be reverted on a restart.
+        let _ = fs::remove_file(RELEASE_FETCH_FILE);
+        let _ = fs::remove_file(STARTUP_UPGRADE_FILE);
+
+        // The daemon must not be restarted by its own upgrade while it is upgrading.
+        let _ = AptMark::new().hold(&["pop-upgrade"]).await;
+
+        let mut attempt = 0;
+
+        while let Err(why) = dist_upgrade(upgrade).await {
+            if attempt == REPAIR_ATTEMPTS {
+                return Err(ReleaseError::ReleaseUpgrade(why));
+            }
+
+            attempt += 1;
+
+            warn!("live upgrade failed: {}: repairing, attempt {}", why, attempt);
+
+            apt_lock_wait().await;
+            let _ = Dpkg::new().configure_all().status().await;
+
+            apt_lock_wait().await;
+            let _ = AptGet::new()
+                .noninteractive()
+                .fix_broken()
+                .allow_downgrades()
+                .force()
+                .status()
+                .await;
+        }
+
+        apt_lock_wait().await;
+        info!("autoremoving packages");
+        let _ =
+            AptGet::new().noninteractive().force().allow_downgrades().autoremove().status().await;
+
+        let _ = PackageDiff::complete().await;
+
+        let report = UpgradeReport::generate(from, to, None).await;
+
+        if let Err(why) = report.save() {
+            error!("failed to save the upgrade report: {}", why);
+        }
+
+        report.into_result()
+    }
+}
+
+async fn dist_upgrade(upgrade: &dyn Fn(AptUpgradeEvent)) -> std::io::Result<()> {
+    apt_lock_wait().await;
+    info!("performing live upgrade");
+
+    let (mut child, events) =
+        AptGet::new().noninteractive().allow_downgrades().force().stream_upgrade().await?;
+
+    futures_util::pin_mut!(events);
+
+    while let Some(event) = events.next().await {
+        upgrade(event);
+    }
+
+    child.status().await
+}
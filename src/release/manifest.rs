@@ -0,0 +1,503 @@
+//! Snapshots of the installed packages, which describe what an upgrade changed on the system.
+
+use super::{recovery, STARTUP_UPGRADE_FILE};
+use crate::recovery::RecoveryMode;
+use apt_cmd::{lock::apt_lock_wait, AptCache, AptGet};
+use async_process::Command;
+use futures::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    path::Path,
+};
+
+/// The packages installed before an upgrade which has yet to be compared to the system.
+pub const SNAPSHOT_FILE: &str = "/var/lib/pop-upgrade/package_snapshot.json";
+
+/// The changes made to the installed packages by the last upgrade.
+pub const DIFF_FILE: &str = "/var/lib/pop-upgrade/package_diff.json";
+
+const DPKG_STATUS: &str = "/var/lib/dpkg/status";
+const EXTENDED_STATES: &str = "/var/lib/apt/extended_states";
+
+/// Package states in which dpkg considers a package to not be installed.
+const NOT_INSTALLED: &[&str] = &["not-installed", "config-files"];
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Package {
+    pub version: String,
+    /// Whether the package was installed by the user, rather than as a dependency.
+    pub manual:  bool,
+}
+
+/// The packages installed on the system at the start of an operation.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Snapshot {
+    pub operation: String,
+    pub packages:  BTreeMap<String, Package>,
+}
+
+impl Snapshot {
+    /// Reads the installed packages from the dpkg status database.
+    pub async fn capture(operation: &str) -> io::Result<Self> {
+        let native = native_architecture().await?;
+        let status = fs::read_to_string(DPKG_STATUS)?;
+        let states = fs::read_to_string(EXTENDED_STATES).unwrap_or_default();
+
+        let auto = auto_installed(&states, &native);
+
+        let packages = installed(&status, &native)
+            .into_iter()
+            .map(|(name, version)| {
+                let manual = !auto.contains(name.as_str());
+                (name, Package { version, manual })
+            })
+            .collect();
+
+        Ok(Snapshot { operation: operation.to_owned(), packages })
+    }
+
+    /// Stores the packages installed before an operation, to be compared once it completes.
+    pub async fn record(operation: &str) {
+        let result = Self::capture(operation).await.and_then(|snapshot| {
+            let data = serde_json::to_vec(&snapshot).map_err(io::Error::from)?;
+            fs::write(SNAPSHOT_FILE, data)
+        });
+
+        if let Err(why) = result {
+            error!("failed to record the installed packages: {}", why);
+        }
+    }
+
+    fn load() -> Option<Self> {
+        let file = fs::File::open(SNAPSHOT_FILE).ok()?;
+
+        serde_json::from_reader(file)
+            .map_err(|why| error!("failed to parse the package snapshot: {}", why))
+            .ok()
+    }
+}
+
+/// A package whose installed version was changed.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PackageChange {
+    pub name: String,
+    /// The version installed before the operation, if the package was installed.
+    pub from: String,
+    /// The version installed after the operation, if the package is still installed.
+    pub to:   String,
+}
+
+/// The changes made to the installed packages by an operation.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct PackageDiff {
+    pub operation:      String,
+    pub added:          Vec<PackageChange>,
+    pub removed:        Vec<PackageChange>,
+    pub upgraded:       Vec<PackageChange>,
+    pub downgraded:     Vec<PackageChange>,
+    /// Packages installed by the user which were removed.
+    pub manual_removed: Vec<String>,
+}
+
+impl PackageDiff {
+    pub fn between(before: &Snapshot, after: &Snapshot) -> Self {
+        let mut diff = PackageDiff { operation: before.operation.clone(), ..Default::default() };
+
+        for (name, old) in &before.packages {
+            let new = match after.packages.get(name) {
+                Some(new) => new,
+                None => {
+                    if old.manual {
+                        diff.manual_removed.push(name.clone());
+                    }
+
+                    diff.removed.push(PackageChange {
+                        name: name.clone(),
+                        from: old.version.clone(),
+                        to:   String::new(),
+                    });
+
+                    continue;
+                }
+            };
+
+            let changes = match compare_versions(&new.version, &old.version) {
+                Ordering::Greater => &mut diff.upgraded,
+                Ordering::Less => &mut diff.downgraded,
+                Ordering::Equal => continue,
+            };
+
+            changes.push(PackageChange {
+                name: name.clone(),
+                from: old.version.clone(),
+                to:   new.version.clone(),
+            });
+        }
+
+        for (name, new) in &after.packages {
+            if !before.packages.contains_key(name) {
+                diff.added.push(PackageChange {
+                    name: name.clone(),
+                    from: String::new(),
+                    to:   new.version.clone(),
+                });
+            }
+        }
+
+        diff
+    }
+
+    /// Compares the recorded snapshot to the packages now installed, and stores the changes.
+    pub async fn complete() -> Option<Self> {
+        let before = Snapshot::load()?;
+        let _ = fs::remove_file(SNAPSHOT_FILE);
+
+        let after = match Snapshot::capture(&before.operation).await {
+            Ok(after) => after,
+            Err(why) => {
+                error!("failed to read the installed packages: {}", why);
+                return None;
+            }
+        };
+
+        let diff = Self::between(&before, &after);
+
+        info!(
+            "{}: {} added, {} removed, {} upgraded, {} downgraded",
+            diff.operation,
+            diff.added.len(),
+            diff.removed.len(),
+            diff.upgraded.len(),
+            diff.downgraded.len()
+        );
+
+        if let Err(why) = diff.save() {
+            error!("failed to save the package changes: {}", why);
+        }
+
+        Some(diff)
+    }
+
+    /// Completes the recorded snapshot, unless its upgrade is still waiting for a reboot.
+    pub async fn complete_pending() {
+        let staged = Path::new(STARTUP_UPGRADE_FILE).exists()
+            || recovery::mode_is(RecoveryMode::Upgrade).unwrap_or(false);
+
+        if !staged {
+            let _ = Self::complete().await;
+        }
+    }
+
+    /// Reads the changes made by the last upgrade, if there was one.
+    pub fn load() -> Option<Self> {
+        let file = fs::File::open(DIFF_FILE).ok()?;
+
+        serde_json::from_reader(file)
+            .map_err(|why| error!("failed to parse the package changes: {}", why))
+            .ok()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let data = serde_json::to_vec_pretty(self).map_err(io::Error::from)?;
+        fs::write(DIFF_FILE, data)
+    }
+}
+
+/// Reinstalls packages installed by the user which the last upgrade removed, if they are
+/// available from the current sources.
+///
+/// Returns the packages which were reinstalled.
+pub async fn reinstall_removed() -> anyhow::Result<Vec<String>> {
+    let mut diff = match PackageDiff::load() {
+        Some(diff) => diff,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut available = Vec::new();
+
+    for package in &diff.manual_removed {
+        if is_available(package).await {
+            available.push(package.as_str());
+        } else {
+            info!("{} is not available to reinstall", package);
+        }
+    }
+
+    if available.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    info!("reinstalling removed packages: {}", available.join(", "));
+
+    apt_lock_wait().await;
+    AptGet::new().noninteractive().install(&available).await?;
+
+    let reinstalled = available.into_iter().map(String::from).collect::<Vec<_>>();
+
+    diff.manual_removed.retain(|package| !reinstalled.contains(package));
+    diff.save()?;
+
+    Ok(reinstalled)
+}
+
+/// Compares two Debian package versions, as dpkg does.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a_upstream, a_revision) = split_version(a);
+    let (b_epoch, b_upstream, b_revision) = split_version(b);
+
+    a_epoch
+        .cmp(&b_epoch)
+        .then_with(|| compare_fragment(a_upstream, b_upstream))
+        .then_with(|| compare_fragment(a_revision, b_revision))
+}
+
+/// Splits a version into its epoch, upstream version, and Debian revision.
+fn split_version(version: &str) -> (u64, &str, &str) {
+    let (epoch, version) = match version.find(':') {
+        Some(pos) => (version[..pos].parse::<u64>().unwrap_or(0), &version[pos + 1..]),
+        None => (0, version),
+    };
+
+    match version.rfind('-') {
+        Some(pos) => (epoch, &version[..pos], &version[pos + 1..]),
+        None => (epoch, version, ""),
+    }
+}
+
+/// The sort weight of a non-digit character, where `~` sorts before everything, even the end.
+fn weight(byte: Option<u8>) -> i32 {
+    match byte {
+        None => 0,
+        Some(b'~') => -1,
+        Some(byte) if byte.is_ascii_digit() => 0,
+        Some(byte) if byte.is_ascii_alphabetic() => i32::from(byte),
+        Some(byte) => i32::from(byte) + 256,
+    }
+}
+
+/// Compares alternating runs of non-digits and digits, as in dpkg's `verrevcmp`.
+fn compare_fragment(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    let is_digit = |bytes: &[u8], pos: usize| bytes.get(pos).map_or(false, u8::is_ascii_digit);
+
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+            let (ac, bc) = (weight(a.get(i).copied()), weight(b.get(j).copied()));
+
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+
+            i += 1;
+            j += 1;
+        }
+
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+
+        let mut first_diff = Ordering::Equal;
+
+        while is_digit(a, i) && is_digit(b, j) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+
+            i += 1;
+            j += 1;
+        }
+
+        if is_digit(a, i) {
+            return Ordering::Greater;
+        }
+
+        if is_digit(b, j) {
+            return Ordering::Less;
+        }
+
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// The installed packages, and their versions, in a dpkg status database.
+///
+/// Packages of a foreign architecture are suffixed with their architecture.
+fn installed(status: &str, native: &str) -> BTreeMap<String, String> {
+    stanzas(status)
+        .filter_map(|fields| {
+            let package = field(&fields, "Package")?;
+            let state = field(&fields, "Status")?.split_whitespace().nth(2)?;
+
+            if NOT_INSTALLED.contains(&state) {
+                return None;
+            }
+
+            let name = package_name(package, field(&fields, "Architecture"), native);
+            Some((name, field(&fields, "Version")?.to_owned()))
+        })
+        .collect()
+}
+
+/// Packages which apt marked as installed automatically, as dependencies of other packages.
+fn auto_installed(states: &str, native: &str) -> HashSet<String> {
+    stanzas(states)
+        .filter(|fields| field(fields, "Auto-Installed") == Some("1"))
+        .filter_map(|fields| {
+            let package = field(&fields, "Package")?;
+            Some(package_name(package, field(&fields, "Architecture"), native))
+        })
+        .collect()
+}
+
+pub(crate) fn package_name(package: &str, arch: Option<&str>, native: &str) -> String {
+    match arch {
+        Some(arch) if arch != native && arch != "all" => [package, ":", arch].concat(),
+        _ => package.to_owned(),
+    }
+}
+
+/// The fields of each stanza in a control file, ignoring continuation lines.
+fn stanzas(content: &str) -> impl Iterator<Item = Vec<(&str, &str)>> {
+    content.split("\n\n").map(|stanza| {
+        stanza
+            .lines()
+            .filter(|line| !line.starts_with(' '))
+            .filter_map(|line| {
+                let mut fields = line.splitn(2, ':');
+                Some((fields.next()?, fields.next()?.trim()))
+            })
+            .collect()
+    })
+}
+
+fn field<'a>(fields: &[(&str, &'a str)], key: &str) -> Option<&'a str> {
+    fields.iter().find(|(name, _)| *name == key).map(|(_, value)| *value)
+}
+
+pub(crate) async fn native_architecture() -> io::Result<String> {
+    let output = Command::new("dpkg").arg("--print-architecture").output().await?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// Whether the package has a candidate to install from the current sources.
+pub(crate) async fn is_available(package: &str) -> bool {
+    let policies = AptCache::new().policy(&[package]).await;
+
+    let (mut child, policies) = match policies {
+        Ok(policies) => policies,
+        Err(_) => return false,
+    };
+
+    futures_util::pin_mut!(policies);
+
+    let available = match policies.next().await {
+        Some(policy) => policy.candidate != "(none)",
+        None => false,
+    };
+
+    let _ = child.status().await;
+
+    available
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = "Package: apt\n\
+                          Status: install ok installed\n\
+                          Architecture: amd64\n\
+                          Version: 2.0.2ubuntu0.1\n\
+                          Description: commandline package manager\n \
+                          This package provides commandline tools.\n\
+                          \n\
+                          Package: libc6\n\
+                          Status: install ok installed\n\
+                          Architecture: i386\n\
+                          Version: 2.31-0ubuntu9\n\
+                          \n\
+                          Package: old-package\n\
+                          Status: deinstall ok config-files\n\
+                          Architecture: amd64\n\
+                          Version: 1.0\n";
+
+    const STATES: &str = "Package: libc6\n\
+                          Architecture: i386\n\
+                          Auto-Installed: 1\n\
+                          \n\
+                          Package: apt\n\
+                          Architecture: amd64\n\
+                          Auto-Installed: 0\n";
+
+    #[test]
+    fn versions() {
+        assert_eq!(compare_versions("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.00", "1.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0", "1.0.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.0~rc1", "1.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.0a", "1.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1:0.9", "2.0"), Ordering::Greater);
+        assert_eq!(compare_versions("2.31-0ubuntu9", "2.31-0ubuntu10"), Ordering::Less);
+        assert_eq!(compare_versions("1.2+dfsg-1", "1.2-1"), Ordering::Greater);
+    }
+
+    #[test]
+    fn status() {
+        let mut expected = BTreeMap::new();
+        expected.insert("apt".to_owned(), "2.0.2ubuntu0.1".to_owned());
+        expected.insert("libc6:i386".to_owned(), "2.31-0ubuntu9".to_owned());
+
+        assert_eq!(installed(STATUS, "amd64"), expected);
+    }
+
+    #[test]
+    fn extended_states() {
+        let auto = auto_installed(STATES, "amd64");
+        assert!(auto.contains("libc6:i386"));
+        assert!(!auto.contains("apt"));
+    }
+
+    #[test]
+    fn diff() {
+        let package = |version: &str, manual| Package { version: version.into(), manual };
+        let change = |name: &str, from: &str, to: &str| PackageChange {
+            name: name.into(),
+            from: from.into(),
+            to:   to.into(),
+        };
+
+        let mut before = Snapshot { operation: "release upgrade".into(), ..Default::default() };
+        before.packages.insert("apt".into(), package("2.0.2", true));
+        before.packages.insert("firefox".into(), package("80.0", true));
+        before.packages.insert("gnome-software".into(), package("3.36", true));
+        before.packages.insert("libfoo".into(), package("1.0", false));
+
+        let mut after = Snapshot { operation: "release upgrade".into(), ..Default::default() };
+        after.packages.insert("apt".into(), package("2.1.20", true));
+        after.packages.insert("firefox".into(), package("79.0", true));
+        after.packages.insert("libbar".into(), package("1.0", false));
+
+        let diff = PackageDiff::between(&before, &after);
+
+        assert_eq!(diff.added, vec![change("libbar", "", "1.0")]);
+        assert_eq!(diff.removed, vec![
+            change("gnome-software", "3.36", ""),
+            change("libfoo", "1.0", ""),
+        ]);
+        assert_eq!(diff.upgraded, vec![change("apt", "2.0.2", "2.1.20")]);
+        assert_eq!(diff.downgraded, vec![change("firefox", "80.0", "79.0")]);
+        assert_eq!(diff.manual_removed, vec!["gnome-software".to_owned()]);
+    }
+}
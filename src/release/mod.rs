@@ -1,9 +1,17 @@
+pub mod bootloader;
 pub mod check;
+pub mod efivars;
 pub mod eol;
+pub mod grub;
+pub mod manifest;
+pub mod offline;
+pub mod preserve;
 pub mod repos;
 pub mod systemd;
+pub mod verify;
 
 mod errors;
+mod live;
 mod recovery;
 mod snapd;
 
@@ -14,7 +22,10 @@ pub use self::{
     errors::{RelResult, ReleaseError},
 };
 use crate::{
+    connectivity,
     daemon::DaemonRuntime,
+    fetch::mirrors::{self, Mirrors},
+    recovery::RecoveryMode,
     repair::{self, RepairError},
 };
 
@@ -27,7 +38,7 @@ use apt_cmd::{
 use futures::prelude::*;
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     convert::TryFrom,
     fs::{self, File},
     os::unix::fs::symlink,
@@ -63,6 +74,9 @@ const DPKG_LOCK: &str = "/var/lib/dpkg/lock";
 const LISTS_LOCK: &str = "/var/lib/apt/lists/lock";
 const RELEASE_FETCH_FILE: &str = "/pop_preparing_release_upgrade";
 const SYSTEM_UPDATE: &str = "/system-update";
+
+/// Number of times to repair the system and try again, when a release upgrade fails.
+const REPAIR_ATTEMPTS: u32 = 10;
 const SYSTEMD_BOOT_LOADER_PATH: &str = "/boot/efi/loader";
 const SYSTEMD_BOOT_LOADER: &str = "/boot/efi/EFI/systemd/systemd-bootx64.efi";
 
@@ -84,50 +98,88 @@ pub enum RefreshOp {
 
 /// Configure the system to refresh the OS in the recovery partition.
 pub fn refresh_os(op: RefreshOp) -> Result<bool, ReleaseError> {
-    recovery::upgrade_prereq()?;
-
-    let mut conf = systemd::BootConf::load().map_err(ReleaseError::RecoveryConf)?;
-
     match op {
         RefreshOp::Disable => {
             info!("Disabling refresh OS");
 
-            conf.set_default_boot_variant(LoaderEntry::Current)
-                .map_err(ReleaseError::SystemdBoot)?;
-
-            recovery::mode_unset().map_err(|why| ReleaseError::RecoveryConf(why.into()))?;
+            recovery::boot_cancel()?;
+            preserve::discard();
 
             Ok(false)
         }
         RefreshOp::Enable => {
             info!("Enabling refresh OS");
 
-            recovery::mode_set("refresh", conf.default_boot())
-                .map_err(|why| ReleaseError::RecoveryConf(why.into()))?;
+            recovery::upgrade_prereq()?;
+
+            // Applications installed by the user are removed by the refresh.
+            async_io::block_on(preserve::export()).map_err(ReleaseError::RefreshPreserve)?;
 
-            conf.set_default_boot_variant(LoaderEntry::Recovery)
-                .map_err(ReleaseError::SystemdBoot)?;
+            recovery::boot_into(RecoveryMode::Refresh)?;
 
             Ok(true)
         }
         RefreshOp::Status => {
             info!("Checking status of refresh OS");
 
-            recovery::mode_is("refresh")
+            recovery::upgrade_prereq()?;
+            recovery::mode_is(RecoveryMode::Refresh)
         }
     }
 }
 
+/// The mode that the recovery partition will boot into, if one is pending.
+pub fn recovery_mode() -> RelResult<Option<RecoveryMode>> {
+    recovery::upgrade_prereq()?;
+    recovery::mode()
+}
+
+/// Boots into the recovery partition in the given mode, on the next boot.
+///
+/// Refreshes and upgrades must be configured through `refresh_os` and `upgrade`, which prepare
+/// the system for them.
+pub fn recovery_mode_set(mode: RecoveryMode) -> RelResult<()> {
+    match mode {
+        RecoveryMode::Refresh | RecoveryMode::Upgrade => {
+            Err(ReleaseError::RecoveryModeUnsupported(mode.as_str()))
+        }
+        RecoveryMode::Install | RecoveryMode::Repair => {
+            info!("setting the recovery partition to boot into {} mode", mode.as_str());
+            recovery::boot_into(mode)
+        }
+    }
+}
+
+/// Cancels the mode that the recovery partition was going to boot into.
+pub fn recovery_mode_unset() -> RelResult<()> {
+    info!("unsetting the recovery mode");
+
+    recovery::boot_cancel()?;
+    preserve::discard();
+
+    Ok(())
+}
+
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
 pub enum UpgradeMethod {
     Offline = 1,
+    Live = 2,
+    Recovery = 3,
+}
+
+impl UpgradeMethod {
+    /// Upgrade methods which are supported by this build of the daemon.
+    pub const ALL: &'static [UpgradeMethod] =
+        &[UpgradeMethod::Offline, UpgradeMethod::Live, UpgradeMethod::Recovery];
 }
 
 impl From<UpgradeMethod> for &'static str {
     fn from(action: UpgradeMethod) -> Self {
         match action {
             UpgradeMethod::Offline => "offline upgrade",
+            UpgradeMethod::Live => "live upgrade",
+            UpgradeMethod::Recovery => "recovery upgrade",
         }
     }
 }
@@ -181,7 +233,7 @@ impl From<UpgradeEvent> for &'static str {
 }
 
 impl DaemonRuntime {
-    /// Get a list of APT URIs to fetch for this operation, and then fetch them.
+    /// Fetch each of the given APT URIs, falling back to other mirrors on failure.
     pub async fn apt_fetch<'a>(
         self: &'a mut Self,
         uris: HashSet<AptRequest>,
@@ -189,6 +241,20 @@ impl DaemonRuntime {
     ) -> RelResult<()> {
         (*func)(FetchEvent::Init(uris.len()));
 
+        let mut mirrors = Mirrors::load();
+
+        // Every package must be available from at least one reachable mirror.
+        let hosts = uris.iter().flat_map(|request| mirrors.candidates(&request.uri));
+        if let Err(unreachable) = connectivity::preflight(hosts.collect::<Vec<_>>()).await {
+            mirrors.mark_unreachable(&unreachable);
+
+            if !uris.iter().all(|request| mirrors.available(&request.uri)) {
+                return Err(ReleaseError::NoConnection(unreachable));
+            }
+
+            warn!("fetching from mirrors of unreachable hosts: {:?}", unreachable);
+        }
+
         apt_lock_wait().await;
         let _lock_files = hold_apt_locks()?;
 
@@ -197,11 +263,26 @@ impl DaemonRuntime {
 
         const CONCURRENT_FETCHES: usize = 4;
         const DELAY_BETWEEN: u64 = 100;
-        const RETRIES: u32 = 3;
 
-        let client = isahc::HttpClient::new().expect("failed to create HTTP Client");
+        // Quick retries of the same URI, before trying the next mirror.
+        const RETRIES: u32 = 1;
+
+        // Attempts across all mirrors, before giving up on a package.
+        const ATTEMPTS: u32 = 5;
+
+        if !Path::new(PARTIAL).exists() {
+            async_fs::create_dir_all(PARTIAL)
+                .await
+                .context("failed to create partial debian directory")
+                .map_err(ReleaseError::PackageFetch)?;
+        }
+
+        let client = crate::http_client::http()
+            .map_err(|why| ReleaseError::PackageFetch(why.into()))?
+            .clone();
 
-        let (fetch_tx, fetch_rx) = flume::bounded(CONCURRENT_FETCHES);
+        // Failed packages are sent back to the fetcher, so the channel must not block.
+        let (fetch_tx, fetch_rx) = flume::unbounded();
 
         use apt_cmd::fetch::{EventKind, PackageFetcher};
 
@@ -210,65 +291,114 @@ impl DaemonRuntime {
             .concurrent(CONCURRENT_FETCHES)
             .delay_between(DELAY_BETWEEN)
             .retries(RETRIES)
-            .fetch(fetch_rx.into_stream(), Arc::from(Path::new(PARTIAL)));
-
-        // The system which sends package-fetching requests
-        let sender = async move {
-            if !Path::new(PARTIAL).exists() {
-                async_fs::create_dir_all(PARTIAL)
-                    .await
-                    .context("failed to create partial debian directory")?;
-            }
+            .fetch(fetch_rx.into_stream(), Arc::from(Path::new(PARTIAL)))
+            .fuse();
 
-            let packages = AptGet::new()
-                .noninteractive()
-                .fetch_uris(&["full-upgrade"])
-                .await
-                .context("failed to spawn apt-get command")?
-                .context("failed to fetch package URIs from apt-get")?;
+        // Retries wait out their backoff here, so that other packages are fetched meanwhile.
+        let mut retries = stream::FuturesUnordered::new();
+
+        let mut pending: HashSet<String> =
+            uris.iter().map(|request| request.name.clone()).collect();
+        let mut attempts = HashMap::<String, u32>::new();
 
-            for package in packages {
-                info!("sending package");
-                let _ = fetch_tx.send_async(Arc::new(package)).await;
-                info!("sending package");
+        for mut request in uris {
+            if let Some(uri) = mirrors.candidates(&request.uri).into_iter().next() {
+                request.uri = uri;
             }
 
-            Ok::<(), anyhow::Error>(())
-        };
+            let _ = fetch_tx.send(Arc::new(request));
+        }
 
-        // The system that handles events received from the package-fetcher
-        let receiver = async move {
-            info!("receiving packages");
-            while let Some(event) = events.next().await {
-                debug!("Package Fetch Event: {:#?}", event);
+        // Handle events from the package-fetcher until every package has been fetched.
+        while !pending.is_empty() {
+            let event = futures::select! {
+                event = events.next() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+
+                request = retries.select_next_some() => {
+                    let _ = fetch_tx.send(request);
+                    continue;
+                }
+            };
 
-                match event.kind {
-                    EventKind::Fetching => {
-                        func(FetchEvent::Fetching((*event.package).clone()));
-                    }
+            debug!("Package Fetch Event: {:#?}", event);
+
+            match event.kind {
+                EventKind::Fetching => {
+                    func(FetchEvent::Fetching((*event.package).clone()));
+                }
 
-                    EventKind::Validated(src) => {
-                        let dst = Path::new(ARCHIVES).join(&event.package.name);
+                EventKind::Validated(src) => {
+                    let dst = Path::new(ARCHIVES).join(&event.package.name);
 
-                        async_fs::rename(&src, &dst)
-                            .await
-                            .context("failed to rename fetched debian package")?;
+                    async_fs::rename(&src, &dst)
+                        .await
+                        .context("failed to rename fetched debian package")
+                        .map_err(ReleaseError::PackageFetch)?;
 
-                        func(FetchEvent::Fetched((*event.package).clone()));
-                    }
+                    mirrors.succeeded(&event.package.uri);
+                    pending.remove(&event.package.name);
 
-                    EventKind::Error(why) => {
-                        return Err(why).context("package fetching failed");
-                    }
+                    func(FetchEvent::Fetched((*event.package).clone()));
+                }
+
+                EventKind::Error(why) => {
+                    let why = anyhow::Error::from(why);
+                    let package = &event.package;
+
+                    mirrors.failed(&package.uri);
+
+                    let attempt = attempts.entry(package.name.clone()).or_insert(0);
+                    *attempt += 1;
+
+                    let next = mirrors.candidates(&package.uri).into_iter().next();
+
+                    match next {
+                        Some(uri) if *attempt < ATTEMPTS => {
+                            let delay = mirrors::backoff(*attempt - 1);
+
+                            warn!(
+                                "fetching {} from {} failed: {}: retrying with {} in {:?}",
+                                package.name,
+                                package.uri,
+                                crate::misc::format_error(why.as_ref()),
+                                uri,
+                                delay
+                            );
+
+                            let mut request = (**package).clone();
+                            request.uri = uri;
 
-                    _ => (),
+                            retries.push(
+                                async_io::Timer::after(delay).map(move |_| Arc::new(request)),
+                            );
+                        }
+
+                        _ if connectivity::is_connection_error(why.as_ref()) => {
+                            return Err(ReleaseError::NoConnection(vec![package.uri.clone()]));
+                        }
+
+                        _ => {
+                            return Err(ReleaseError::PackageFetch(
+                                why.context("package fetching failed"),
+                            ));
+                        }
+                    }
                 }
+
+                _ => (),
             }
+        }
 
-            Ok::<(), anyhow::Error>(())
-        };
+        if !pending.is_empty() {
+            let mut pending: Vec<String> = pending.into_iter().collect();
+            pending.sort();
+            return Err(ReleaseError::Unfetched(pending));
+        }
 
-        futures::try_join!(sender, receiver).map(|_| ()).map_err(ReleaseError::PackageFetch)
+        Ok(())
     }
 
     /// Check if release files can be upgraded, and then overwrite them with the new release.
@@ -390,10 +520,9 @@ impl DaemonRuntime {
         let from_codename =
             Codename::try_from(from_version).expect("release doesn't have a codename");
 
-        // Ensure that prerequest files and mounts are available.
-        match action {
-            UpgradeMethod::Offline => systemd::upgrade_prereq()?,
-        }
+        upgrade_preflight(action).await?;
+
+        manifest::Snapshot::record(&fomat!("release upgrade from " (from) " to " (to))).await;
 
         let _ = AptMark::new().hold(&["pop-upgrade"]).await;
 
@@ -455,7 +584,9 @@ impl DaemonRuntime {
         // Update the package lists for the current release.
         apt_lock_wait().await;
         (logger)(UpgradeEvent::UpdatingPackageLists);
-        AptGet::new().noninteractive().update().await.map_err(ReleaseError::CurrentUpdate)?;
+        if let Err(why) = AptGet::new().noninteractive().update().await {
+            return Err(connection_lost(ReleaseError::CurrentUpdate(why)).await);
+        }
 
         // Fetch required packages for upgrading the current release.
         (*logger)(UpgradeEvent::FetchingPackages);
@@ -496,6 +627,12 @@ impl DaemonRuntime {
             )
         }
 
+        if action == UpgradeMethod::Live {
+            self.live_upgrade(logger, upgrade, from, to).await?;
+            (*logger)(UpgradeEvent::SuccessLive);
+            return Ok(());
+        }
+
         (*logger)(UpgradeEvent::Success);
         Ok(())
     }
@@ -568,7 +705,9 @@ impl DaemonRuntime {
             info!("updated the package lists for the new release");
             apt_lock_wait().await;
             (logger)(UpgradeEvent::UpdatingPackageLists);
-            AptGet::new().noninteractive().update().await.map_err(ReleaseError::ReleaseUpdate)?;
+            if let Err(why) = AptGet::new().noninteractive().update().await {
+                return Err(connection_lost(ReleaseError::ReleaseUpdate(why)).await);
+            }
 
             snapd::hold_transitional_packages().await?;
 
@@ -605,10 +744,27 @@ impl DaemonRuntime {
     }
 }
 
+/// Ensures that the files and mounts which the upgrade method requires are available, and that the
+/// required repositories and the release API are reachable.
+pub async fn upgrade_preflight(action: UpgradeMethod) -> RelResult<()> {
+    match action {
+        UpgradeMethod::Offline => systemd::upgrade_prereq()?,
+        UpgradeMethod::Live => (),
+        UpgradeMethod::Recovery => recovery::upgrade_prereq()?,
+    }
+
+    let mut required = required_sources();
+    required.push(connectivity::RELEASE_API.into());
+    connectivity::preflight(required).await.map_err(ReleaseError::NoConnection)
+}
+
 /// Currently not a supported path
 pub fn upgrade_finalize(action: UpgradeMethod, from: &str, to: &str) -> RelResult<()> {
     match action {
         UpgradeMethod::Offline => systemd::upgrade_set(from, to),
+        // The new release was already installed.
+        UpgradeMethod::Live => Ok(()),
+        UpgradeMethod::Recovery => recovery::upgrade_set(),
     }
 }
 
@@ -673,6 +829,29 @@ pub async fn cleanup() {
     }
 }
 
+/// Sources which must be reachable to upgrade the release.
+///
+/// If none of the required repositories are configured, all sources are required.
+fn required_sources() -> Vec<String> {
+    let sources = connectivity::apt_sources();
+    let required =
+        sources.iter().filter(|uri| is_required_ppa(uri)).cloned().collect::<Vec<String>>();
+
+    if required.is_empty() {
+        sources
+    } else {
+        required
+    }
+}
+
+/// Checks if an apt failure was caused by a loss of connectivity to the required repositories.
+async fn connection_lost(why: ReleaseError) -> ReleaseError {
+    match connectivity::preflight(required_sources()).await {
+        Ok(()) => why,
+        Err(hosts) => ReleaseError::NoConnection(hosts),
+    }
+}
+
 fn hold_apt_locks() -> RelResult<(File, File)> {
     File::open(LISTS_LOCK)
         .and_then(|lists| File::open(DPKG_LOCK).map(|dpkg| (lists, dpkg)))
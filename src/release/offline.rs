@@ -0,0 +1,479 @@
+//! Applies a staged offline upgrade from `system-update.target`, on the boot after it was staged.
+
+use super::{
+    repos, systemd, ReleaseError, RELEASE_FETCH_FILE, REPAIR_ATTEMPTS, STARTUP_UPGRADE_FILE,
+    SYSTEM_UPDATE,
+};
+use anyhow::Context;
+use apt_cmd::{AptGet, AptUpgradeEvent, Dpkg};
+use async_process::Command;
+use envfile::EnvFile;
+use futures::prelude::*;
+use std::{fs, io, path::Path, time::Duration};
+
+/// Records the outcome of the last offline upgrade, for the daemon to report on the next boot.
+pub const RESULT_FILE: &str = "/var/lib/pop-upgrade/offline_result";
+
+/// Records the releases of the upgrade being applied, to resume it after a reboot in the middle.
+const ATTEMPTED: &str = "/upgrade-attempted";
+
+/// Packages which must be configured before the rest of the upgrade.
+const PREINST: &[&str] = &["zlib1g", "libc6:i386", "libmount1:i386"];
+
+/// Services which must not run while the upgrade is being applied.
+const MASKED: &[&str] = &["acpid", "pop-upgrade"];
+
+const SYSTEMD_BOOT_EFI: &str = "\\EFI\\SYSTEMD\\SYSTEMD-BOOTX64.EFI";
+
+/// The releases of an upgrade which is to be applied.
+#[derive(Debug, PartialEq)]
+struct Staged {
+    from:  String,
+    to:    String,
+    /// The last attempt was interrupted before it completed.
+    retry: bool,
+}
+
+/// The outcome of an offline upgrade.
+#[derive(Debug)]
+pub struct OfflineResult {
+    pub from:  Box<str>,
+    pub to:    Box<str>,
+    pub error: Option<Box<str>>,
+}
+
+impl OfflineResult {
+    /// Reads the result of the last offline upgrade, if there is one.
+    pub fn read() -> Option<Self> { Self::read_from(Path::new(RESULT_FILE)) }
+
+    fn read_from(path: &Path) -> Option<Self> {
+        let env = EnvFile::new(path).ok()?;
+
+        Some(OfflineResult {
+            from:  env.get("FROM")?.into(),
+            to:    env.get("TO")?.into(),
+            error: env.get("ERROR").map(Box::from),
+        })
+    }
+
+    /// Reads the result of the last offline upgrade, and removes it so that it is reported once.
+    pub fn take() -> Option<Self> {
+        let result = Self::read();
+        let _ = fs::remove_file(RESULT_FILE);
+        result
+    }
+
+    fn write(&self) -> io::Result<()> { self.write_to(Path::new(RESULT_FILE)) }
+
+    fn write_to(&self, path: &Path) -> io::Result<()> {
+        let mut data = fomat!("FROM=" (self.from) "\nTO=" (self.to) "\n");
+
+        if let Some(ref why) = self.error {
+            data.push_str(&fomat!("ERROR=" (why.replace('\n', " ")) "\n"));
+        }
+
+        fs::write(path, data)
+    }
+}
+
+/// Applies the upgrade staged by `systemd::upgrade_set`, and then reboots the system.
+///
+/// On failure, the default boot entry is restored to the previous release, as are the source
+/// lists if no packages of the new release were applied.
+pub async fn apply() -> anyhow::Result<()> {
+    let Staged { from, to, retry } =
+        match begin(Path::new(STARTUP_UPGRADE_FILE), Path::new(ATTEMPTED)) {
+            Some(staged) => staged,
+            None => {
+                warn!("an offline upgrade has not been staged: resuming a normal boot");
+                let _ = fs::remove_file(SYSTEM_UPDATE);
+                return Ok(());
+            }
+        };
+
+    let _ = fs::remove_file(RELEASE_FETCH_FILE);
+
+    plymouth(&["display-message", "--text=system-updates"]).await;
+
+    // Packages of the new release have been applied if the last attempt was interrupted.
+    let mut applied = retry;
+
+    if applied {
+        message("System rebooted before upgrade was completed. Trying again").await;
+        async_io::Timer::after(Duration::from_secs(6)).await;
+    }
+
+    message("Installing Updates (0%)").await;
+
+    systemctl("mask", MASKED).await;
+
+    let result = match attempt_upgrade(&mut applied).await {
+        Ok(()) => {
+            finalize().await;
+            message("Upgrade complete. Now rebooting").await;
+            Ok(())
+        }
+        Err(why) => {
+            let why = crate::misc::format_error(why.as_ref());
+            error!("offline upgrade failed: {}", why);
+
+            plymouth(&["update", "--status=failed"]).await;
+            if applied {
+                message("Upgrade failed. Restarting").await;
+            } else {
+                message("Upgrade failed. Restoring the previous release, and restarting").await;
+            }
+            plymouth(&["update", "--status=normal"]).await;
+
+            rollback(&from, applied);
+            Err(why)
+        }
+    };
+
+    let result =
+        OfflineResult { from: from.into(), to: to.into(), error: result.err().map(Box::from) };
+
+    if let Err(why) = result.write() {
+        error!("failed to write the offline upgrade result to {}: {}", RESULT_FILE, why);
+    }
+
+    let _ = fs::remove_file(SYSTEM_UPDATE);
+    let _ = fs::remove_file(ATTEMPTED);
+
+    async_io::Timer::after(Duration::from_secs(6)).await;
+    plymouth(&["display-message", "--text=system-updates-stop"]).await;
+
+    unsafe {
+        libc::sync();
+    }
+
+    systemctl("unmask", MASKED).await;
+    systemctl("reboot", &[]).await;
+
+    Ok(())
+}
+
+/// Reads the upgrade to apply, and records it as attempted until the upgrade is finished.
+///
+/// The staged upgrade file is removed so that the daemon will not revert the apt sources once the
+/// upgrade begins. If a previous attempt was interrupted, the upgrade is read from its record.
+fn begin(staged: &Path, attempted: &Path) -> Option<Staged> {
+    let (data, retry) = match fs::read_to_string(staged) {
+        Ok(data) => (data, attempted.exists()),
+        Err(_) => (fs::read_to_string(attempted).ok()?, true),
+    };
+
+    let mut fields = data.split_whitespace();
+    let from = fields.next()?.to_owned();
+    let to = fields.next()?.to_owned();
+
+    if let Err(why) = fs::write(attempted, fomat!((from) " " (to))) {
+        warn!("failed to record the upgrade attempt at {}: {}", attempted.display(), why);
+    }
+
+    let _ = fs::remove_file(staged);
+
+    Some(Staged { from, to, retry })
+}
+
+/// Attempts the upgrade, and if it fails, repairs the system and tries again.
+///
+/// `applied` is set once packages of the new release have been installed.
+async fn attempt_upgrade(applied: &mut bool) -> anyhow::Result<()> {
+    let mut attempt = 0;
+
+    loop {
+        let why = match upgrade(applied).await {
+            Ok(()) => return Ok(()),
+            Err(why) => why,
+        };
+
+        if attempt == REPAIR_ATTEMPTS {
+            return Err(why);
+        }
+
+        attempt += 1;
+        warn!("upgrade failed: {}: attempting repair {}", why, attempt);
+        message("Upgrade failed: attempting to repair").await;
+    }
+}
+
+async fn upgrade(applied: &mut bool) -> anyhow::Result<()> {
+    message("Configuring packages").await;
+    Dpkg::new().configure_all().status().await.map_err(ReleaseError::DpkgConfigure)?;
+
+    message("Checking for package fixes").await;
+    apt_get().fix_broken().status().await.map_err(ReleaseError::FixBroken)?;
+
+    message("Installing Prerequisites").await;
+    for &package in PREINST {
+        if let Some(version) = installed_version(package).await {
+            apt_get()
+                .install(&[package])
+                .await
+                .with_context(|| fomat!("failed to install " (package)))?;
+
+            *applied |= installed_version(package).await.as_ref() != Some(&version);
+        }
+    }
+
+    let (mut child, events) =
+        apt_get().stream_upgrade().await.map_err(ReleaseError::ReleaseUpgrade)?;
+
+    futures_util::pin_mut!(events);
+
+    let mut percent = 0;
+
+    while let Some(event) = events.next().await {
+        let status = match event {
+            AptUpgradeEvent::Progress { percent: progress } => {
+                percent = progress;
+                plymouth(&["system-update", &fomat!("--progress=" (percent))]).await;
+                continue;
+            }
+            AptUpgradeEvent::Processing { package } => fomat!("Processing triggers for " (package)),
+            AptUpgradeEvent::SettingUp { package } => {
+                *applied = true;
+                fomat!("Setting up " (package))
+            }
+            AptUpgradeEvent::Unpacking { package, .. } => {
+                *applied = true;
+                fomat!("Unpacking " (package))
+            }
+            AptUpgradeEvent::WaitingOnLock => continue,
+        };
+
+        message(&fomat!("Installing Updates (" (percent) "%): " (status) " ...")).await;
+    }
+
+    child.status().await.map_err(ReleaseError::ReleaseUpgrade)?;
+
+    Ok(())
+}
+
+/// Cleans up after a successful upgrade.
+async fn finalize() {
+    message("Upgrade complete. Removing old kernels").await;
+    let _ = AptGet::new().noninteractive().force().remove(vec!["linux-image-*hwe*"]).await;
+
+    message("Upgrade complete. Now autoremoving old packages").await;
+    let _ = AptGet::new().noninteractive().force().autoremove().status().await;
+
+    message("Upgrade complete. Updating initramfs for all kernels").await;
+    let _ = Command::new("update-initramfs").args(&["-c", "-k", "all"]).status().await;
+
+    if let Err(why) = efi_rename().await {
+        warn!("failed to rename the EFI boot entry: {}", crate::misc::format_error(why.as_ref()));
+    }
+}
+
+/// Restores the default boot entry of the previous release, and its source lists if the upgrade
+/// has yet to apply any packages of the new release.
+fn rollback(from: &str, applied: bool) {
+    if let Err(why) = restore_sources(from, applied, repos::restore) {
+        error!("failed to restore source lists: {}", crate::misc::format_error(why.as_ref()));
+    }
+
+    if let Err(why) = systemd::restore_default() {
+        error!("failed to restore boot entry: {}", crate::misc::format_error(why.as_ref()));
+    }
+}
+
+/// Restoring the sources of the previous release on a partially-upgraded system would leave it
+/// unable to install the rest of the new release, so the sources are only restored if the upgrade
+/// did not apply any packages.
+fn restore_sources(
+    from: &str,
+    applied: bool,
+    restore: impl FnOnce(&str) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    if applied {
+        warn!("keeping the sources of the new release, because the upgrade was partially applied");
+        return Ok(());
+    }
+
+    restore(from)
+}
+
+/// Replaces the EFI boot entry of the current OS with one that is named after the new release.
+async fn efi_rename() -> anyhow::Result<()> {
+    if !Path::new("/sys/firmware/efi").exists() {
+        return Ok(());
+    }
+
+    let output = Command::new("efibootmgr").output().await.context("failed to run efibootmgr")?;
+    let entries = String::from_utf8_lossy(&output.stdout);
+
+    let current = entries
+        .lines()
+        .find_map(|line| line.strip_prefix("BootCurrent:"))
+        .map(str::trim)
+        .context("current boot entry not found")?;
+
+    let label = os_release::OsRelease::new().context("failed to read os-release")?.pretty_name;
+
+    let esp = proc_mounts::MountIter::new()
+        .context("failed to read mounts")?
+        .filter_map(Result::ok)
+        .find(|mount| mount.dest == Path::new("/boot/efi"))
+        .context("EFI system partition is not mounted")?;
+
+    let partition = esp
+        .source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("EFI system partition has no device name")?
+        .to_owned();
+
+    let disk = fs::read_dir("/sys/block")
+        .context("failed to read /sys/block")?
+        .filter_map(Result::ok)
+        .find(|block| block.path().join(&partition).exists())
+        .map(|block| Path::new("/dev").join(block.file_name()))
+        .context("disk of the EFI system partition not found")?;
+
+    let number = fs::read_to_string(["/sys/class/block/", &partition, "/partition"].concat())
+        .context("partition number of the EFI system partition not found")?;
+
+    Command::new("efibootmgr")
+        .args(&["-b", current, "-B"])
+        .status()
+        .await
+        .context("failed to remove the current boot entry")?;
+
+    Command::new("efibootmgr")
+        .args(&["-c", "-L", &label, "-d"])
+        .arg(&disk)
+        .args(&["-p", number.trim(), "-l", SYSTEMD_BOOT_EFI])
+        .status()
+        .await
+        .context("failed to create the new boot entry")?;
+
+    Ok(())
+}
+
+/// Packages are only installed from the archives fetched before the reboot, and may overwrite
+/// files of other packages.
+fn apt_get() -> AptGet {
+    let mut apt_get = AptGet::new().noninteractive().allow_downgrades().force();
+    apt_get.args(&["-o", "Dpkg::Options::=--force-overwrite", "--no-download", "--ignore-missing"]);
+    apt_get
+}
+
+async fn installed_version(package: &str) -> Option<String> {
+    let output = Command::new("dpkg-query")
+        .args(&["-W", "-f=${Status} ${Version}", package])
+        .output()
+        .await
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_installed(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The version of a package from `dpkg-query -f='${Status} ${Version}'`, if it is installed.
+fn parse_installed(query: &str) -> Option<String> {
+    let mut fields = query.split_whitespace();
+    let state = fields.nth(2)?;
+    let version = fields.next()?;
+
+    if state == "installed" {
+        Some(version.to_owned())
+    } else {
+        None
+    }
+}
+
+async fn message(text: &str) {
+    info!("{}", text);
+    plymouth(&["display-message", &["--text=", text].concat()]).await;
+}
+
+async fn plymouth(args: &[&str]) { let _ = Command::new("plymouth").args(args).status().await; }
+
+async fn systemctl(action: &str, units: &[&str]) {
+    let _ = Command::new("systemctl").arg(action).args(units).status().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn result() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("offline_result");
+
+        let result = OfflineResult {
+            from:  "20.04".into(),
+            to:    "21.04".into(),
+            error: Some("dpkg failed:\nexit status 1".into()),
+        };
+
+        result.write_to(&path).unwrap();
+
+        let read = OfflineResult::read_from(&path).unwrap();
+        assert_eq!((&*read.from, &*read.to), ("20.04", "21.04"));
+        assert_eq!(read.error.as_deref(), Some("dpkg failed: exit status 1"));
+
+        OfflineResult { error: None, ..result }.write_to(&path).unwrap();
+        assert!(OfflineResult::read_from(&path).unwrap().error.is_none());
+
+        fs::write(&path, "FROM=20.04\n").unwrap();
+        assert!(OfflineResult::read_from(&path).is_none());
+
+        assert!(OfflineResult::read_from(&dir.path().join("missing")).is_none());
+    }
+
+    #[test]
+    fn interrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let staged = dir.path().join("pop-upgrade");
+        let attempted = dir.path().join("upgrade-attempted");
+
+        assert_eq!(begin(&staged, &attempted), None);
+
+        fs::write(&staged, "focal groovy").unwrap();
+
+        let first = begin(&staged, &attempted).unwrap();
+        assert_eq!(first, Staged { from: "focal".into(), to: "groovy".into(), retry: false });
+        assert!(!staged.exists());
+
+        // The system was rebooted before the first attempt finished.
+        let second = begin(&staged, &attempted).unwrap();
+        assert_eq!(second, Staged { retry: true, ..first });
+
+        fs::remove_file(&attempted).unwrap();
+        assert_eq!(begin(&staged, &attempted), None);
+    }
+
+    #[test]
+    fn sources_rollback() {
+        let restored = Cell::new(None);
+
+        let restore = |release: &str| {
+            restored.set(Some(release.to_owned()));
+            Ok(())
+        };
+
+        restore_sources("20.04", true, restore).unwrap();
+        assert_eq!(restored.take(), None);
+
+        restore_sources("20.04", false, restore).unwrap();
+        assert_eq!(restored.take(), Some("20.04".to_owned()));
+
+        let failed = restore_sources("20.04", false, |_| Err(anyhow!("read-only file system")));
+        assert!(failed.is_err());
+    }
+
+    #[test]
+    fn installed() {
+        assert_eq!(parse_installed("install ok installed 1:1.2.11"), Some("1:1.2.11".into()));
+        assert_eq!(parse_installed("deinstall ok config-files 1:1.2.11"), None);
+        assert_eq!(parse_installed("unknown ok not-installed "), None);
+    }
+}
@@ -0,0 +1,381 @@
+//! Preserves the applications installed by the user in `/home`, which survives a refresh of
+//! the OS, so that they may be reinstalled on the refreshed system.
+
+use super::manifest::{self, Snapshot};
+use crate::{connectivity, external::findmnt_uuid, misc::file_name, recovery::RecoveryMode};
+use anyhow::Context;
+use apt_cmd::{lock::apt_lock_wait, AptGet};
+use async_process::Command;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Where the applications of the user are exported to before a refresh.
+pub const PRESERVE_DIR: &str = "/home/.pop-upgrade/refresh";
+
+const APPLICATIONS: &str = "applications.json";
+const KEYS_DIR: &str = "/etc/apt/trusted.gpg.d";
+const SOURCES_DIR: &str = "/etc/apt/sources.list.d";
+
+/// Sources which are provided by the OS, and which will be restored by the refresh.
+const SYSTEM_SOURCES: &[&str] = &["pop-os-apps.sources", "pop-os-ppa.list", "system.sources"];
+
+/// A flatpak application installed for all users, and the remote that it was installed from.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Flatpak {
+    pub remote:      String,
+    pub url:         String,
+    pub application: String,
+}
+
+/// Applications installed by the user, which a refresh of the OS will remove.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Preserved {
+    /// Packages which were installed manually.
+    pub packages: Vec<String>,
+    /// File names of the third party sources which were enabled.
+    pub sources:  Vec<String>,
+    pub flatpaks: Vec<Flatpak>,
+}
+
+impl Preserved {
+    /// Reads the applications exported before a refresh, if there are any.
+    pub fn load() -> Option<Self> {
+        let file = fs::File::open(Path::new(PRESERVE_DIR).join(APPLICATIONS)).ok()?;
+
+        serde_json::from_reader(file)
+            .map_err(|why| error!("failed to parse the preserved applications: {}", why))
+            .ok()
+    }
+
+    /// Preserved applications which are waiting to be restored on a refreshed system.
+    pub fn pending() -> Option<Self> {
+        if super::recovery::mode_is(RecoveryMode::Refresh).unwrap_or(false) {
+            None
+        } else {
+            Self::load()
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let data = serde_json::to_vec_pretty(self)?;
+        fs::write(Path::new(PRESERVE_DIR).join(APPLICATIONS), data)
+            .context("failed to write the preserved applications")
+    }
+}
+
+/// Exports the manually-installed packages, enabled third party sources, and system flatpaks.
+pub async fn export() -> anyhow::Result<()> {
+    let dir = Path::new(PRESERVE_DIR);
+
+    discard();
+
+    fs::create_dir_all(dir.join("sources")).context("failed to create the preserve directory")?;
+    fs::create_dir_all(dir.join("keys")).context("failed to create the preserve directory")?;
+
+    // Packages shipped by the recovery image are reinstalled by the refresh itself.
+    let baseline = baseline().await.unwrap_or_else(|why| {
+        warn!("failed to read the packages of the recovery image: {}", why);
+        HashSet::new()
+    });
+
+    let packages = Snapshot::capture("refresh")
+        .await
+        .context("failed to read the installed packages")?
+        .packages
+        .into_iter()
+        .filter(|(name, package)| package.manual && !baseline.contains(name))
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+
+    let mut sources = Vec::new();
+
+    for path in third_party_sources().context("failed to read the sources directory")? {
+        let name = file_name(&path);
+        fs::copy(&path, dir.join("sources").join(&name))
+            .with_context(|| fomat!("failed to preserve " (path.display())))?;
+        sources.push(name);
+    }
+
+    if let Ok(keys) = fs::read_dir(KEYS_DIR) {
+        let keys = keys.filter_map(Result::ok).map(|entry| entry.path());
+
+        for path in keys.filter(|path| path.is_file()) {
+            fs::copy(&path, dir.join("keys").join(file_name(&path)))
+                .with_context(|| fomat!("failed to preserve " (path.display())))?;
+        }
+    }
+
+    let flatpaks = system_flatpaks().await.unwrap_or_else(|why| {
+        warn!("failed to list system flatpaks: {}", why);
+        Vec::new()
+    });
+
+    info!(
+        "preserving {} packages, {} sources, and {} flatpaks for the refresh",
+        packages.len(),
+        sources.len(),
+        flatpaks.len()
+    );
+
+    Preserved { packages, sources, flatpaks }.save()
+}
+
+/// Prepares the preserved applications on the first boot of the refreshed system, so that the
+/// user may be offered to reinstall them.
+///
+/// Applications which the refreshed system already has are dropped from the offer, and the
+/// preserved applications are discarded if nothing is left to reinstall.
+pub async fn first_boot() -> anyhow::Result<()> {
+    let mut preserved = match Preserved::pending() {
+        Some(preserved) => preserved,
+        None => return Ok(()),
+    };
+
+    let installed = Snapshot::capture("refresh")
+        .await
+        .context("failed to read the installed packages")?
+        .packages;
+
+    preserved.packages.retain(|package| !installed.contains_key(package));
+
+    if let Ok(apps) = flatpak(&["list", "--system", "--app", "--columns=application"]).await {
+        let apps = apps.lines().map(str::trim).collect::<HashSet<_>>();
+        preserved.flatpaks.retain(|flatpak| !apps.contains(flatpak.application.as_str()));
+    }
+
+    if preserved.packages.is_empty() && preserved.flatpaks.is_empty() {
+        info!("the refreshed system already has the preserved applications");
+        discard();
+        return Ok(());
+    }
+
+    info!(
+        "offering to reinstall {} packages and {} flatpaks preserved before the refresh",
+        preserved.packages.len(),
+        preserved.flatpaks.len()
+    );
+
+    preserved.save()
+}
+
+/// Restores the sources, and reinstalls the packages and flatpaks, preserved before a refresh.
+///
+/// Returns the applications which could not be reinstalled.
+pub async fn restore() -> anyhow::Result<Vec<String>> {
+    let preserved = match Preserved::load() {
+        Some(preserved) => preserved,
+        None => return Ok(Vec::new()),
+    };
+
+    let dir = Path::new(PRESERVE_DIR);
+
+    if let Ok(keys) = fs::read_dir(dir.join("keys")) {
+        for path in keys.filter_map(Result::ok).map(|entry| entry.path()) {
+            let dest = Path::new(KEYS_DIR).join(file_name(&path));
+
+            if !dest.exists() {
+                fs::copy(&path, &dest)
+                    .with_context(|| fomat!("failed to restore " (dest.display())))?;
+            }
+        }
+    }
+
+    for source in &preserved.sources {
+        let dest = Path::new(SOURCES_DIR).join(source);
+
+        if !dest.exists() {
+            info!("restoring {}", dest.display());
+            fs::copy(dir.join("sources").join(source), &dest)
+                .with_context(|| fomat!("failed to restore " (dest.display())))?;
+        }
+    }
+
+    apt_lock_wait().await;
+    AptGet::new().noninteractive().update().await.context("failed to update source lists")?;
+
+    let mut unavailable = Vec::new();
+    let mut available = Vec::new();
+
+    for package in &preserved.packages {
+        if manifest::is_available(package).await {
+            available.push(package.as_str());
+        } else {
+            unavailable.push(package.clone());
+        }
+    }
+
+    if !available.is_empty() {
+        info!("reinstalling {} preserved packages", available.len());
+        apt_lock_wait().await;
+        AptGet::new()
+            .noninteractive()
+            .install(&available)
+            .await
+            .context("failed to reinstall the preserved packages")?;
+    }
+
+    for flatpak in &preserved.flatpaks {
+        if let Err(why) = install_flatpak(flatpak).await {
+            error!("failed to reinstall {}: {}", flatpak.application, why);
+            unavailable.push(flatpak.application.clone());
+        }
+    }
+
+    discard();
+
+    Ok(unavailable)
+}
+
+/// Removes the applications that were preserved for a refresh.
+pub fn discard() {
+    if Path::new(PRESERVE_DIR).exists() {
+        if let Err(why) = fs::remove_dir_all(PRESERVE_DIR) {
+            error!("failed to remove {}: {}", PRESERVE_DIR, why);
+        }
+    }
+}
+
+/// Packages installed by the casper image of the recovery partition.
+async fn baseline() -> anyhow::Result<HashSet<String>> {
+    let uuid =
+        findmnt_uuid("/recovery").await.context("cannot find UUID of recovery partition")?;
+
+    let path = ["/recovery/casper-", &uuid, "/filesystem.manifest"].concat();
+    let manifest = fs::read_to_string(&path).with_context(|| fomat!("failed to read " (path)))?;
+
+    let native = manifest::native_architecture()
+        .await
+        .context("failed to get the native architecture")?;
+
+    Ok(parse_manifest(&manifest, &native))
+}
+
+/// Package names from a casper `filesystem.manifest`, in the form used by the dpkg snapshots.
+fn parse_manifest(manifest: &str, native: &str) -> HashSet<String> {
+    manifest
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|package| match package.find(':') {
+            Some(pos) => manifest::package_name(&package[..pos], Some(&package[pos + 1..]), native),
+            None => package.to_owned(),
+        })
+        .collect()
+}
+
+/// Enabled sources which were added by the user, or by the applications they installed.
+fn third_party_sources() -> std::io::Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+
+    for entry in fs::read_dir(SOURCES_DIR)?.filter_map(Result::ok) {
+        let path = entry.path();
+
+        if SYSTEM_SOURCES.contains(&file_name(&path).as_str()) {
+            continue;
+        }
+
+        let enabled = fs::read_to_string(&path)
+            .map_or(false, |content| !connectivity::source_uris(&path, &content).is_empty());
+
+        if enabled {
+            sources.push(path);
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Flatpak applications installed for all users, which are stored outside of `/home`.
+async fn system_flatpaks() -> anyhow::Result<Vec<Flatpak>> {
+    let remotes = flatpak(&["remotes", "--system", "--columns=name,url"]).await?;
+    let apps = flatpak(&["list", "--system", "--app", "--columns=origin,application"]).await?;
+
+    Ok(parse_flatpaks(&remotes, &apps))
+}
+
+fn parse_flatpaks(remotes: &str, apps: &str) -> Vec<Flatpak> {
+    let remotes = remotes
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some((fields.next()?.trim(), fields.next()?.trim()))
+        })
+        .collect::<Vec<_>>();
+
+    apps.lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let remote = fields.next()?.trim();
+            let application = fields.next()?.trim();
+            let url = remotes.iter().find(|(name, _)| *name == remote)?.1;
+
+            Some(Flatpak {
+                remote:      remote.to_owned(),
+                url:         url.to_owned(),
+                application: application.to_owned(),
+            })
+        })
+        .collect()
+}
+
+async fn install_flatpak(app: &Flatpak) -> anyhow::Result<()> {
+    flatpak(&["remote-add", "--system", "--if-not-exists", &app.remote, &app.url]).await?;
+    flatpak(&["install", "--system", "--noninteractive", "-y", &app.remote, &app.application])
+        .await?;
+
+    Ok(())
+}
+
+async fn flatpak(args: &[&str]) -> anyhow::Result<String> {
+    let output =
+        Command::new("flatpak").args(args).output().await.context("failed to run flatpak")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "flatpak {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest() {
+        let manifest = "firefox\t98.0+build3-0ubuntu0.20.04.2\n\
+                        libc6:amd64\t2.31-0ubuntu9.7\n\
+                        libc6:i386\t2.31-0ubuntu9.7\n\
+                        tzdata:all 2022a-0ubuntu0.20.04\n\
+                        \n";
+
+        let expected = ["firefox", "libc6", "libc6:i386", "tzdata"]
+            .iter()
+            .map(|name| String::from(*name))
+            .collect::<HashSet<_>>();
+
+        assert_eq!(parse_manifest(manifest, "amd64"), expected);
+    }
+
+    #[test]
+    fn flatpaks() {
+        let remotes = "flathub\thttps://dl.flathub.org/repo/\n\
+                       pop-os\thttps://apt.pop-os.org/flatpak/\n";
+
+        let apps = "flathub\tcom.spotify.Client\n\
+                    unknown\torg.example.App\n";
+
+        assert_eq!(parse_flatpaks(remotes, apps), vec![Flatpak {
+            remote:      "flathub".into(),
+            url:         "https://dl.flathub.org/repo/".into(),
+            application: "com.spotify.Client".into(),
+        }]);
+    }
+}
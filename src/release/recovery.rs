@@ -1,48 +1,79 @@
-use super::*;
+use super::{
+    bootloader::{self, BootloaderKind},
+    *,
+};
 
-use envfile::EnvFile;
+use crate::recovery::{RecoveryConf, RecoveryMode};
 use std::path::Path;
 
-/// Checks if the `MODE` in `/recovery/recovery.conf` is set to the given option.
-pub fn mode_is(option: &str) -> RelResult<bool> {
-    Ok(EnvFile::new(Path::new("/recovery/recovery.conf"))
-        .map_err(ReleaseError::RecoveryConfOpen)?
-        .get("MODE")
-        .map_or(false, |mode| mode == option))
+/// The mode that the recovery partition will boot into, if one is pending.
+pub fn mode() -> RelResult<Option<RecoveryMode>> {
+    Ok(RecoveryConf::load().map_err(ReleaseError::RecoveryConfOpen)?.mode)
 }
 
-/// Fetch the systemd-boot configuration, and designate the recovery partition as the default
-/// boot option.
+/// Checks if the `MODE` in `/recovery/recovery.conf` is set to the given mode.
+pub fn mode_is(mode: RecoveryMode) -> RelResult<bool> { Ok(self::mode()? == Some(mode)) }
+
+/// Sets the mode that the recovery partition will boot into, and the boot entry to return to.
 ///
 /// It will be up to the recovery partition to revert this change once it has completed its job.
-pub fn mode_set(mode: &str, prev_boot: &str) -> RelResult<()> {
-    EnvFile::new(Path::new("/recovery/recovery.conf"))
-        .map_err(ReleaseError::RecoveryConfOpen)?
-        .update("MODE", mode)
-        .update("PREV_BOOT", prev_boot)
-        .write()
-        .map_err(ReleaseError::RecoveryUpdate)
+pub fn mode_set(mode: RecoveryMode, prev_boot: &str) -> RelResult<()> {
+    let mut conf = RecoveryConf::load().map_err(ReleaseError::RecoveryConfOpen)?;
+    conf.set_mode(mode, prev_boot);
+    conf.write().map_err(ReleaseError::RecoveryUpdate)
 }
 
 /// Unsets the `MODE` variable defined in `/recovery/recovery.conf`.
 pub fn mode_unset() -> RelResult<()> {
-    let mut envfile = EnvFile::new(Path::new("/recovery/recovery.conf"))
-        .map_err(ReleaseError::RecoveryConfOpen)?;
+    let mut conf = RecoveryConf::load().map_err(ReleaseError::RecoveryConfOpen)?;
+    conf.unset_mode();
+    conf.write().map_err(ReleaseError::RecoveryUpdate)
+}
+
+/// Boots into the recovery partition in the given mode, on the next boot only.
+pub fn boot_into(mode: RecoveryMode) -> RelResult<()> {
+    upgrade_prereq()?;
+
+    let mut loader = bootloader::load().map_err(ReleaseError::RecoveryConf)?;
+
+    mode_set(mode, loader.default_boot())?;
+
+    loader.boot_once(LoaderEntry::Recovery).map_err(ReleaseError::Bootloader)
+}
+
+/// Cancels the pending mode of the recovery partition, and restores the default boot option.
+pub fn boot_cancel() -> RelResult<()> {
+    upgrade_prereq()?;
 
-    envfile.store.remove("MODE");
-    envfile.store.remove("PREV_BOOT");
+    let mut loader = bootloader::load().map_err(ReleaseError::RecoveryConf)?;
 
-    envfile.write().map_err(ReleaseError::RecoveryUpdate)
+    loader.boot_cancel().map_err(ReleaseError::Bootloader)?;
+
+    mode_unset()
+}
+
+/// Configures the recovery partition to install the new release on the next boot.
+///
+/// The recovery partition should already have been upgraded to the new release.
+pub fn upgrade_set() -> RelResult<()> {
+    boot_into(RecoveryMode::Upgrade)?;
+
+    // The recovery partition now owns the upgrade, so the new sources must not be reverted.
+    let _ = fs::remove_file(RELEASE_FETCH_FILE);
+
+    Ok(())
 }
 
 /// Checks if necessary requirements to use the recovery partition are made.
 pub fn upgrade_prereq() -> RelResult<()> {
-    if !Path::new(SYSTEMD_BOOT_LOADER).exists() {
-        return Err(ReleaseError::SystemdBootLoaderNotFound);
-    }
-
-    if !Path::new(SYSTEMD_BOOT_LOADER_PATH).exists() {
-        return Err(ReleaseError::SystemdBootEfiPathNotFound);
+    match BootloaderKind::detect() {
+        Some(BootloaderKind::SystemdBoot) => {
+            if !Path::new(SYSTEMD_BOOT_LOADER_PATH).exists() {
+                return Err(ReleaseError::SystemdBootEfiPathNotFound);
+            }
+        }
+        Some(BootloaderKind::Grub) => (),
+        None => return Err(ReleaseError::BootloaderNotFound),
     }
 
     let partitions = fs::read_to_string("/proc/mounts").map_err(ReleaseError::ReadingPartitions)?;
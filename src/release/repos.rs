@@ -142,7 +142,10 @@ pub fn is_old_release(codename: Codename) -> bool {
     ]
     .concat();
 
-    isahc::head(url).ok().map_or(false, |resp| resp.status().is_success())
+    crate::http_client::http()
+        .ok()
+        .and_then(|client| client.head(url).ok())
+        .map_or(false, |resp| resp.status().is_success())
 }
 
 pub fn repair(release: &str) -> anyhow::Result<()> {
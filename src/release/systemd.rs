@@ -1,4 +1,4 @@
-use super::*;
+use super::{bootloader::Bootloader, efivars::EfiVars, *};
 
 use anyhow::Context;
 use std::fs;
@@ -6,7 +6,10 @@ use ubuntu_version::{Codename, Version};
 
 pub const PREVIOUS_DEFAULT: &str = "/var/lib/pop-upgrade/previous_default";
 
-pub struct BootConf(SystemdBootConf);
+pub struct BootConf {
+    conf:    SystemdBootConf,
+    efivars: EfiVars,
+}
 
 impl BootConf {
     const DEFAULT_BOOT: &'static str = "Pop_OS-current";
@@ -14,16 +17,24 @@ impl BootConf {
     pub fn load() -> anyhow::Result<Self> {
         SystemdBootConf::new("/boot/efi")
             .context("failed to load systemd-boot configuration")
-            .map(Self)
+            .map(|conf| Self { conf, efivars: EfiVars::default() })
+    }
+
+    /// Reads and writes the loader variables from an alternative efivarfs.
+    pub fn with_efivars(mut self, efivars: EfiVars) -> Self {
+        self.efivars = efivars;
+        self
     }
 
     pub fn default_boot(&self) -> &str {
-        self.0
+        self.conf
             .loader_conf
             .default
             .as_ref()
             .map(Box::as_ref)
-            .unwrap_or_else(|| self.0.current_entry().map_or(Self::DEFAULT_BOOT, |e| e.id.as_ref()))
+            .unwrap_or_else(|| {
+                self.conf.current_entry().map_or(Self::DEFAULT_BOOT, |e| e.id.as_ref())
+            })
     }
 
     /// Modified the default boot entry
@@ -41,9 +52,9 @@ impl BootConf {
 
         let _ = fs::write(PREVIOUS_DEFAULT, previous);
 
-        modify(&mut self.0)?;
+        modify(&mut self.conf)?;
 
-        self.0.overwrite_loader_conf().context("failed to overwrite systemd-boot configuration")
+        self.conf.overwrite_loader_conf().context("failed to overwrite systemd-boot configuration")
     }
 
     /// Defines the specified entry as the default boot entry
@@ -56,24 +67,63 @@ impl BootConf {
 
     /// Defines the specified entry as the default boot entry
     pub fn set_default_boot_variant(&mut self, variant: LoaderEntry) -> anyhow::Result<()> {
-        self.set_default_boot(|conf| {
-            let comparison: fn(filename: &str) -> bool = match variant {
-                LoaderEntry::Current => |e| e.to_lowercase().ends_with("current"),
-                LoaderEntry::Recovery => |e| e.to_lowercase().starts_with("recovery"),
-            };
-
-            let recovery_entry = conf
-                .entries
-                .iter()
-                .find(|e| comparison(&e.id))
-                .ok_or(ReleaseError::MissingRecoveryEntry)?;
-
-            conf.loader_conf.default = Some(recovery_entry.id.clone());
-            Ok(())
-        })
+        let entry = self.entry_id(variant)?;
+        self.set_default_boot_id(&entry)
+    }
+
+    /// Boots the specified entry on the next boot only, with the `LoaderEntryOneShot` variable.
+    ///
+    /// Loaders which do not support one-shot entries have their default boot entry changed
+    /// instead, which must be restored with `restore_default` afterwards.
+    pub fn boot_once_variant(&mut self, variant: LoaderEntry) -> anyhow::Result<()> {
+        let entry = self.entry_id(variant)?;
+
+        if self.efivars.supports_oneshot() {
+            match self.efivars.set_oneshot(&entry) {
+                Ok(()) => {
+                    info!("booting into {} on the next boot", entry);
+                    return Ok(());
+                }
+                Err(why) => warn!("failed to set the one-shot boot entry: {}", why),
+            }
+        }
+
+        info!("changing the default boot entry to {}", entry);
+        self.set_default_boot_id(&entry)
     }
+
+    /// Cancels a pending one-shot boot, and makes the current OS the default boot entry again.
+    pub fn boot_once_cancel(&mut self) -> anyhow::Result<()> {
+        self.efivars.clear_oneshot().context("failed to clear the one-shot boot entry")?;
+        self.set_default_boot_variant(LoaderEntry::Current)
+    }
+
+    fn entry_id(&self, variant: LoaderEntry) -> anyhow::Result<Box<str>> {
+        let comparison: fn(filename: &str) -> bool = match variant {
+            LoaderEntry::Current => |e| e.to_lowercase().ends_with("current"),
+            LoaderEntry::Recovery => |e| e.to_lowercase().starts_with("recovery"),
+        };
+
+        self.conf
+            .entries
+            .iter()
+            .find(|e| comparison(&e.id))
+            .map(|e| e.id.clone())
+            .ok_or_else(|| ReleaseError::MissingRecoveryEntry.into())
+    }
+}
+
+impl Bootloader for BootConf {
+    fn default_boot(&self) -> &str { BootConf::default_boot(self) }
+
+    fn boot_once(&mut self, entry: LoaderEntry) -> anyhow::Result<()> {
+        self.boot_once_variant(entry)
+    }
+
+    fn boot_cancel(&mut self) -> anyhow::Result<()> { self.boot_once_cancel() }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum LoaderEntry {
     Current,
     Recovery,
@@ -116,8 +166,7 @@ pub fn upgrade_set(from: &str, to: &str) -> RelResult<()> {
 
 /// Validate that the pre-required files for performing a system upgrade are in place.
 pub fn upgrade_prereq() -> RelResult<()> {
-    const REQUIRED_UPGRADE_FILES: [&str; 3] = [
-        "/usr/lib/pop-upgrade/upgrade.sh",
+    const REQUIRED_UPGRADE_FILES: [&str; 2] = [
         "/usr/lib/systemd/system/pop-upgrade-init.service",
         "/usr/lib/systemd/system/system-update.target.wants/pop-upgrade-init.service",
     ];
@@ -0,0 +1,250 @@
+//! Verifies that a release upgrade left the system on the new release, in a consistent state.
+
+use super::{ReleaseError, RelResult, CORE_PACKAGES, STARTUP_UPGRADE_FILE};
+use apt_cmd::DpkgQuery;
+use async_process::Command;
+use futures::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use std::{fs, io, path::Path, str::FromStr};
+use ubuntu_version::{Codename, Version};
+
+/// The report of the last release upgrade, which persists until it is dismissed.
+pub const REPORT_FILE: &str = "/var/lib/pop-upgrade/upgrade_report.json";
+
+/// Package states which indicate that a package was not completely installed.
+const UNCONFIGURED: &[&str] =
+    &["half-configured", "half-installed", "triggers-awaited", "triggers-pending", "unpacked"];
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct UpgradeReport {
+    pub from:         String,
+    pub to:           String,
+    /// The release that the system reports after the upgrade.
+    pub detected:     String,
+    /// Why the upgrade failed, if it did.
+    pub error:        Option<String>,
+    /// Core packages which are not installed.
+    pub missing:      Vec<String>,
+    /// Packages which were not completely installed.
+    pub unconfigured: Vec<String>,
+    /// Whether the upgrade is still staged for the next boot.
+    pub staged:       bool,
+    #[serde(default)]
+    pub dismissed:    bool,
+}
+
+impl UpgradeReport {
+    /// Inspects the system after an upgrade from one release to another.
+    pub async fn generate(from: &str, to: &str, error: Option<String>) -> Self {
+        info!("verifying the upgrade from {} to {}", from, to);
+
+        let detected = match Version::detect() {
+            Ok(version) => version_string(version),
+            Err(why) => {
+                error!("failed to detect the installed release: {}", why);
+                String::new()
+            }
+        };
+
+        let missing = missing_packages(CORE_PACKAGES).await.unwrap_or_else(|why| {
+            error!("failed to query installed packages: {}", why);
+            Vec::new()
+        });
+
+        let unconfigured = unconfigured_packages().await.unwrap_or_else(|why| {
+            error!("failed to query package states: {}", why);
+            Vec::new()
+        });
+
+        UpgradeReport {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            detected,
+            error,
+            missing,
+            unconfigured,
+            staged: Path::new(STARTUP_UPGRADE_FILE).exists(),
+            dismissed: false,
+        }
+    }
+
+    /// Reads the report of the last release upgrade, if there is one.
+    pub fn load() -> Option<Self> {
+        let file = fs::File::open(REPORT_FILE).ok()?;
+
+        serde_json::from_reader(file)
+            .map_err(|why| error!("failed to parse the upgrade report: {}", why))
+            .ok()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let data = serde_json::to_vec_pretty(self).map_err(io::Error::from)?;
+        fs::write(REPORT_FILE, data)
+    }
+
+    /// Marks the report as seen, so that it will not be shown again.
+    pub fn dismiss() -> io::Result<()> {
+        match Self::load() {
+            Some(mut report) => {
+                report.dismissed = true;
+                report.save()
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the system reports the release that it was upgraded to.
+    pub fn upgraded(&self) -> bool {
+        release_version(&self.to).map_or(false, |to| to == self.detected)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+            && self.missing.is_empty()
+            && self.unconfigured.is_empty()
+            && !self.staged
+            && self.upgraded()
+    }
+
+    /// Describes each of the problems found with the upgrade.
+    pub fn issues(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+
+        if let Some(ref why) = self.error {
+            issues.push(fomat!("the upgrade failed: " (why)));
+        }
+
+        if !self.upgraded() {
+            issues.push(fomat!(
+                "the system reports release " (self.detected) " instead of " (self.to)
+            ));
+        }
+
+        if !self.missing.is_empty() {
+            issues.push(fomat!("core packages are not installed: " (self.missing.join(", "))));
+        }
+
+        if !self.unconfigured.is_empty() {
+            issues.push(fomat!(
+                "packages were not fully installed: " (self.unconfigured.join(", "))
+            ));
+        }
+
+        if self.staged {
+            issues.push("the upgrade is still staged for the next boot".into());
+        }
+
+        issues
+    }
+
+    /// The outcome of the upgrade, to be reported as the last known release upgrade status.
+    pub fn into_result(self) -> RelResult<()> {
+        if let Some(why) = self.error {
+            return Err(ReleaseError::OfflineUpgrade(why.into()));
+        }
+
+        if self.is_success() {
+            Ok(())
+        } else {
+            Err(ReleaseError::Verify(anyhow!("{}", self.issues().join("; "))))
+        }
+    }
+}
+
+/// Packages from this list which are not installed.
+async fn missing_packages(packages: &[&str]) -> io::Result<Vec<String>> {
+    let (mut child, installed) = DpkgQuery::new().show_installed(packages).await?;
+
+    futures_util::pin_mut!(installed);
+
+    let mut missing = packages.iter().map(|&package| package.to_owned()).collect::<Vec<_>>();
+
+    while let Some(package) = installed.next().await {
+        missing.retain(|core| *core != package);
+    }
+
+    let _ = child.status().await;
+
+    Ok(missing)
+}
+
+/// Packages which dpkg reports were not completely installed.
+async fn unconfigured_packages() -> io::Result<Vec<String>> {
+    let output = Command::new("dpkg-query")
+        .args(&["-W", "-f", "${binary:Package}\\t${db:Status-Status}\\n"])
+        .output()
+        .await?;
+
+    Ok(parse_unconfigured(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_unconfigured(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let package = fields.next()?;
+            let status = fields.next()?;
+
+            if UNCONFIGURED.contains(&status) {
+                Some(package.to_owned())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The version of a release given by either its version or codename.
+fn release_version(release: &str) -> Option<String> {
+    release
+        .parse::<Version>()
+        .ok()
+        .or_else(|| Codename::from_str(release).ok().map(Version::from))
+        .map(version_string)
+}
+
+fn version_string(version: Version) -> String { fomat!((version.major) "." {:02}(version.minor)) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DPKG_QUERY: &str = "apt\tinstalled\n\
+                              libc6:amd64\thalf-configured\n\
+                              linux-generic\tunpacked\n\
+                              old-package\tconfig-files\n\
+                              pop-desktop\ttriggers-pending\n";
+
+    #[test]
+    fn unconfigured() {
+        assert_eq!(parse_unconfigured(DPKG_QUERY), vec![
+            "libc6:amd64".to_owned(),
+            "linux-generic".to_owned(),
+            "pop-desktop".to_owned(),
+        ]);
+    }
+
+    #[test]
+    fn release_versions() {
+        assert_eq!(release_version("20.10").as_deref(), Some("20.10"));
+        assert_eq!(release_version("hirsute").as_deref(), Some("21.04"));
+        assert_eq!(release_version("unknown"), None);
+    }
+
+    #[test]
+    fn success() {
+        let mut report = UpgradeReport {
+            from: "focal".into(),
+            to: "hirsute".into(),
+            detected: "21.04".into(),
+            ..Default::default()
+        };
+
+        assert!(report.is_success());
+
+        report.unconfigured.push("libc6".into());
+        assert!(!report.is_success());
+        assert_eq!(report.issues(), vec!["packages were not fully installed: libc6".to_owned()]);
+    }
+}
@@ -1,13 +1,29 @@
-use serde_derive::Deserialize;
+use isahc::http::StatusCode;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use thiserror::Error;
 
 const BASE: &str = "https://api.pop-os.org/";
 
+/// Responses of the release API, which are reused until they expire, and while offline.
+const CACHE_DIR: &str = "/var/lib/pop-upgrade/release-api";
+
+/// How long a cached response is used before it is revalidated with the server.
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("build ({}) is not a number", _0)]
     BuildNaN(String),
 
+    #[error("failed to create a client for the release API")]
+    Client(#[from] crate::http_client::ClientError),
+
     #[error("failed to GET release API")]
     Get(#[from] isahc::Error),
 
@@ -18,7 +34,69 @@ pub enum ApiError {
     Status(isahc::http::StatusCode),
 }
 
-#[derive(Debug, Deserialize)]
+/// The channel of releases that upgrades are offered from.
+///
+/// The release API serves a single build of each release and architecture, so channels differ in
+/// which releases are offered, and in how soon a newly published build is noticed.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
+pub enum Channel {
+    /// Upgrades to releases which are no longer in development.
+    Stable = 1,
+    /// Also upgrades to releases in development, once their builds are published.
+    Beta = 2,
+    /// As beta, but the release API is asked for a new build on every check.
+    Development = 3,
+}
+
+impl Channel {
+    pub const ALL: &'static [Channel] = &[Channel::Stable, Channel::Beta, Channel::Development];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Channel::Stable => "stable",
+            Channel::Beta => "beta",
+            Channel::Development => "development",
+        }
+    }
+
+    /// Whether upgrades to releases which are still in development are offered.
+    pub fn allows_development(self) -> bool { self != Channel::Stable }
+
+    /// How long a cached response of the release API is used before it is revalidated.
+    fn cache_ttl(self) -> Duration {
+        match self {
+            Channel::Stable | Channel::Beta => CACHE_TTL,
+            Channel::Development => Duration::from_secs(0),
+        }
+    }
+}
+
+impl Default for Channel {
+    fn default() -> Self { Channel::Stable }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(self.as_str()) }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown channel: {}", _0)]
+pub struct UnknownChannel(pub String);
+
+impl FromStr for Channel {
+    type Err = UnknownChannel;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Channel::ALL
+            .iter()
+            .copied()
+            .find(|channel| channel.as_str() == input)
+            .ok_or_else(|| UnknownChannel(input.to_owned()))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct RawRelease {
     pub version: String,
     pub url:     String,
@@ -30,12 +108,12 @@ pub struct RawRelease {
 }
 
 impl RawRelease {
-    fn into_release(self) -> Result<Release, ApiError> {
+    fn into_release(self, stale: bool) -> Result<Release, ApiError> {
         let RawRelease { version, url, size, sha_sum, channel, build, urgent } = self;
         let build = build.parse::<u16>().map_err(|_| ApiError::BuildNaN(build))?;
         let urgent = if urgent == "true" { true } else { false };
 
-        Ok(Release { version, url, size, sha_sum, channel, build, urgent })
+        Ok(Release { version, url, size, sha_sum, channel, build, urgent, stale })
     }
 }
 
@@ -48,32 +126,256 @@ pub struct Release {
     pub channel: String,
     pub build:   u16,
     pub urgent:  bool,
+    /// Served from the cache because the release API could not be reached.
+    pub stale:   bool,
 }
 
 impl Release {
-    pub fn get_release(version: &str, channel: &str) -> Result<Release, ApiError> {
-        info!("checking for build {} in channel {}", version, channel);
-        let url = [BASE, "builds/", version, "/", channel].concat();
+    pub fn get_release(version: &str, arch: &str, channel: Channel) -> Result<Release, ApiError> {
+        info!("checking for {} build {} in channel {}", arch, version, channel);
+        let url = build_url(version, arch);
+        let name = CachedRelease::name(version, arch);
 
-        let response = isahc::get(&url).map_err(ApiError::Get)?;
+        cached_release(Path::new(CACHE_DIR), &name, channel.cache_ttl(), |etag| fetch(&url, etag))
+    }
+
+    pub fn build_exists(version: &str, arch: &str, channel: Channel) -> Result<u16, ApiError> {
+        Self::get_release(version, arch, channel).map(|r| r.build)
+    }
+}
+
+/// The release API's endpoint for the build of a version and architecture.
+fn build_url(version: &str, arch: &str) -> String { [BASE, "builds/", version, "/", arch].concat() }
+
+/// Serves a response of the release API from the cache in `dir`, which is revalidated with
+/// `fetch` once it is older than `ttl`, and served as stale while the release API cannot be
+/// reached.
+fn cached_release(
+    dir: &Path,
+    name: &str,
+    ttl: Duration,
+    fetch: impl FnOnce(Option<&str>) -> Result<Fetched, ApiError>,
+) -> Result<Release, ApiError> {
+    let cache = dir.join(name);
+    let cached = CachedRelease::load(&cache);
 
-        let status = response.status();
-        if !status.is_success() {
-            return Err(ApiError::Status(status));
+    if let Some(ref cached) = cached {
+        if cached.age() < ttl {
+            return cached.release.clone().into_release(false);
         }
+    }
+
+    let etag = cached.as_ref().and_then(|cached| cached.etag.as_deref());
 
-        serde_json::from_reader::<_, RawRelease>(response.into_body())
-            .map_err(ApiError::Json)?
-            .into_release()
+    match (fetch(etag), cached) {
+        (Ok(Fetched::Release(release, etag)), _) => {
+            CachedRelease { etag, fetched: now(), release: release.clone() }.store(dir, &cache);
+            release.into_release(false)
+        }
+        (Ok(Fetched::NotModified), Some(mut cached)) => {
+            cached.fetched = now();
+            cached.store(dir, &cache);
+            cached.release.into_release(false)
+        }
+        (Ok(Fetched::NotModified), None) => Err(ApiError::Status(StatusCode::NOT_MODIFIED)),
+        (Err(why), Some(cached)) if why.is_offline() => {
+            warn!("release API is unavailable; using its cached response: {}", why);
+            cached.release.into_release(true)
+        }
+        (Err(why), _) => Err(why),
     }
+}
 
-    pub fn build_exists(version: &str, channel: &str) -> Result<u16, ApiError> {
-        Self::get_release(version, channel).map(|r| r.build)
+enum Fetched {
+    NotModified,
+    Release(RawRelease, Option<String>),
+}
+
+fn fetch(url: &str, etag: Option<&str>) -> Result<Fetched, ApiError> {
+    let mut request = isahc::Request::get(url);
+
+    if let Some(etag) = etag {
+        request = request.header("If-None-Match", etag);
+    }
+
+    let request = request.body(()).map_err(|why| ApiError::Get(why.into()))?;
+    let response = crate::http_client::https()?.send(request).map_err(ApiError::Get)?;
+
+    let status = response.status();
+    if status == StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    } else if !status.is_success() {
+        return Err(ApiError::Status(status));
     }
+
+    let etag = response
+        .headers()
+        .get("etag")
+        .and_then(|etag| etag.to_str().ok())
+        .map(String::from);
+
+    serde_json::from_reader::<_, RawRelease>(response.into_body())
+        .map(|release| Fetched::Release(release, etag))
+        .map_err(ApiError::Json)
 }
 
+impl ApiError {
+    /// Whether the release API could not be reached, as opposed to rejecting the request.
+    fn is_offline(&self) -> bool {
+        match self {
+            ApiError::Get(_) => true,
+            ApiError::Status(status) => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedRelease {
+    etag:    Option<String>,
+    /// Seconds since the Unix epoch at which the response was fetched, or last revalidated.
+    fetched: u64,
+    release: RawRelease,
+}
+
+impl CachedRelease {
+    fn name(version: &str, arch: &str) -> String {
+        [version, "-", arch, ".json"].concat().replace('/', "_")
+    }
+
+    fn load(path: &Path) -> Option<Self> {
+        let data = fs::read(path).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    fn store(&self, dir: &Path, path: &Path) {
+        let result = fs::create_dir_all(dir).and_then(|_| {
+            let data = serde_json::to_vec(self).map_err(std::io::Error::from)?;
+            fs::write(path, data)
+        });
+
+        if let Err(why) = result {
+            warn!("failed to cache release API response at {}: {}", path.display(), why);
+        }
+    }
+
+    fn age(&self) -> Duration { Duration::from_secs(now().saturating_sub(self.fetched)) }
+}
+
+fn now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()) }
+
 #[test]
 pub fn release_exists() {
-    let result = Release::get_release("20.04", "intel");
+    let result = Release::get_release("20.04", "intel", Channel::Stable);
     assert!(result.is_ok());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &str = "20.10-intel.json";
+
+    fn raw_release(build: &str) -> RawRelease {
+        RawRelease {
+            version: "20.10".into(),
+            url:     "https://iso.pop-os.org/20.10/amd64/intel/10/pop-os_20.10_amd64_intel_10.iso"
+                .into(),
+            size:    2_500_000_000,
+            sha_sum: "0".repeat(64),
+            channel: "intel".into(),
+            build:   build.into(),
+            urgent:  "false".into(),
+        }
+    }
+
+    fn cache(dir: &Path, fetched: u64) {
+        CachedRelease { etag: Some("\"a\"".into()), fetched, release: raw_release("10") }
+            .store(dir, &dir.join(NAME));
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(build_url("21.04", "nvidia"), "https://api.pop-os.org/builds/21.04/nvidia");
+        assert_eq!(CachedRelease::name("21.04", "intel"), "21.04-intel.json");
+    }
+
+    #[test]
+    fn fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        cache(dir.path(), now());
+
+        let ttl = Channel::Beta.cache_ttl();
+        let release =
+            cached_release(dir.path(), NAME, ttl, |_| panic!("a fresh response was revalidated"))
+                .unwrap();
+
+        assert_eq!((release.build, release.stale), (10, false));
+
+        // The development channel revalidates even a fresh response.
+        let ttl = Channel::Development.cache_ttl();
+        let release = cached_release(dir.path(), NAME, ttl, |etag| {
+            assert_eq!(etag, Some("\"a\""));
+            Ok(Fetched::Release(raw_release("11"), None))
+        })
+        .unwrap();
+
+        assert_eq!((release.build, release.stale), (11, false));
+    }
+
+    #[test]
+    fn expired() {
+        let dir = tempfile::tempdir().unwrap();
+        cache(dir.path(), 0);
+
+        let release = cached_release(dir.path(), NAME, CACHE_TTL, |etag| {
+            assert_eq!(etag, Some("\"a\""));
+            Ok(Fetched::Release(raw_release("11"), Some("\"b\"".into())))
+        })
+        .unwrap();
+
+        assert_eq!((release.build, release.stale), (11, false));
+
+        let cached = CachedRelease::load(&dir.path().join(NAME)).unwrap();
+        assert_eq!(cached.etag.as_deref(), Some("\"b\""));
+        assert!(cached.age() < CACHE_TTL);
+    }
+
+    #[test]
+    fn not_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        cache(dir.path(), 0);
+
+        let release =
+            cached_release(dir.path(), NAME, CACHE_TTL, |_| Ok(Fetched::NotModified)).unwrap();
+        assert_eq!((release.build, release.stale), (10, false));
+
+        let cached = CachedRelease::load(&dir.path().join(NAME)).unwrap();
+        assert!(cached.age() < CACHE_TTL);
+
+        let missing =
+            cached_release(dir.path(), "missing.json", CACHE_TTL, |_| Ok(Fetched::NotModified));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn offline() {
+        let dir = tempfile::tempdir().unwrap();
+        cache(dir.path(), 0);
+
+        let unavailable = || -> Result<Fetched, ApiError> {
+            Err(ApiError::Status(StatusCode::SERVICE_UNAVAILABLE))
+        };
+
+        let release = cached_release(dir.path(), NAME, CACHE_TTL, |_| unavailable()).unwrap();
+        assert_eq!((release.build, release.stale), (10, true));
+
+        assert!(cached_release(dir.path(), "missing.json", CACHE_TTL, |_| unavailable()).is_err());
+
+        // Rejected requests are reported, rather than served from the cache.
+        let rejected = cached_release(dir.path(), NAME, CACHE_TTL, |_| {
+            Err(ApiError::Status(StatusCode::NOT_FOUND))
+        });
+        assert!(rejected.is_err());
+    }
+}
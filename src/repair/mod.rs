@@ -2,6 +2,7 @@ pub mod crypttab;
 pub mod fstab;
 pub mod misc;
 pub mod packaging;
+pub mod recovery;
 
 use self::fstab::FstabError;
 use std::io;
@@ -24,6 +25,9 @@ pub enum RepairError {
     #[error("packaging error")]
     Packaging(#[source] anyhow::Error),
 
+    #[error("failed to restore the boot entry of the recovery partition")]
+    RecoveryEntry(#[source] anyhow::Error),
+
     #[error("failed to wipe pulseaudio settings for users")]
     WipePulse(#[source] io::Error),
 }
@@ -34,6 +38,7 @@ pub async fn repair() -> Result<(), RepairError> {
     crypttab::repair().map_err(RepairError::Crypttab)?;
     fstab::repair().map_err(RepairError::Fstab)?;
     packaging::repair().await.map_err(RepairError::Packaging)?;
+    recovery::repair().await.map_err(RepairError::RecoveryEntry)?;
 
     Ok(())
 }
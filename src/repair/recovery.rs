@@ -0,0 +1,47 @@
+//! Restores the systemd-boot entry of the recovery partition, if it has gone missing, and the
+//! previous casper image if an upgrade of the recovery partition was interrupted.
+
+use crate::{
+    external::findmnt_uuid,
+    recovery::{entry, recovery_exists, stage::Staging},
+    release::bootloader::BootloaderKind,
+};
+use anyhow::Context;
+use std::path::Path;
+
+pub async fn repair() -> anyhow::Result<()> {
+    if BootloaderKind::detect() != Some(BootloaderKind::SystemdBoot)
+        || !recovery_exists().unwrap_or(false)
+    {
+        return Ok(());
+    }
+
+    let uuid =
+        findmnt_uuid("/recovery").await.context("cannot find UUID of recovery partition")?;
+
+    let recovery = Path::new("/recovery");
+    let casper = recovery.join(["casper-", &uuid].concat());
+    let efi_dir = entry::efi_dir(&uuid);
+
+    Staging::new(recovery, &uuid, true).recover();
+
+    // The recovery partition has yet to be installed, so there is nothing to boot.
+    if !casper.exists() {
+        return Ok(());
+    }
+
+    for file in &["vmlinuz.efi", "initrd.gz"] {
+        let dest = efi_dir.join(file);
+
+        if !dest.exists() {
+            info!("restoring the recovery partition's {}", dest.display());
+            std::fs::create_dir_all(&efi_dir)
+                .with_context(|| fomat!("failed to create " (efi_dir.display())))?;
+            crate::misc::cp(&casper.join(file), &dest).await?;
+        }
+    }
+
+    entry::ensure_loader_entry(&uuid)?;
+
+    Ok(())
+}